@@ -0,0 +1,333 @@
+//! Cluster (droplet) detection on the particle positions
+
+use crate::cell_list::CellList;
+use crate::particles::Particles;
+use crate::vec3::Vec3;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+
+/// A disjoint-set forest with path compression and union by size.
+#[derive(Debug, Clone)]
+pub struct UnionFind {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl UnionFind {
+    pub fn new(n: usize) -> Self {
+        Self {
+            parents: (0..n).collect(),
+            sizes: vec![1; n],
+        }
+    }
+
+    pub fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    pub fn union(&mut self, i: usize, j: usize) {
+        let (mut a, mut b) = (self.find(i), self.find(j));
+        if a == b {
+            return;
+        }
+        if self.sizes[a] < self.sizes[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parents[b] = a;
+        self.sizes[a] += self.sizes[b];
+    }
+
+    /// Returns the members of each set, in order of their smallest member.
+    pub fn groups(&mut self) -> Vec<Vec<usize>> {
+        let mut group_of_root = vec![usize::MAX; self.parents.len()];
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for i in 0..self.parents.len() {
+            let root = self.find(i);
+            if group_of_root[root] == usize::MAX {
+                group_of_root[root] = groups.len();
+                groups.push(Vec::new());
+            }
+            groups[group_of_root[root]].push(i);
+        }
+        groups
+    }
+}
+
+/// Friends-of-friends clustering: two particles belong to the same group if they are
+/// connected by a chain of particles, each closer than `linking_length` to the next.
+///
+/// Returns the particle indices of all groups, including single particles.
+///
+/// # Arguments
+///
+/// * `positions` - The particle positions
+/// * `linking_length` - The maximum distance between two linked particles
+///
+pub fn friends_of_friends(positions: &[Vec3], linking_length: f64) -> Vec<Vec<usize>> {
+    let cells = CellList::new(positions, linking_length);
+    let mut groups = UnionFind::new(positions.len());
+
+    for (i, p) in positions.iter().enumerate() {
+        for j in cells.within(positions, p, linking_length) {
+            if j > i {
+                groups.union(i, j);
+            }
+        }
+    }
+
+    groups.groups()
+}
+
+/// [DBSCAN](https://en.wikipedia.org/wiki/DBSCAN) clustering: particles with at least `min_pts`
+/// neighbors within a distance of `eps` (including themselves) are core particles, and clusters
+/// consist of core particles connected by neighborship, plus the neighbors of their core particles.
+///
+/// If `velocities` is given, clustering is done in phase space, where the distance between two
+/// particles is sqrt(|r1 - r2|^2 + (w |v1 - v2|)^2) with the velocity weight `w`, a time scale.
+///
+/// Returns the particle indices of all clusters. Noise particles are not part of any cluster.
+///
+/// # Arguments
+///
+/// * `positions` - The particle positions
+/// * `velocities` - Optionally, the particle velocities and the velocity weight
+/// * `eps` - The neighborhood radius
+/// * `min_pts` - The minimum number of neighbors of core particles
+///
+pub fn dbscan(
+    positions: &[Vec3],
+    velocities: Option<(&[Vec3], f64)>,
+    eps: f64,
+    min_pts: usize,
+) -> Vec<Vec<usize>> {
+    let cells = CellList::new(positions, eps);
+    let eps_sq = eps * eps;
+
+    // the position distance is a lower bound of the phase space distance,
+    // so all neighbors are found among the position neighbors
+    let neighbors = |i: usize| {
+        cells.within(positions, &positions[i], eps).filter(move |&j| match velocities {
+            None => true,
+            Some((v, w)) => {
+                let d_sq = (positions[i] - positions[j]).abs_sq() + w * w * (v[i] - v[j]).abs_sq();
+                d_sq <= eps_sq
+            }
+        })
+    };
+
+    let core: Vec<bool> = (0..positions.len())
+        .into_par_iter()
+        .map(|i| neighbors(i).take(min_pts).count() >= min_pts)
+        .collect();
+
+    let mut assigned = vec![false; positions.len()];
+    let mut clusters = Vec::new();
+
+    for start in 0..positions.len() {
+        if !core[start] || assigned[start] {
+            continue;
+        }
+
+        // breadth-first expansion from the core particle
+        let mut cluster = vec![start];
+        assigned[start] = true;
+        let mut queue = VecDeque::from(vec![start]);
+        while let Some(i) = queue.pop_front() {
+            for j in neighbors(i) {
+                if assigned[j] {
+                    continue;
+                }
+                assigned[j] = true;
+                cluster.push(j);
+                // border particles are part of the cluster, but do not expand it
+                if core[j] {
+                    queue.push_back(j);
+                }
+            }
+        }
+
+        cluster.sort_unstable();
+        clusters.push(cluster);
+    }
+
+    clusters
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+/// The clusters found in a simulation, sorted from the largest to the smallest.
+/// Particles are referred to by their index in the simulation.
+pub struct Clusters {
+    /// The simulation time at which the clusters were found
+    #[pyo3(get)]
+    pub time: f64,
+    /// The particle indices of each cluster
+    #[pyo3(get)]
+    pub members: Vec<Vec<usize>>,
+    /// The particle [IDs](../../particles/struct.Particles.html#method.ids) of each cluster,
+    /// in the same order as the indices
+    #[pyo3(get)]
+    pub member_ids: Vec<Vec<u64>>,
+    /// The cluster index of each particle, or -1 for unclustered particles
+    #[pyo3(get)]
+    pub labels: Vec<i64>,
+    /// The center of mass of each cluster
+    #[pyo3(get)]
+    pub centers_of_mass: Vec<Vec3>,
+    /// The total mass of each cluster
+    #[pyo3(get)]
+    pub masses: Vec<f64>,
+}
+
+#[pymethods]
+impl Clusters {
+    /// The number of clusters.
+    #[getter]
+    pub fn num_clusters(&self) -> usize {
+        self.members.len()
+    }
+
+    /// The number of particles in each cluster.
+    #[getter]
+    pub fn sizes(&self) -> Vec<usize> {
+        self.members.iter().map(|m| m.len()).collect()
+    }
+
+    /// The indices of all particles that are not part of any cluster.
+    #[getter]
+    pub fn unclustered(&self) -> Vec<usize> {
+        self.labels
+            .iter()
+            .enumerate()
+            .filter(|(_, &l)| l < 0)
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl Clusters {
+    /// Build the clusters from groups of particle indices.
+    /// Groups with less than `min_size` members are discarded.
+    pub fn from_groups(particles: &Particles, groups: Vec<Vec<usize>>, min_size: usize) -> Self {
+        let mut members: Vec<Vec<usize>> = groups
+            .into_iter()
+            .filter(|g| !g.is_empty() && g.len() >= min_size)
+            .collect();
+        // largest clusters first, ties are broken by the smallest member for reproducibility
+        members.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+
+        let mut labels = vec![-1; particles.state.positions.len()];
+        for (c, cluster) in members.iter().enumerate() {
+            for &i in cluster {
+                labels[i] = c as i64;
+            }
+        }
+
+        let member_ids = members
+            .iter()
+            .map(|cluster| cluster.iter().map(|&i| particles.ids[i]).collect())
+            .collect();
+
+        let masses: Vec<f64> = members
+            .iter()
+            .map(|cluster| cluster.iter().map(|&i| particles.state.masses[i]).sum())
+            .collect();
+
+        let centers_of_mass = members
+            .iter()
+            .zip(masses.iter())
+            .map(|(cluster, &mass)| {
+                cluster
+                    .iter()
+                    .map(|&i| particles.state.positions[i] * particles.state.masses[i])
+                    .sum::<Vec3>()
+                    / mass
+            })
+            .collect();
+
+        Self {
+            time: particles.state.time,
+            members,
+            member_ids,
+            labels,
+            centers_of_mass,
+            masses,
+        }
+    }
+}
+
+#[pymethods]
+impl Particles {
+    /// Find clusters with the friends-of-friends algorithm: two particles belong to the same
+    /// cluster if they are connected by a chain of particles, each closer than
+    /// `linking_length` to the next.
+    ///
+    /// # Arguments
+    ///
+    /// * `linking_length` - The maximum distance between two linked particles.
+    /// * `min_size` - The minimum number of particles in a cluster. Smaller groups are
+    /// considered unclustered. Defaults to 2.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// clusters = particles.friends_of_friends(1.5, min_size=5)
+    /// print(clusters.num_clusters, clusters.sizes, clusters.centers_of_mass)
+    /// ```
+    ///
+    #[args(min_size = "2")]
+    pub fn friends_of_friends(&self, linking_length: f64, min_size: usize) -> PyResult<Clusters> {
+        if !linking_length.is_finite() || linking_length <= 0.0 {
+            return Err(PyValueError::new_err("the linking length must be positive and finite"));
+        }
+
+        let groups = friends_of_friends(&self.state.positions, linking_length);
+        Ok(Clusters::from_groups(self, groups, min_size))
+    }
+
+    /// Find clusters with the [DBSCAN](https://en.wikipedia.org/wiki/DBSCAN) algorithm:
+    /// particles with at least `min_pts` neighbors within a distance of `eps` (including themselves)
+    /// are core particles. Clusters consist of core particles which are neighbors of each other,
+    /// and of the neighbors of their core particles. All other particles are unclustered noise.
+    ///
+    /// # Arguments
+    ///
+    /// * `eps` - The neighborhood radius.
+    /// * `min_pts` - The minimum number of neighbors of core particles. Defaults to 5.
+    /// * `velocity_weight` - If given, cluster in phase space, where the distance between
+    /// two particles is sqrt(|r1 - r2|^2 + (velocity_weight * |v1 - v2|)^2).
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// clusters = particles.dbscan(1.5, min_pts=4, velocity_weight=0.5)
+    /// ```
+    ///
+    #[args(min_pts = "5", velocity_weight = "None")]
+    pub fn dbscan(&self, eps: f64, min_pts: usize, velocity_weight: Option<f64>) -> PyResult<Clusters> {
+        if !eps.is_finite() || eps <= 0.0 {
+            return Err(PyValueError::new_err("eps must be positive and finite"));
+        }
+        if let Some(w) = velocity_weight {
+            if !w.is_finite() || w < 0.0 {
+                return Err(PyValueError::new_err("the velocity weight must be non-negative and finite"));
+            }
+        }
+
+        let velocities = velocity_weight.map(|w| (self.state.velocities.as_slice(), w));
+        let groups = dbscan(&self.state.positions, velocities, eps, min_pts.max(1));
+        Ok(Clusters::from_groups(self, groups, 1))
+    }
+}
@@ -0,0 +1,352 @@
+//! Cluster size distributions and nucleation statistics over the course of a run
+
+use crate::analysis::cluster::{dbscan, friends_of_friends, Clusters};
+use crate::interaction::pair_energy;
+use crate::observer::{lock, recorder_methods, ObserverBase, Record, Recorder};
+use crate::particles::Particles;
+use crate::vec3::Vec3;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+/// Statistics of the clusters in a simulation at some point in time.
+///
+/// The internal temperature of a cluster is T = 2 K / (3 (n - 1)), where K is the kinetic energy
+/// relative to the cluster's center of mass and n its size, in units where k_B = 1.
+/// The binding energy of a cluster is -(K + U), where U is the pair potential energy between its members.
+/// External potentials are not taken into account.
+pub struct ClusterStatistics {
+    /// The simulation time
+    #[pyo3(get)]
+    pub time: f64,
+    /// The number of time steps performed
+    #[pyo3(get)]
+    pub step: usize,
+    /// The total number of particles
+    #[pyo3(get)]
+    pub num_particles: usize,
+    /// The number of clusters
+    #[pyo3(get)]
+    pub num_clusters: usize,
+    /// The number of particles in the largest cluster
+    #[pyo3(get)]
+    pub largest_cluster_size: usize,
+    /// The fraction of particles in the largest cluster
+    #[pyo3(get)]
+    pub largest_cluster_fraction: f64,
+    /// The fraction of particles in any cluster
+    #[pyo3(get)]
+    pub clustered_fraction: f64,
+    /// The mean internal temperature of the clusters, weighted by their size
+    #[pyo3(get)]
+    pub mean_temperature: f64,
+    /// The sum of the binding energies of all clusters
+    #[pyo3(get)]
+    pub total_binding_energy: f64,
+    /// The number of clusters of each size
+    #[pyo3(get)]
+    pub size_histogram: BTreeMap<usize, usize>,
+    /// The internal temperature of each cluster
+    #[pyo3(get)]
+    pub temperatures: Vec<f64>,
+    /// The binding energy of each cluster
+    #[pyo3(get)]
+    pub binding_energies: Vec<f64>,
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl ClusterStatistics {
+    pub fn new(particles: &Particles, clusters: &Clusters) -> Self {
+        let n = particles.state.positions.len();
+        let sizes: Vec<usize> = clusters.members.iter().map(|m| m.len()).collect();
+
+        let mut size_histogram = BTreeMap::new();
+        for &size in sizes.iter() {
+            *size_histogram.entry(size).or_insert(0) += 1;
+        }
+
+        let (temperatures, binding_energies): (Vec<f64>, Vec<f64>) = clusters
+            .members
+            .iter()
+            .map(|members| cluster_energetics(particles, members))
+            .unzip();
+
+        let clustered: usize = sizes.iter().sum();
+        let largest = sizes.iter().copied().max().unwrap_or(0);
+        let fraction = |count: usize| if n > 0 { count as f64 / n as f64 } else { 0.0 };
+
+        let mean_temperature = if clustered > 0 {
+            temperatures
+                .iter()
+                .zip(sizes.iter())
+                .map(|(t, &size)| t * size as f64)
+                .sum::<f64>()
+                / clustered as f64
+        } else {
+            0.0
+        };
+
+        Self {
+            time: particles.state.time,
+            step: particles.state.step,
+            num_particles: n,
+            num_clusters: sizes.len(),
+            largest_cluster_size: largest,
+            largest_cluster_fraction: fraction(largest),
+            clustered_fraction: fraction(clustered),
+            mean_temperature,
+            total_binding_energy: binding_energies.iter().sum(),
+            size_histogram,
+            temperatures,
+            binding_energies,
+        }
+    }
+}
+
+/// Returns the internal temperature and the binding energy of a cluster.
+fn cluster_energetics(particles: &Particles, members: &[usize]) -> (f64, f64) {
+    let mass: f64 = members.iter().map(|&i| particles.state.masses[i]).sum();
+    if members.is_empty() || mass <= 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let momentum: Vec3 = members
+        .iter()
+        .map(|&i| particles.state.velocities[i] * particles.state.masses[i])
+        .sum();
+    let u = momentum / mass;
+
+    let kinetic: f64 = members
+        .iter()
+        .map(|&i| 0.5 * particles.state.masses[i] * (particles.state.velocities[i] - u).abs_sq())
+        .sum();
+
+    let potential: f64 = members
+        .par_iter()
+        .enumerate()
+        .map(|(k, &i)| {
+            members[k + 1..]
+                .iter()
+                .map(|&j| pair_energy((particles.state.positions[j] - particles.state.positions[i]).abs()))
+                .sum::<f64>()
+        })
+        .sum();
+
+    let temperature = if members.len() > 1 {
+        2.0 * kinetic / (3.0 * (members.len() - 1) as f64)
+    } else {
+        0.0
+    };
+
+    (temperature, -(kinetic + potential))
+}
+
+#[pymethods]
+impl Particles {
+    /// Compute the statistics of previously found clusters, such as their size distribution,
+    /// internal temperatures and binding energies.
+    /// See [ClusterStatistics](../analysis/cluster_statistics/struct.ClusterStatistics.html).
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// stats = particles.cluster_statistics(particles.friends_of_friends(1.5))
+    /// print(stats.largest_cluster_fraction, stats.size_histogram)
+    /// ```
+    ///
+    pub fn cluster_statistics(&self, clusters: &Clusters) -> PyResult<ClusterStatistics> {
+        if clusters.labels.len() != self.state.positions.len() {
+            return Err(PyValueError::new_err(
+                "the clusters were found in a simulation with a different number of particles",
+            ));
+        }
+        Ok(ClusterStatistics::new(self, clusters))
+    }
+}
+
+/// The clustering algorithm used by the observer
+#[derive(Debug, Clone, Copy)]
+enum Method {
+    FriendsOfFriends { linking_length: f64, min_size: usize },
+    Dbscan { eps: f64, min_pts: usize, min_size: usize },
+}
+
+impl Method {
+    fn find(self, particles: &Particles) -> Clusters {
+        match self {
+            Method::FriendsOfFriends { linking_length, min_size } => {
+                let groups = friends_of_friends(&particles.state.positions, linking_length);
+                Clusters::from_groups(particles, groups, min_size)
+            }
+            Method::Dbscan { eps, min_pts, min_size } => {
+                let groups = dbscan(&particles.state.positions, None, eps, min_pts);
+                Clusters::from_groups(particles, groups, min_size)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ClusterStatisticsRows {
+    method: Method,
+    rows: Vec<ClusterStatistics>,
+}
+
+impl Record for ClusterStatisticsRows {
+    fn record(&mut self, particles: &Particles) -> PyResult<()> {
+        let clusters = self.method.find(particles);
+        self.rows.push(ClusterStatistics::new(particles, &clusters));
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.rows.clear();
+    }
+}
+
+// the scalar columns of the exported table
+const COLUMNS: [&str; 9] = [
+    "time",
+    "step",
+    "num_particles",
+    "num_clusters",
+    "largest_cluster_size",
+    "largest_cluster_fraction",
+    "clustered_fraction",
+    "mean_temperature",
+    "total_binding_energy",
+];
+
+fn row_values(row: &ClusterStatistics) -> [f64; 9] {
+    [
+        row.time,
+        row.step as f64,
+        row.num_particles as f64,
+        row.num_clusters as f64,
+        row.largest_cluster_size as f64,
+        row.largest_cluster_fraction,
+        row.clustered_fraction,
+        row.mean_temperature,
+        row.total_binding_energy,
+    ]
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(extends=ObserverBase)]
+#[derive(Debug, Clone)]
+/// Finds clusters and records their [statistics](struct.ClusterStatistics.html) every `interval` steps
+/// when added to a simulation with [add_observer](../../particles/struct.Particles.html#method.add_observer).
+///
+/// # Arguments
+///
+/// * `radius` - The linking length for friends-of-friends, or `eps` for DBSCAN.
+/// * `interval` - The number of steps between two observations.
+/// * `method` - The clustering algorithm, `"fof"` (default) or `"dbscan"`.
+/// * `min_size` - The minimum number of particles in a cluster.
+/// * `min_pts` - The minimum number of neighbors of core particles for DBSCAN.
+///
+/// # Examples
+///
+/// Python:
+/// ```python
+/// observer = ClusterStatisticsObserver(1.5, interval=100)
+/// particles.add_observer(observer)
+/// particles.run(n=10000, h=0.01)
+/// observer.to_csv("clusters.csv")
+/// ```
+///
+pub struct ClusterStatisticsObserver {
+    recorder: Arc<Mutex<Recorder<ClusterStatisticsRows>>>,
+}
+
+#[pymethods]
+impl ClusterStatisticsObserver {
+    #[new]
+    #[args(interval = "1", method = "\"fof\"", min_size = "2", min_pts = "5")]
+    pub fn new(radius: f64, interval: usize, method: &str, min_size: usize, min_pts: usize) -> PyResult<(Self, ObserverBase)> {
+        if !radius.is_finite() || radius <= 0.0 {
+            return Err(PyValueError::new_err("the radius must be positive and finite"));
+        }
+
+        let method = match method.to_lowercase().as_str() {
+            "fof" => Method::FriendsOfFriends { linking_length: radius, min_size },
+            "dbscan" => Method::Dbscan { eps: radius, min_pts: min_pts.max(1), min_size },
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown clustering method '{}', expected 'fof' or 'dbscan'",
+                    method
+                )))
+            }
+        };
+
+        let rows = ClusterStatisticsRows { method, rows: Vec::new() };
+        let (recorder, base) = ObserverBase::share(Recorder::new(interval, rows)?);
+        Ok((Self { recorder }, base))
+    }
+
+    /// The recorded statistics, one per observation.
+    #[getter]
+    pub fn rows(&self) -> PyResult<Vec<ClusterStatistics>> {
+        Ok(lock(&self.recorder)?.data.rows.clone())
+    }
+
+    /// The recorded scalar statistics as a dictionary of columns,
+    /// e.g. for use with `pandas.DataFrame(observer.table())`.
+    pub fn table<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let recorder = lock(&self.recorder)?;
+        let table = PyDict::new(py);
+        for (c, name) in COLUMNS.iter().enumerate() {
+            let column: Vec<f64> = recorder.data.rows.iter().map(|row| row_values(row)[c]).collect();
+            table.set_item(name, column)?;
+        }
+        Ok(table)
+    }
+
+    /// The size histograms of all observations as a table of cluster counts,
+    /// with one row per observation and one column per cluster size.
+    /// Returns the cluster sizes and the table rows.
+    pub fn size_histogram_table(&self) -> PyResult<(Vec<usize>, Vec<Vec<usize>>)> {
+        let recorder = lock(&self.recorder)?;
+        let max_size = recorder
+            .data
+            .rows
+            .iter()
+            .filter_map(|row| row.size_histogram.keys().next_back().copied())
+            .max()
+            .unwrap_or(0);
+        let sizes: Vec<usize> = (1..=max_size).collect();
+        let rows = recorder
+            .data
+            .rows
+            .iter()
+            .map(|row| sizes.iter().map(|s| *row.size_histogram.get(s).unwrap_or(&0)).collect())
+            .collect();
+        Ok((sizes, rows))
+    }
+
+    /// Write the recorded scalar statistics to a CSV file.
+    pub fn to_csv(&self, path: &str) -> PyResult<()> {
+        let recorder = lock(&self.recorder)?;
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "{}", COLUMNS.join(","))?;
+        for row in recorder.data.rows.iter() {
+            let values: Vec<String> = row_values(row).iter().map(|v| v.to_string()).collect();
+            writeln!(file, "{}", values.join(","))?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+}
+
+recorder_methods!(ClusterStatisticsObserver);
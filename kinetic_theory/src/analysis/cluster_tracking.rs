@@ -0,0 +1,202 @@
+//! Tracking of clusters across consecutive snapshots by member overlap
+
+use crate::analysis::cluster::Clusters;
+use std::collections::HashMap;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass]
+#[derive(Debug, Clone)]
+/// Something that happened to one or more clusters between two snapshots.
+pub struct ClusterEvent {
+    /// One of `"nucleation"`, `"merger"`, `"split"` or `"evaporation"`
+    #[pyo3(get)]
+    pub kind: String,
+    /// The time of the snapshot in which the event was detected
+    #[pyo3(get)]
+    pub time: f64,
+    /// The tracks involved before the event, empty for nucleations
+    #[pyo3(get)]
+    pub tracks_before: Vec<u64>,
+    /// The tracks involved after the event, empty for evaporations
+    #[pyo3(get)]
+    pub tracks_after: Vec<u64>,
+    /// The sizes of the clusters before the event
+    #[pyo3(get)]
+    pub sizes_before: Vec<usize>,
+    /// The sizes of the clusters after the event
+    #[pyo3(get)]
+    pub sizes_after: Vec<usize>,
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass]
+#[derive(Debug, Clone)]
+/// Matches the clusters of consecutive snapshots by their member overlap, and assigns each
+/// cluster a track ID that persists as long as the cluster does.
+///
+/// A cluster of the previous snapshot is linked to a cluster of the current snapshot if at least
+/// a fraction `min_overlap` of either of them is shared with the other. A current cluster without
+/// links has nucleated, one with several links is the result of a merger. A previous cluster without
+/// links has evaporated, one with several links has split. A current cluster continues the track of
+/// the previous cluster it shares most particles with, unless that one continues in another cluster.
+///
+/// The first snapshot only starts a track for each of its clusters, without events, so the clusters of
+/// the initial condition are not counted as nucleations.
+///
+/// Clusters are compared by particle [ID](../../particles/struct.Particles.html#method.ids), so particles
+/// may be added, removed or reordered between snapshots.
+pub struct ClusterTracker {
+    min_overlap: f64,
+    // member IDs and track IDs of the clusters of the last snapshot
+    previous: Vec<Vec<u64>>,
+    tracks: Vec<u64>,
+    // whether a snapshot was seen since the creation or the last reset
+    initialized: bool,
+    next_track: u64,
+    events: Vec<ClusterEvent>,
+}
+
+#[pymethods]
+impl ClusterTracker {
+    #[new]
+    #[args(min_overlap = "0.5")]
+    pub fn new(min_overlap: f64) -> PyResult<Self> {
+        if !(min_overlap > 0.0 && min_overlap <= 1.0) {
+            return Err(PyValueError::new_err("min_overlap must be in (0, 1]"));
+        }
+
+        Ok(Self {
+            min_overlap,
+            previous: Vec::new(),
+            tracks: Vec::new(),
+            initialized: false,
+            next_track: 0,
+            events: Vec::new(),
+        })
+    }
+
+    /// Match the clusters of the next snapshot to those of the last one.
+    /// Returns the events detected in this update, none for the first snapshot.
+    pub fn update(&mut self, clusters: &Clusters) -> Vec<ClusterEvent> {
+        let current = &clusters.member_ids;
+
+        if !self.initialized {
+            self.initialized = true;
+            self.previous = current.clone();
+            self.tracks = (0..current.len()).map(|_| self.new_track()).collect();
+            return Vec::new();
+        }
+
+        // count the shared particles of each pair of previous and current clusters
+        let mut previous_of: HashMap<u64, usize> = HashMap::new();
+        for (a, members) in self.previous.iter().enumerate() {
+            for &id in members {
+                previous_of.insert(id, a);
+            }
+        }
+        let mut overlaps: HashMap<(usize, usize), usize> = HashMap::new();
+        for (b, members) in current.iter().enumerate() {
+            for id in members {
+                if let Some(&a) = previous_of.get(id) {
+                    *overlaps.entry((a, b)).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let mut successors: Vec<Vec<(usize, usize)>> = vec![Vec::new(); self.previous.len()];
+        let mut predecessors: Vec<Vec<(usize, usize)>> = vec![Vec::new(); current.len()];
+        let mut links: Vec<(&(usize, usize), &usize)> = overlaps.iter().collect();
+        links.sort();
+        for (&(a, b), &overlap) in links {
+            let shared = overlap as f64;
+            if shared >= self.min_overlap * self.previous[a].len() as f64
+                || shared >= self.min_overlap * current[b].len() as f64
+            {
+                successors[a].push((b, overlap));
+                predecessors[b].push((a, overlap));
+            }
+        }
+
+        // the successor sharing most particles continues the track of a previous cluster
+        let main_successor: Vec<Option<usize>> = successors
+            .iter()
+            .map(|s| s.iter().max_by_key(|&&(b, overlap)| (overlap, std::cmp::Reverse(b))).map(|&(b, _)| b))
+            .collect();
+
+        let mut tracks = Vec::with_capacity(current.len());
+        for (b, p) in predecessors.iter().enumerate() {
+            let continued = p
+                .iter()
+                .filter(|&&(a, _)| main_successor[a] == Some(b))
+                .max_by_key(|&&(a, overlap)| (overlap, std::cmp::Reverse(a)))
+                .map(|&(a, _)| self.tracks[a]);
+            tracks.push(continued.unwrap_or_else(|| self.new_track()));
+        }
+
+        let mut events = Vec::new();
+        let event = |kind: &str, before: &[usize], after: &[usize]| ClusterEvent {
+            kind: kind.to_string(),
+            time: clusters.time,
+            tracks_before: before.iter().map(|&a| self.tracks[a]).collect(),
+            tracks_after: after.iter().map(|&b| tracks[b]).collect(),
+            sizes_before: before.iter().map(|&a| self.previous[a].len()).collect(),
+            sizes_after: after.iter().map(|&b| current[b].len()).collect(),
+        };
+
+        for (b, p) in predecessors.iter().enumerate() {
+            let before: Vec<usize> = p.iter().map(|&(a, _)| a).collect();
+            match before.len() {
+                0 => events.push(event("nucleation", &[], &[b])),
+                1 => {}
+                _ => events.push(event("merger", &before, &[b])),
+            }
+        }
+        for (a, s) in successors.iter().enumerate() {
+            let after: Vec<usize> = s.iter().map(|&(b, _)| b).collect();
+            match after.len() {
+                0 => events.push(event("evaporation", &[a], &[])),
+                1 => {}
+                _ => events.push(event("split", &[a], &after)),
+            }
+        }
+
+        self.previous = current.clone();
+        self.tracks = tracks;
+        self.events.extend(events.iter().cloned());
+
+        events
+    }
+
+    /// The track IDs of the clusters of the last snapshot, in the same order as the clusters.
+    #[getter]
+    pub fn track_ids(&self) -> Vec<u64> {
+        self.tracks.clone()
+    }
+
+    /// All events detected so far.
+    #[getter]
+    pub fn events(&self) -> Vec<ClusterEvent> {
+        self.events.clone()
+    }
+
+    /// Forget all tracks and events.
+    pub fn reset(&mut self) {
+        self.previous.clear();
+        self.tracks.clear();
+        self.initialized = false;
+        self.next_track = 0;
+        self.events.clear();
+    }
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl ClusterTracker {
+    fn new_track(&mut self) -> u64 {
+        self.next_track += 1;
+        self.next_track - 1
+    }
+}
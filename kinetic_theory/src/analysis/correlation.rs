@@ -0,0 +1,278 @@
+//! Time-correlation analysis of a stream of simulation states
+
+use crate::particles::Particles;
+use crate::vec3::Vec3;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// One level of the multiple-tau correlator.
+/// Level `l` holds every `block_size^l`-th frame, so its lags are `j * block_size^l` frames.
+#[derive(Debug, Clone, Default)]
+struct Level {
+    // newest frame first
+    positions: VecDeque<Vec<Vec3>>,
+    velocities: VecDeque<Vec<Vec3>>,
+    // accumulated sums per lag index
+    msd: Vec<f64>,
+    vacf: Vec<f64>,
+    counts: Vec<u64>,
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass]
+#[derive(Debug, Clone)]
+/// Computes the mean-squared displacement and the velocity autocorrelation function
+/// of a stream of simulation states, without storing the full trajectory.
+///
+/// Correlations are accumulated with a multiple-tau scheme: the most recent
+/// `points_per_level` frames are held at full resolution, while older frames are kept
+/// at successively coarser resolution, each level keeping only every `block_size`-th frame
+/// of the level below. This gives logarithmically spaced lag times up to
+/// `points_per_level * block_size^(num_levels - 1)` frames, with memory proportional to
+/// `points_per_level * num_levels` frames. Coarse levels use sampled rather than averaged
+/// frames, so all lags are exact.
+///
+/// Frames must be pushed at equally spaced times.
+/// If `box_size` is given, positions are assumed to be wrapped into a periodic box
+/// with these edge lengths and are unwrapped before computing displacements.
+pub struct TimeCorrelation {
+    points_per_level: usize,
+    block_size: usize,
+    box_size: Option<Vec3>,
+    levels: Vec<Level>,
+    // raw and unwrapped positions of the last frame
+    last_positions: Vec<Vec3>,
+    unwrapped: Vec<Vec3>,
+    last_time: Option<f64>,
+    frame_dt: Option<f64>,
+    num_frames: usize,
+}
+
+#[pymethods]
+impl TimeCorrelation {
+    /// Create a new, empty time correlator.
+    ///
+    /// # Arguments
+    ///
+    /// * `points_per_level` - The number of frames stored per level. Must be a multiple of `block_size`.
+    /// * `num_levels` - The number of levels.
+    /// * `block_size` - The decimation factor between consecutive levels.
+    /// * `box_size` - Optional [`Vec3`](../../vec3/struct.Vec3.html) of periodic box edge lengths.
+    ///
+    #[new]
+    #[args(points_per_level = "16", num_levels = "12", block_size = "2", box_size = "None")]
+    pub fn new(
+        points_per_level: usize,
+        num_levels: usize,
+        block_size: usize,
+        box_size: Option<Vec3>,
+    ) -> PyResult<Self> {
+        if block_size < 2 || points_per_level < block_size || points_per_level % block_size != 0 {
+            return Err(PyValueError::new_err(
+                "points_per_level must be a multiple of block_size, and block_size must be at least 2",
+            ));
+        }
+        if num_levels == 0 {
+            return Err(PyValueError::new_err("num_levels must be at least 1"));
+        }
+
+        let level = Level {
+            msd: vec![0.0; points_per_level],
+            vacf: vec![0.0; points_per_level],
+            counts: vec![0; points_per_level],
+            ..Level::default()
+        };
+
+        Ok(Self {
+            points_per_level,
+            block_size,
+            box_size,
+            levels: vec![level; num_levels],
+            last_positions: Vec::new(),
+            unwrapped: Vec::new(),
+            last_time: None,
+            frame_dt: None,
+            num_frames: 0,
+        })
+    }
+
+    /// Add the current state of a simulation as the next frame.
+    pub fn push(&mut self, particles: &Particles) -> PyResult<()> {
+        self.push_frame(particles.state.time, &particles.state.positions, &particles.state.velocities)
+    }
+
+    /// Forget all pushed frames and accumulated correlations.
+    pub fn reset(&mut self) {
+        for level in self.levels.iter_mut() {
+            *level = Level {
+                msd: vec![0.0; self.points_per_level],
+                vacf: vec![0.0; self.points_per_level],
+                counts: vec![0; self.points_per_level],
+                ..Level::default()
+            };
+        }
+        self.last_positions.clear();
+        self.unwrapped.clear();
+        self.last_time = None;
+        self.frame_dt = None;
+        self.num_frames = 0;
+    }
+
+    /// The number of frames pushed so far.
+    #[getter]
+    pub fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    /// The lag times at which correlations are available, in ascending order.
+    pub fn lags(&self) -> Vec<f64> {
+        let dt = self.frame_dt.unwrap_or(0.0);
+        self.collect(|_, lag, _| lag as f64 * dt)
+    }
+
+    /// The mean-squared displacement for each of the [lags](#method.lags).
+    pub fn msd(&self) -> Vec<f64> {
+        self.collect(|level, _, j| level.msd[j] / level.counts[j] as f64)
+    }
+
+    /// The velocity autocorrelation function <v(0) * v(t)> for each of the [lags](#method.lags).
+    pub fn vacf(&self) -> Vec<f64> {
+        self.collect(|level, _, j| level.vacf[j] / level.counts[j] as f64)
+    }
+
+    /// The number of time origins averaged over for each of the [lags](#method.lags).
+    pub fn counts(&self) -> Vec<u64> {
+        self.collect(|level, _, j| level.counts[j])
+    }
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl TimeCorrelation {
+    /// Add a frame given by its time, positions and velocities.
+    pub fn push_frame(&mut self, time: f64, positions: &[Vec3], velocities: &[Vec3]) -> PyResult<()> {
+        if self.num_frames > 0 && positions.len() != self.unwrapped.len() {
+            return Err(PyValueError::new_err(format!(
+                "the number of particles changed from {} to {}",
+                self.unwrapped.len(),
+                positions.len()
+            )));
+        }
+
+        // all frames must be equally spaced in time for the lags to be meaningful
+        if let Some(last_time) = self.last_time {
+            let dt = time - last_time;
+            match self.frame_dt {
+                None => self.frame_dt = Some(dt),
+                Some(frame_dt) => {
+                    if (dt - frame_dt).abs() > 1e-9 * frame_dt.abs().max(1.0) {
+                        return Err(PyValueError::new_err(format!(
+                            "frames must be equally spaced in time, expected a spacing of {} but got {}",
+                            frame_dt, dt
+                        )));
+                    }
+                }
+            }
+        }
+        self.last_time = Some(time);
+
+        self.unwrap(positions);
+        self.last_positions = positions.to_vec();
+
+        let frame = self.num_frames;
+        let mut stride = 1;
+        for l in 0..self.levels.len() {
+            if frame % stride != 0 {
+                break;
+            }
+            self.push_level(l, self.unwrapped.clone(), velocities.to_vec());
+            stride *= self.block_size;
+        }
+
+        self.num_frames += 1;
+
+        Ok(())
+    }
+
+    fn unwrap(&mut self, positions: &[Vec3]) {
+        match (&self.box_size, self.num_frames) {
+            (Some(l), n) if n > 0 => {
+                // undo jumps across the periodic boundaries with the minimum image convention
+                for ((u, p), last) in self
+                    .unwrapped
+                    .iter_mut()
+                    .zip(positions.iter())
+                    .zip(self.last_positions.iter())
+                {
+                    let mut d = p - last;
+                    d.x -= l.x * (d.x / l.x).round();
+                    d.y -= l.y * (d.y / l.y).round();
+                    d.z -= l.z * (d.z / l.z).round();
+                    *u += d;
+                }
+            }
+            _ => self.unwrapped = positions.to_vec(),
+        }
+    }
+
+    fn push_level(&mut self, l: usize, positions: Vec<Vec3>, velocities: Vec<Vec3>) {
+        // lags shorter than this are already covered by the finer level below
+        let j_min = if l == 0 { 0 } else { self.points_per_level / self.block_size };
+        let points_per_level = self.points_per_level;
+        let level = &mut self.levels[l];
+
+        level.positions.push_front(positions);
+        level.velocities.push_front(velocities);
+        level.positions.truncate(points_per_level);
+        level.velocities.truncate(points_per_level);
+
+        let (new_positions, new_velocities) = (&level.positions[0], &level.velocities[0]);
+        let n = new_positions.len().max(1) as f64;
+
+        let sums: Vec<(usize, f64, f64)> = (j_min..level.positions.len())
+            .into_par_iter()
+            .map(|j| {
+                let msd: f64 = new_positions
+                    .iter()
+                    .zip(level.positions[j].iter())
+                    .map(|(a, b)| (a - b).abs_sq())
+                    .sum();
+                let vacf: f64 = new_velocities
+                    .iter()
+                    .zip(level.velocities[j].iter())
+                    .map(|(a, b)| a * b)
+                    .sum();
+                (j, msd / n, vacf / n)
+            })
+            .collect();
+
+        for (j, msd, vacf) in sums {
+            level.msd[j] += msd;
+            level.vacf[j] += vacf;
+            level.counts[j] += 1;
+        }
+    }
+
+    /// Collect a quantity for all lags with at least one sample, in ascending lag order.
+    /// `f` receives the level, the lag in frames and the lag index within the level.
+    fn collect<T, F>(&self, f: F) -> Vec<T>
+    where
+        F: Fn(&Level, usize, usize) -> T,
+    {
+        let mut result = Vec::new();
+        let mut stride = 1;
+        for (l, level) in self.levels.iter().enumerate() {
+            let j_min = if l == 0 { 0 } else { self.points_per_level / self.block_size };
+            for j in j_min..self.points_per_level {
+                if level.counts[j] > 0 {
+                    result.push(f(level, j * stride, j));
+                }
+            }
+            stride *= self.block_size;
+        }
+        result
+    }
+}
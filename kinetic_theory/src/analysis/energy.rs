@@ -0,0 +1,206 @@
+//! Energies and the kinetic temperature of a simulation
+
+use crate::interaction::pair_energy;
+use crate::observer::{lock, recorder_methods, ObserverBase, Record, Recorder};
+use crate::particles::Particles;
+use crate::vector::{Scalar, Vector};
+use rayon::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use pyo3::prelude::*;
+
+// these are generic over the vector type, and widen the components to f64 before squaring them,
+// so they are computed and accumulated in f64 for every precision
+
+/// The squared norm of a vector in double precision.
+fn abs_sq_f64<V: Vector>(v: &V) -> f64 {
+    (0..V::DIM).map(|i| v.component(i) * v.component(i)).sum()
+}
+
+/// The total kinetic energy of the particles.
+pub fn kinetic_energy<V: Vector>(masses: &[V::Scalar], velocities: &[V]) -> f64 {
+    masses
+        .iter()
+        .zip(velocities.iter())
+        .map(|(m, v)| 0.5 * m.to_f64() * abs_sq_f64(v))
+        .sum()
+}
+
+/// The total pair interaction energy of the particles, see
+/// [pair_energy](../../interaction/fn.pair_energy.html).
+pub fn interaction_energy<V: Vector>(positions: &[V]) -> f64 {
+    positions
+        .par_iter()
+        .enumerate()
+        .map(|(i, p1)| {
+            positions[i + 1..]
+                .iter()
+                .map(|&p2| pair_energy(abs_sq_f64(&(p2 - *p1)).sqrt()))
+                .sum::<f64>()
+        })
+        .sum()
+}
+
+/// The kinetic temperature T = 2 K / (d (N - 1)) in units where k_B = 1, where K is the kinetic
+/// energy relative to the center of mass, which has d (N - 1) degrees of freedom in d dimensions.
+pub fn temperature<V: Vector>(masses: &[V::Scalar], velocities: &[V]) -> f64 {
+    let masses: Vec<f64> = masses.iter().map(|m| m.to_f64()).collect();
+    let mass: f64 = masses.iter().sum();
+    if masses.len() < 2 || mass <= 0.0 {
+        return 0.0;
+    }
+
+    let mut momentum = vec![0.0; V::DIM];
+    for (m, v) in masses.iter().zip(velocities.iter()) {
+        for (i, p) in momentum.iter_mut().enumerate() {
+            *p += v.component(i) * m;
+        }
+    }
+    let u: Vec<f64> = momentum.iter().map(|p| p / mass).collect();
+    let internal = masses
+        .iter()
+        .zip(velocities.iter())
+        .map(|(m, v)| 0.5 * m * u.iter().enumerate().map(|(i, u)| (v.component(i) - u).powi(2)).sum::<f64>())
+        .sum::<f64>();
+
+    2.0 * internal / ((V::DIM * (masses.len() - 1)) as f64)
+}
+
+#[pymethods]
+impl Particles {
+    /// The total kinetic energy of all particles.
+    pub fn kinetic_energy(&self) -> f64 {
+        kinetic_energy(&self.state.masses, &self.state.velocities)
+    }
+
+    /// The total pair interaction energy of all particles.
+    /// The external potential is given as a force and does not contribute.
+    pub fn interaction_energy(&self) -> f64 {
+        interaction_energy(&self.state.positions)
+    }
+
+    /// The kinetic temperature, in units where k_B = 1,
+    /// from the kinetic energy relative to the center of mass.
+    pub fn temperature(&self) -> f64 {
+        temperature(&self.state.masses, &self.state.velocities)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Energies {
+    kinetic: Vec<f64>,
+    interaction: Vec<f64>,
+}
+
+impl Record for Energies {
+    fn record(&mut self, particles: &Particles) -> PyResult<()> {
+        self.kinetic.push(particles.kinetic_energy());
+        self.interaction.push(particles.interaction_energy());
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.kinetic.clear();
+        self.interaction.clear();
+    }
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(extends=ObserverBase)]
+#[derive(Debug, Clone)]
+/// Records the kinetic and interaction energies every `interval` steps when added to a simulation with
+/// [add_observer](../../particles/struct.Particles.html#method.add_observer).
+///
+/// # Examples
+///
+/// Python:
+/// ```python
+/// energies = EnergyObserver(interval=10)
+/// particles.add_observer(energies)
+/// particles.run(n=1000, h=0.01)
+/// plt.plot(energies.times, energies.total)
+/// ```
+///
+pub struct EnergyObserver {
+    recorder: Arc<Mutex<Recorder<Energies>>>,
+}
+
+#[pymethods]
+impl EnergyObserver {
+    #[new]
+    #[args(interval = "1")]
+    pub fn new(interval: usize) -> PyResult<(Self, ObserverBase)> {
+        let (recorder, base) = ObserverBase::share(Recorder::new(interval, Energies::default())?);
+        Ok((Self { recorder }, base))
+    }
+
+    /// The recorded kinetic energies.
+    #[getter]
+    pub fn kinetic(&self) -> PyResult<Vec<f64>> {
+        Ok(lock(&self.recorder)?.data.kinetic.clone())
+    }
+
+    /// The recorded pair interaction energies.
+    #[getter]
+    pub fn interaction(&self) -> PyResult<Vec<f64>> {
+        Ok(lock(&self.recorder)?.data.interaction.clone())
+    }
+
+    /// The recorded sums of the kinetic and interaction energies.
+    #[getter]
+    pub fn total(&self) -> PyResult<Vec<f64>> {
+        let recorder = lock(&self.recorder)?;
+        Ok(recorder
+            .data
+            .kinetic
+            .iter()
+            .zip(recorder.data.interaction.iter())
+            .map(|(k, u)| k + u)
+            .collect())
+    }
+}
+
+recorder_methods!(EnergyObserver);
+
+#[derive(Debug, Default)]
+struct Temperatures {
+    temperatures: Vec<f64>,
+}
+
+impl Record for Temperatures {
+    fn record(&mut self, particles: &Particles) -> PyResult<()> {
+        self.temperatures.push(particles.temperature());
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.temperatures.clear();
+    }
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(extends=ObserverBase)]
+#[derive(Debug, Clone)]
+/// Records the [temperature](../../particles/struct.Particles.html#method.temperature) every `interval`
+/// steps when added to a simulation with [add_observer](../../particles/struct.Particles.html#method.add_observer).
+pub struct TemperatureObserver {
+    recorder: Arc<Mutex<Recorder<Temperatures>>>,
+}
+
+#[pymethods]
+impl TemperatureObserver {
+    #[new]
+    #[args(interval = "1")]
+    pub fn new(interval: usize) -> PyResult<(Self, ObserverBase)> {
+        let (recorder, base) = ObserverBase::share(Recorder::new(interval, Temperatures::default())?);
+        Ok((Self { recorder }, base))
+    }
+
+    /// The recorded temperatures.
+    #[getter]
+    pub fn temperatures(&self) -> PyResult<Vec<f64>> {
+        Ok(lock(&self.recorder)?.data.temperatures.clone())
+    }
+}
+
+recorder_methods!(TemperatureObserver);
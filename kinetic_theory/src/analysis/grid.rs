@@ -0,0 +1,439 @@
+//! Deposition of particle mass, momentum and kinetic energy onto Cartesian grids
+
+use crate::particles::Particles;
+use crate::vec3::Vec3;
+use rayon::prelude::*;
+
+use numpy::{PyArray, PyArrayDyn};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+// number of deposited channels per cell: mass, number, momentum (3), kinetic energy
+const CHANNELS: usize = 6;
+
+/// The scheme used to assign a particle to the surrounding grid cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assignment {
+    /// Nearest Grid Point: the whole particle is assigned to the cell it is in
+    Ngp,
+    /// Cloud In Cell: linear weights over the two nearest cells per axis
+    Cic,
+    /// Triangular Shaped Cloud: quadratic weights over the three nearest cells per axis
+    Tsc,
+}
+
+impl Assignment {
+    /// Parse an assignment scheme from its (case-insensitive) abbreviation.
+    pub fn parse(name: &str) -> PyResult<Self> {
+        match name.to_lowercase().as_str() {
+            "ngp" => Ok(Assignment::Ngp),
+            "cic" => Ok(Assignment::Cic),
+            "tsc" => Ok(Assignment::Tsc),
+            _ => Err(PyValueError::new_err(format!(
+                "unknown assignment scheme '{}', expected one of 'ngp', 'cic' or 'tsc'",
+                name
+            ))),
+        }
+    }
+
+    /// Returns the cell indices and weights along one axis for a particle at `s`,
+    /// where `s` is the position in units of the cell size, relative to the center of cell 0.
+    fn weights(self, s: f64) -> Vec<(isize, f64)> {
+        match self {
+            // floor of the position relative to the lower grid edge, so the lower edge of each
+            // cell belongs to it, unlike rounding, which moves a particle at s = -0.5 out of the grid
+            Assignment::Ngp => vec![((s + 0.5).floor() as isize, 1.0)],
+            Assignment::Cic => {
+                let i = s.floor();
+                let f = s - i;
+                vec![(i as isize, 1.0 - f), (i as isize + 1, f)]
+            }
+            Assignment::Tsc => {
+                let i = s.round();
+                let d = s - i;
+                let i = i as isize;
+                vec![
+                    (i - 1, 0.5 * (0.5 - d).powi(2)),
+                    (i, 0.75 - d * d),
+                    (i + 1, 0.5 * (0.5 + d).powi(2)),
+                ]
+            }
+        }
+    }
+}
+
+/// A regular Cartesian grid over the first 1, 2 or 3 axes (x, y, z).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    pub lower: Vec<f64>,
+    pub upper: Vec<f64>,
+    pub bins: Vec<usize>,
+}
+
+impl Grid {
+    /// Create a new grid, checking that the bounds and bins are consistent.
+    pub fn new(lower: Vec<f64>, upper: Vec<f64>, bins: Vec<usize>) -> PyResult<Self> {
+        if bins.is_empty() || bins.len() > 3 {
+            return Err(PyValueError::new_err("grids must have 1, 2 or 3 dimensions"));
+        }
+        if lower.len() != bins.len() || upper.len() != bins.len() {
+            return Err(PyValueError::new_err("there must be one range per grid dimension"));
+        }
+        if bins.contains(&0) {
+            return Err(PyValueError::new_err("grids must have at least one bin per dimension"));
+        }
+        if lower.iter().zip(upper.iter()).any(|(l, u)| l >= u || !l.is_finite() || !u.is_finite()) {
+            return Err(PyValueError::new_err("grid ranges must be finite and non-empty"));
+        }
+
+        Ok(Self { lower, upper, bins })
+    }
+
+    /// Create a grid that covers the bounding box of the given positions.
+    pub fn bounding(positions: &[Vec3], bins: Vec<usize>) -> PyResult<Self> {
+        let ndim = bins.len();
+        let mut lower = vec![f64::INFINITY; ndim];
+        let mut upper = vec![f64::NEG_INFINITY; ndim];
+        for p in positions {
+            for (axis, c) in components(p).iter().take(ndim).enumerate() {
+                lower[axis] = lower[axis].min(*c);
+                upper[axis] = upper[axis].max(*c);
+            }
+        }
+
+        for (l, u) in lower.iter_mut().zip(upper.iter_mut()) {
+            if !l.is_finite() || !u.is_finite() {
+                // no particles, use the unit interval
+                *l = 0.0;
+                *u = 1.0;
+            }
+            // pad the range slightly so particles on the upper edge are still inside the grid,
+            // and flat distributions do not result in an empty range
+            let pad = 1e-9 * (*u - *l).abs().max(1.0);
+            *l -= pad;
+            *u += pad;
+        }
+
+        Self::new(lower, upper, bins)
+    }
+
+    pub fn ndim(&self) -> usize {
+        self.bins.len()
+    }
+
+    pub fn num_cells(&self) -> usize {
+        self.bins.iter().product()
+    }
+
+    pub fn cell_size(&self, axis: usize) -> f64 {
+        (self.upper[axis] - self.lower[axis]) / self.bins[axis] as f64
+    }
+
+    pub fn cell_volume(&self) -> f64 {
+        (0..self.ndim()).map(|axis| self.cell_size(axis)).product()
+    }
+
+    /// The bin edges along an axis.
+    pub fn edges(&self, axis: usize) -> Vec<f64> {
+        let dx = self.cell_size(axis);
+        (0..=self.bins[axis])
+            .map(|i| self.lower[axis] + i as f64 * dx)
+            .collect()
+    }
+
+    /// The bin centers along an axis.
+    pub fn centers(&self, axis: usize) -> Vec<f64> {
+        let dx = self.cell_size(axis);
+        (0..self.bins[axis])
+            .map(|i| self.lower[axis] + (i as f64 + 0.5) * dx)
+            .collect()
+    }
+
+    /// Returns the flat (row-major) cell indices and weights a particle at `p` is assigned to.
+    /// Cells outside of the grid are skipped, so particles near or beyond
+    /// the grid boundary deposit only part of (or none of) their weight.
+    fn assign(&self, p: &Vec3, scheme: Assignment) -> Vec<(usize, f64)> {
+        let mut result = vec![(0usize, 1.0)];
+
+        for (axis, c) in components(p).iter().take(self.ndim()).enumerate() {
+            let s = (c - self.lower[axis]) / self.cell_size(axis) - 0.5;
+            let n = self.bins[axis] as isize;
+            let axis_weights: Vec<(usize, f64)> = scheme
+                .weights(s)
+                .into_iter()
+                .filter(|&(i, w)| i >= 0 && i < n && w > 0.0)
+                .map(|(i, w)| (i as usize, w))
+                .collect();
+
+            result = result
+                .iter()
+                .flat_map(|&(flat, w0)| {
+                    axis_weights
+                        .iter()
+                        .map(move |&(i, w)| (flat * self.bins[axis] + i, w0 * w))
+                })
+                .collect();
+        }
+
+        result
+    }
+}
+
+fn components(v: &Vec3) -> [f64; 3] {
+    [v.x, v.y, v.z]
+}
+
+/// The flow velocity of a cell, zero if the cell is empty.
+pub(crate) fn flow_velocity(cell: &[f64]) -> [f64; 3] {
+    if cell[0] > 0.0 {
+        [cell[2] / cell[0], cell[3] / cell[0], cell[4] / cell[0]]
+    } else {
+        [0.0; 3]
+    }
+}
+
+/// The temperature of a cell in the frame co-moving with its flow velocity, with `dimensions`
+/// degrees of freedom per particle, zero if the cell is empty.
+pub(crate) fn cell_temperature(cell: &[f64], dimensions: usize) -> f64 {
+    if cell[0] > 0.0 && cell[1] > 0.0 {
+        let p_sq = cell[2] * cell[2] + cell[3] * cell[3] + cell[4] * cell[4];
+        let thermal = cell[5] - p_sq / (2.0 * cell[0]);
+        (2.0 * thermal / (dimensions as f64 * cell[1])).max(0.0)
+    } else {
+        0.0
+    }
+}
+
+/// The number of axes along which the particles are spread out, so a planar cloud has two
+/// and a cloud on a line one dimension. Like the principal widths of a
+/// [Shape](../shape/struct.Shape.html), widths below a tiny fraction of the largest count as zero.
+/// A cloud without any extent is taken to be three-dimensional.
+pub(crate) fn spanned_dimensions(positions: &[Vec3]) -> usize {
+    let mut lower = [f64::INFINITY; 3];
+    let mut upper = [f64::NEG_INFINITY; 3];
+    for p in positions {
+        for (axis, c) in components(p).iter().enumerate() {
+            lower[axis] = lower[axis].min(*c);
+            upper[axis] = upper[axis].max(*c);
+        }
+    }
+
+    let widths: Vec<f64> = lower.iter().zip(upper.iter()).map(|(l, u)| (u - l).max(0.0)).collect();
+    let largest = widths.iter().copied().fold(0.0, f64::max);
+    if largest == 0.0 || !largest.is_finite() {
+        return 3;
+    }
+    widths.iter().filter(|&&w| w > 1e-12 * largest).count()
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass]
+#[derive(Debug, Clone)]
+/// Mass, momentum and kinetic energy of the particles, deposited onto a Cartesian grid.
+/// All fields are returned as NumPy arrays with one axis per grid dimension,
+/// vector fields have an additional trailing axis of length 3.
+pub struct GridFields {
+    grid: Grid,
+    /// The number of dimensions the particles are spread out in, see [temperature](#method.temperature)
+    #[pyo3(get)]
+    dimensions: usize,
+    // interleaved channels per cell, see CHANNELS
+    data: Vec<f64>,
+}
+
+#[pymethods]
+impl GridFields {
+    /// The number of grid cells along each axis.
+    #[getter]
+    pub fn shape(&self) -> Vec<usize> {
+        self.grid.bins.clone()
+    }
+
+    /// The bin edges along the given axis.
+    pub fn edges(&self, axis: usize) -> PyResult<Vec<f64>> {
+        self.check_axis(axis)?;
+        Ok(self.grid.edges(axis))
+    }
+
+    /// The bin centers along the given axis.
+    pub fn centers(&self, axis: usize) -> PyResult<Vec<f64>> {
+        self.check_axis(axis)?;
+        Ok(self.grid.centers(axis))
+    }
+
+    /// The mass density.
+    #[getter]
+    pub fn density<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArrayDyn<f64>> {
+        let volume = self.grid.cell_volume();
+        self.scalar_field(py, |cell| cell[0] / volume)
+    }
+
+    /// The number density.
+    #[getter]
+    pub fn number_density<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArrayDyn<f64>> {
+        let volume = self.grid.cell_volume();
+        self.scalar_field(py, |cell| cell[1] / volume)
+    }
+
+    /// The momentum density.
+    #[getter]
+    pub fn momentum_density<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArrayDyn<f64>> {
+        let volume = self.grid.cell_volume();
+        self.vector_field(py, |cell| [cell[2] / volume, cell[3] / volume, cell[4] / volume])
+    }
+
+    /// The kinetic energy density.
+    #[getter]
+    pub fn kinetic_energy_density<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArrayDyn<f64>> {
+        let volume = self.grid.cell_volume();
+        self.scalar_field(py, |cell| cell[5] / volume)
+    }
+
+    /// The flow velocity, i.e. the momentum density divided by the mass density.
+    /// This is zero in empty cells.
+    #[getter]
+    pub fn velocity<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArrayDyn<f64>> {
+        self.vector_field(py, flow_velocity)
+    }
+
+    /// The local temperature, from the kinetic energy in the frame co-moving
+    /// with the flow velocity: T = 2 (E - P^2 / 2M) / dN, in units where k_B = 1.
+    /// The number of degrees of freedom d is the number of [dimensions](#structfield.dimensions)
+    /// the particles are spread out in, e.g. two for a planar cloud.
+    /// This is zero in empty cells.
+    #[getter]
+    pub fn temperature<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArrayDyn<f64>> {
+        let dimensions = self.dimensions;
+        self.scalar_field(py, |cell| cell_temperature(cell, dimensions))
+    }
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl GridFields {
+    /// Deposit the particles onto the grid with the given assignment scheme.
+    pub fn deposit(particles: &Particles, grid: Grid, scheme: Assignment) -> Self {
+        let len = grid.num_cells() * CHANNELS;
+
+        let data = particles
+            .state.positions
+            .par_iter()
+            .zip(particles.state.velocities.par_iter())
+            .zip(particles.state.masses.par_iter())
+            .fold(
+                || vec![0.0; len],
+                |mut data, ((x, v), &m)| {
+                    let p = v * m;
+                    let e = 0.5 * m * v.abs_sq();
+                    for (cell, w) in grid.assign(x, scheme) {
+                        let cell = &mut data[cell * CHANNELS..(cell + 1) * CHANNELS];
+                        cell[0] += w * m;
+                        cell[1] += w;
+                        cell[2] += w * p.x;
+                        cell[3] += w * p.y;
+                        cell[4] += w * p.z;
+                        cell[5] += w * e;
+                    }
+                    data
+                },
+            )
+            .reduce(
+                || vec![0.0; len],
+                |mut a, b| {
+                    a.iter_mut().zip(b.iter()).for_each(|(a, b)| *a += b);
+                    a
+                },
+            );
+
+        Self {
+            grid,
+            dimensions: spanned_dimensions(&particles.state.positions),
+            data,
+        }
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// The deposited channels of each cell in row-major order:
+    /// mass, number, momentum (3) and kinetic energy.
+    pub fn cells(&self) -> std::slice::Chunks<'_, f64> {
+        self.data.chunks(CHANNELS)
+    }
+
+    fn check_axis(&self, axis: usize) -> PyResult<()> {
+        if axis >= self.grid.ndim() {
+            return Err(PyValueError::new_err(format!(
+                "axis {} is out of range for a grid with {} dimensions",
+                axis,
+                self.grid.ndim()
+            )));
+        }
+        Ok(())
+    }
+
+    fn scalar_field<'py, F>(&self, py: Python<'py>, f: F) -> PyResult<&'py PyArrayDyn<f64>>
+    where
+        F: Fn(&[f64]) -> f64,
+    {
+        let values: Vec<f64> = self.data.chunks(CHANNELS).map(f).collect();
+        PyArray::from_vec(py, values).reshape(self.grid.bins.clone())
+    }
+
+    fn vector_field<'py, F>(&self, py: Python<'py>, f: F) -> PyResult<&'py PyArrayDyn<f64>>
+    where
+        F: Fn(&[f64]) -> [f64; 3],
+    {
+        let values: Vec<f64> = self.data.chunks(CHANNELS).flat_map(|cell| f(cell).to_vec()).collect();
+        let mut shape = self.grid.bins.clone();
+        shape.push(3);
+        PyArray::from_vec(py, values).reshape(shape)
+    }
+}
+
+#[pymethods]
+impl Particles {
+    /// Deposit the particle mass, momentum and kinetic energy onto a Cartesian grid,
+    /// from which density, flow velocity and temperature fields can be obtained.
+    ///
+    /// # Arguments
+    ///
+    /// * `bins` - The number of bins per axis. One, two or three entries bin the particles
+    /// over the x, the x and y, or the x, y and z axes, respectively.
+    /// * `ranges` - Optional list of `(lower, upper)` bounds per axis. Defaults to the bounding
+    /// box of the particles. Particles outside of the grid are not deposited.
+    /// * `scheme` - The assignment scheme, one of `"ngp"`, `"cic"` (default) or `"tsc"`.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// fields = particles.grid_fields([50, 50], ranges=[(-30.0, 30.0), (-30.0, 30.0)])
+    /// plt.imshow(fields.density.T, origin="lower")
+    /// ```
+    ///
+    #[args(ranges = "None", scheme = "\"cic\"")]
+    pub fn grid_fields(
+        &self,
+        bins: Vec<usize>,
+        ranges: Option<Vec<(f64, f64)>>,
+        scheme: &str,
+    ) -> PyResult<GridFields> {
+        let scheme = Assignment::parse(scheme)?;
+        let grid = match ranges {
+            Some(ranges) => Grid::new(
+                ranges.iter().map(|r| r.0).collect(),
+                ranges.iter().map(|r| r.1).collect(),
+                bins,
+            )?,
+            None => Grid::bounding(&self.state.positions, bins)?,
+        };
+
+        Ok(GridFields::deposit(self, grid, scheme))
+    }
+}
@@ -0,0 +1,13 @@
+//! Analysis routines that operate on the state of a [`Particles`](../particles/struct.Particles.html)
+//! simulation. These are implemented in Rust so they are cheap enough to be called
+//! regularly during a run instead of copying the whole state to Python.
+
+pub mod cluster;
+pub mod cluster_statistics;
+pub mod cluster_tracking;
+pub mod correlation;
+pub mod energy;
+pub mod grid;
+pub mod shape;
+pub mod structure_factor;
+pub mod velocity_distribution;
@@ -0,0 +1,339 @@
+//! Cloud shape analysis from the second moments of the position and momentum distributions
+
+use crate::observer::{lock, recorder_methods, ObserverBase, Record, Recorder};
+use crate::particles::Particles;
+use crate::utils::symmetric_eigen;
+use crate::vec3::Vec3;
+use std::sync::{Arc, Mutex};
+
+use pyo3::prelude::*;
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+/// The shape of a distribution of points, described by its first and second moments.
+/// Principal widths and axes are sorted from the largest to the smallest width.
+pub struct Shape {
+    /// The mean of the distribution
+    #[pyo3(get)]
+    pub center: Vec3,
+    /// The covariance matrix
+    pub covariance: [[f64; 3]; 3],
+    /// The standard deviations along the principal axes
+    #[pyo3(get)]
+    pub principal_widths: [f64; 3],
+    /// The principal axes, i.e. the eigenvectors of the covariance matrix
+    pub principal_axes: [Vec3; 3],
+    /// The standard deviations along the x, y and z axes
+    #[pyo3(get)]
+    pub rms_widths: Vec3,
+    /// The widths of Gaussians fitted to the histograms along the x, y and z axes.
+    /// These are NaN where the fit is not possible.
+    #[pyo3(get)]
+    pub gaussian_widths: Vec3,
+}
+
+#[pymethods]
+impl Shape {
+    /// The covariance matrix as a list of rows.
+    #[getter]
+    pub fn covariance(&self) -> Vec<Vec<f64>> {
+        self.covariance.iter().map(|row| row.to_vec()).collect()
+    }
+
+    /// The principal axes, i.e. the eigenvectors of the covariance matrix.
+    #[getter]
+    pub fn principal_axes(&self) -> Vec<Vec3> {
+        self.principal_axes.to_vec()
+    }
+
+    /// The root mean square distance of the points from their center,
+    /// i.e. the square root of the trace of the covariance matrix.
+    #[getter]
+    pub fn rms_radius(&self) -> f64 {
+        (self.covariance[0][0] + self.covariance[1][1] + self.covariance[2][2]).sqrt()
+    }
+
+    /// The ratio of the largest to the smallest principal width.
+    /// Axes with vanishing width, e.g. z for a flat cloud, are ignored.
+    #[getter]
+    pub fn aspect_ratio(&self) -> f64 {
+        let (largest, smallest) = self.extreme_widths();
+        largest / smallest
+    }
+
+    /// The eccentricity sqrt(1 - (b / a)^2), where a and b are the largest and smallest principal widths.
+    /// Axes with vanishing width, e.g. z for a flat cloud, are ignored.
+    #[getter]
+    pub fn eccentricity(&self) -> f64 {
+        let (largest, smallest) = self.extreme_widths();
+        (1.0 - (smallest / largest).powi(2)).max(0.0).sqrt()
+    }
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl Shape {
+    /// Computes the shape of a distribution of points.
+    pub fn new(points: &[Vec3]) -> Self {
+        if points.is_empty() {
+            return Self::default();
+        }
+
+        let n = points.len() as f64;
+        let center = points.iter().copied().sum::<Vec3>() / n;
+
+        let mut covariance = [[0.0; 3]; 3];
+        for p in points {
+            let d = components(&(p - center));
+            for (i, row) in covariance.iter_mut().enumerate() {
+                for (j, c) in row.iter_mut().enumerate() {
+                    *c += d[i] * d[j] / n;
+                }
+            }
+        }
+
+        let (eigenvalues, eigenvectors) = symmetric_eigen(covariance);
+        let principal_widths = [
+            eigenvalues[0].max(0.0).sqrt(),
+            eigenvalues[1].max(0.0).sqrt(),
+            eigenvalues[2].max(0.0).sqrt(),
+        ];
+        let principal_axes = [
+            Vec3::new(eigenvectors[0][0], eigenvectors[0][1], eigenvectors[0][2]),
+            Vec3::new(eigenvectors[1][0], eigenvectors[1][1], eigenvectors[1][2]),
+            Vec3::new(eigenvectors[2][0], eigenvectors[2][1], eigenvectors[2][2]),
+        ];
+
+        let rms_widths = Vec3::new(
+            covariance[0][0].sqrt(),
+            covariance[1][1].sqrt(),
+            covariance[2][2].sqrt(),
+        );
+
+        let mean = components(&center);
+        let rms = components(&rms_widths);
+        let fit = |axis: usize| {
+            let values: Vec<f64> = points.iter().map(|p| components(p)[axis]).collect();
+            gaussian_width(&values, mean[axis], rms[axis])
+        };
+        let gaussian_widths = Vec3::new(fit(0), fit(1), fit(2));
+
+        Self {
+            center,
+            covariance,
+            principal_widths,
+            principal_axes,
+            rms_widths,
+            gaussian_widths,
+        }
+    }
+
+    /// The largest and the smallest non-vanishing principal width
+    fn extreme_widths(&self) -> (f64, f64) {
+        let largest = self.principal_widths[0];
+        let smallest = self
+            .principal_widths
+            .iter()
+            .copied()
+            .filter(|&w| w > 1e-12 * largest)
+            .fold(largest, f64::min);
+        (largest, smallest)
+    }
+}
+
+fn components(v: &Vec3) -> [f64; 3] {
+    [v.x, v.y, v.z]
+}
+
+/// Fits a Gaussian to the histogram of `values` and returns its width.
+/// The histogram covers 4 standard deviations around the mean, and the fit is a
+/// weighted least squares fit of a parabola to the logarithm of the bin counts
+/// ([Caruana's Algorithm](https://doi.org/10.1109/MSP.2011.941846)).
+///
+/// Returns NaN if the values do not allow a fit.
+fn gaussian_width(values: &[f64], mean: f64, rms: f64) -> f64 {
+    if rms.is_nan() || rms <= 0.0 || values.len() < 3 {
+        return f64::NAN;
+    }
+
+    let bins = ((values.len() as f64).sqrt() as usize).clamp(8, 100);
+    let (lower, upper) = (mean - 4.0 * rms, mean + 4.0 * rms);
+    let dx = (upper - lower) / bins as f64;
+
+    let mut counts = vec![0.0; bins];
+    for v in values {
+        let i = ((v - lower) / dx).floor();
+        if i >= 0.0 && (i as usize) < bins {
+            counts[i as usize] += 1.0;
+        }
+    }
+
+    // normal equations of the weighted fit ln(y) = a + b x + c x^2 with weights y^2,
+    // where x is measured from the mean for numerical stability
+    let mut matrix = [[0.0; 3]; 3];
+    let mut rhs = [0.0; 3];
+    for (i, &y) in counts.iter().enumerate() {
+        if y <= 0.0 {
+            continue;
+        }
+        let x = (lower + (i as f64 + 0.5) * dx - mean) / rms;
+        let w = y * y;
+        let powers = [1.0, x, x * x];
+        for (r, row) in matrix.iter_mut().enumerate() {
+            for (c, m) in row.iter_mut().enumerate() {
+                *m += w * powers[r] * powers[c];
+            }
+            rhs[r] += w * powers[r] * y.ln();
+        }
+    }
+
+    match solve3(matrix, rhs) {
+        Some([_, _, c]) if c < 0.0 => rms * (-1.0 / (2.0 * c)).sqrt(),
+        _ => f64::NAN,
+    }
+}
+
+/// Solves the linear system `m x = b` with Gaussian elimination and partial pivoting.
+fn solve3(mut m: [[f64; 3]; 3], mut b: [f64; 3]) -> Option<[f64; 3]> {
+    for col in 0..3 {
+        let pivot = (col..3).max_by(|&i, &j| m[i][col].abs().partial_cmp(&m[j][col].abs()).unwrap())?;
+        if m[pivot][col].abs() < 1e-300 {
+            return None;
+        }
+        m.swap(col, pivot);
+        b.swap(col, pivot);
+
+        for row in col + 1..3 {
+            let factor = m[row][col] / m[col][col];
+            let pivot_row = m[col];
+            for (k, v) in m[row].iter_mut().enumerate().skip(col) {
+                *v -= factor * pivot_row[k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = [0.0; 3];
+    for row in (0..3).rev() {
+        let sum: f64 = (row + 1..3).map(|k| m[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / m[row][row];
+    }
+    Some(x)
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+/// The shape of a particle cloud in position and momentum space at some point in time.
+pub struct CloudShape {
+    /// The simulation time at which the shape was computed
+    #[pyo3(get)]
+    pub time: f64,
+    /// The shape of the position distribution
+    #[pyo3(get)]
+    pub position: Shape,
+    /// The shape of the momentum distribution
+    #[pyo3(get)]
+    pub momentum: Shape,
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl CloudShape {
+    pub fn new(particles: &Particles) -> Self {
+        let momenta: Vec<Vec3> = particles
+            .state.velocities
+            .iter()
+            .zip(particles.state.masses.iter())
+            .map(|(v, &m)| v * m)
+            .collect();
+
+        Self {
+            time: particles.state.time,
+            position: Shape::new(&particles.state.positions),
+            momentum: Shape::new(&momenta),
+        }
+    }
+}
+
+#[pymethods]
+impl Particles {
+    /// Compute the shape of the particle cloud in position and momentum space,
+    /// i.e. its second moments, principal axes and widths, aspect ratio and eccentricity.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// shape = particles.shape()
+    /// print(shape.position.aspect_ratio, shape.momentum.eccentricity)
+    /// ```
+    ///
+    pub fn shape(&self) -> CloudShape {
+        CloudShape::new(self)
+    }
+}
+
+#[derive(Debug)]
+struct Shapes {
+    max_radius: Option<f64>,
+    shapes: Vec<CloudShape>,
+}
+
+impl Record for Shapes {
+    fn record(&mut self, particles: &Particles) -> PyResult<()> {
+        self.shapes.push(CloudShape::new(particles));
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.shapes.clear();
+    }
+
+    fn stop_requested(&self) -> bool {
+        match (self.max_radius, self.shapes.last()) {
+            (Some(max_radius), Some(shape)) => shape.position.rms_radius() > max_radius,
+            _ => false,
+        }
+    }
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(extends=ObserverBase)]
+#[derive(Debug, Clone)]
+/// Records the [shape](../../particles/struct.Particles.html#method.shape) of the particle cloud
+/// every `interval` steps when added to a simulation with
+/// [add_observer](../../particles/struct.Particles.html#method.add_observer).
+/// If `max_radius` is given, the run stops once the RMS radius of the cloud exceeds it.
+pub struct ShapeObserver {
+    recorder: Arc<Mutex<Recorder<Shapes>>>,
+}
+
+#[pymethods]
+impl ShapeObserver {
+    #[new]
+    #[args(interval = "1", max_radius = "None")]
+    pub fn new(interval: usize, max_radius: Option<f64>) -> PyResult<(Self, ObserverBase)> {
+        let shapes = Shapes {
+            max_radius,
+            shapes: Vec::new(),
+        };
+        let (recorder, base) = ObserverBase::share(Recorder::new(interval, shapes)?);
+        Ok((Self { recorder }, base))
+    }
+
+    /// The RMS radius beyond which the run stops, if any.
+    #[getter]
+    pub fn max_radius(&self) -> PyResult<Option<f64>> {
+        Ok(lock(&self.recorder)?.data.max_radius)
+    }
+
+    /// All recorded shapes.
+    #[getter]
+    pub fn shapes(&self) -> PyResult<Vec<CloudShape>> {
+        Ok(lock(&self.recorder)?.data.shapes.clone())
+    }
+}
+
+recorder_methods!(ShapeObserver);
@@ -0,0 +1,123 @@
+//! Static structure factor S(k) computed directly from the density Fourier sum
+
+use crate::particles::Particles;
+use crate::vec3::Vec3;
+use rayon::prelude::*;
+
+use pyo3::prelude::*;
+
+/// Computes the static structure factor for a single wave vector `k`
+///
+/// S(k) = |sum_j exp(i k * r_j)|^2 / N
+///
+/// # Arguments
+///
+/// * `positions` - The particle positions
+/// * `k` - The wave vector
+///
+pub fn structure_factor(positions: &[Vec3], k: &Vec3) -> f64 {
+    if positions.is_empty() {
+        return 0.0;
+    }
+
+    // real and imaginary part of the density Fourier component
+    let (re, im) = positions.iter().fold((0.0, 0.0), |(re, im), r| {
+        let phase = k * r;
+        (re + phase.cos(), im + phase.sin())
+    });
+
+    (re * re + im * im) / positions.len() as f64
+}
+
+/// Computes the static structure factor for each of the given wave vectors in parallel.
+/// See also [structure_factor](fn.structure_factor.html).
+pub fn structure_factor_vectors(positions: &[Vec3], ks: &[Vec3]) -> Vec<f64> {
+    ks.par_iter()
+        .map(|k| structure_factor(positions, k))
+        .collect()
+}
+
+/// Computes the spherically averaged static structure factor for each wave number in `ks`.
+/// For each wave number, S(k) is averaged over `n_directions` wave vectors
+/// of that length, which are evenly spread over the unit sphere.
+///
+/// # Arguments
+///
+/// * `positions` - The particle positions
+/// * `ks` - The wave numbers |k| at which to evaluate S(k)
+/// * `n_directions` - The number of directions to average over for each wave number
+///
+pub fn structure_factor_spherical(positions: &[Vec3], ks: &[f64], n_directions: usize) -> Vec<f64> {
+    let directions = sphere_directions(n_directions);
+
+    ks.par_iter()
+        .map(|&k| {
+            if directions.is_empty() {
+                return 0.0;
+            }
+            let sum: f64 = directions
+                .iter()
+                .map(|d| structure_factor(positions, &(d * k)))
+                .sum();
+            sum / directions.len() as f64
+        })
+        .collect()
+}
+
+/// Returns `n` unit vectors spread evenly over the unit sphere,
+/// using the [Fibonacci Lattice](https://en.wikipedia.org/wiki/Fibonacci_lattice).
+pub fn sphere_directions(n: usize) -> Vec<Vec3> {
+    let golden_angle = std::f64::consts::PI * (3.0 - 5f64.sqrt());
+
+    (0..n)
+        .map(|i| {
+            let z = 1.0 - (2 * i + 1) as f64 / n as f64;
+            let rho = (1.0 - z * z).sqrt();
+            let phi = golden_angle * i as f64;
+            Vec3::new(rho * phi.cos(), rho * phi.sin(), z)
+        })
+        .collect()
+}
+
+#[pymethods]
+impl Particles {
+    /// Compute the static structure factor S(k) = |sum_j exp(i k * r_j)|^2 / N
+    /// for each of the given wave vectors.
+    ///
+    /// # Arguments
+    ///
+    /// * `k_vectors` - A list of [`Vec3`](../vec3/struct.Vec3.html) wave vectors.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// s = particles.structure_factor([Vec3(0.1, 0.0, 0.0), Vec3(0.0, 0.1, 0.0)])
+    /// ```
+    ///
+    pub fn structure_factor(&self, k_vectors: Vec<Vec3>) -> Vec<f64> {
+        structure_factor_vectors(&self.state.positions, &k_vectors)
+    }
+
+    /// Compute the spherically averaged static structure factor S(k)
+    /// for each of the given wave numbers.
+    ///
+    /// # Arguments
+    ///
+    /// * `k` - A list of wave numbers |k|.
+    /// * `n_directions` - The number of wave vector directions to average over
+    /// for each wave number. Defaults to 64.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// ks = [0.05 * i for i in range(1, 100)]
+    /// s = particles.structure_factor_spherical(ks, n_directions=128)
+    /// ```
+    ///
+    #[args(n_directions = "64")]
+    pub fn structure_factor_spherical(&self, k: Vec<f64>, n_directions: usize) -> Vec<f64> {
+        structure_factor_spherical(&self.state.positions, &k, n_directions)
+    }
+}
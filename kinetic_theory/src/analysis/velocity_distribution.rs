@@ -0,0 +1,536 @@
+//! Velocity distribution histograms and their deviation from the Maxwell-Boltzmann distribution
+
+use crate::observer::{lock, recorder_methods, ObserverBase, Record, Recorder};
+use crate::particles::Particles;
+use crate::utils::erfc;
+use crate::vec3::Vec3;
+use std::f64::consts::{PI, SQRT_2};
+use std::sync::{Arc, Mutex};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+// the range of the histograms in units of the thermal velocity sqrt(T / m), if not given
+const DEFAULT_RANGE: f64 = 6.0;
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass]
+#[derive(Debug, Clone)]
+/// Histograms of the particle speeds and velocity components, relative to the center of mass
+/// velocity, compared to a Maxwell-Boltzmann distribution at the measured temperature.
+///
+/// Velocities are taken over the first `dimensions` axes (x, x and y, or x, y and z), and
+/// temperatures are given in units where k_B = 1. For mixtures, the reference distribution
+/// uses the mean particle mass. The distribution can be accumulated over several snapshots
+/// with [add](#method.add), in which case the reference temperature is the mean over all samples.
+pub struct VelocityDistribution {
+    dimensions: usize,
+    bins: usize,
+    bins_h: usize,
+    v_max: f64,
+    // number of particle velocities added, and the sums of their masses and thermal energies
+    samples: f64,
+    mass_sum: f64,
+    thermal_sum: f64,
+    // speed counts, with an overflow bin at the end
+    speed_counts: Vec<f64>,
+    // component counts per axis, with an underflow and an overflow bin at the end
+    component_counts: Vec<Vec<f64>>,
+    // counts on a coarser grid in velocity space, with an overflow bin at the end
+    h_counts: Vec<f64>,
+}
+
+#[pymethods]
+impl VelocityDistribution {
+    /// Create a new, empty velocity distribution.
+    ///
+    /// # Arguments
+    ///
+    /// * `v_max` - The largest speed (and velocity component) covered by the histograms.
+    /// * `bins` - The number of bins of the speed and velocity component histograms.
+    /// * `dimensions` - The number of velocity components to consider.
+    /// * `bins_h` - The number of bins per axis of the histogram in velocity space,
+    /// which is used to estimate the H-function.
+    ///
+    #[new]
+    #[args(bins = "50", dimensions = "3", bins_h = "20")]
+    pub fn new(v_max: f64, bins: usize, dimensions: usize, bins_h: usize) -> PyResult<Self> {
+        if !(1..=3).contains(&dimensions) {
+            return Err(PyValueError::new_err("dimensions must be 1, 2 or 3"));
+        }
+        if bins == 0 || bins_h == 0 {
+            return Err(PyValueError::new_err("histograms must have at least one bin"));
+        }
+        if !v_max.is_finite() || v_max <= 0.0 {
+            return Err(PyValueError::new_err("v_max must be positive and finite"));
+        }
+
+        Ok(Self {
+            dimensions,
+            bins,
+            bins_h,
+            v_max,
+            samples: 0.0,
+            mass_sum: 0.0,
+            thermal_sum: 0.0,
+            speed_counts: vec![0.0; bins + 1],
+            component_counts: vec![vec![0.0; bins + 2]; dimensions],
+            h_counts: vec![0.0; bins_h.pow(dimensions as u32) + 1],
+        })
+    }
+
+    /// Add the velocities of the particles in a simulation to the histograms.
+    pub fn add(&mut self, particles: &Particles) {
+        let peculiar = peculiar_velocities(particles);
+        let speed_width = self.v_max / self.bins as f64;
+        let component_width = 2.0 * self.v_max / self.bins as f64;
+        let h_width = 2.0 * self.v_max / self.bins_h as f64;
+
+        for (w, &m) in peculiar.iter().zip(particles.state.masses.iter()) {
+            let c = &components(w)[..self.dimensions];
+            let speed_sq: f64 = c.iter().map(|x| x * x).sum();
+
+            self.samples += 1.0;
+            self.mass_sum += m;
+            self.thermal_sum += m * speed_sq;
+
+            let i = bin(speed_sq.sqrt(), 0.0, speed_width, self.bins);
+            self.speed_counts[i.unwrap_or(self.bins)] += 1.0;
+
+            let mut h_index = Some(0);
+            for (axis, &x) in c.iter().enumerate() {
+                let i = match bin(x, -self.v_max, component_width, self.bins) {
+                    Some(i) => i,
+                    None if x < 0.0 => self.bins,
+                    None => self.bins + 1,
+                };
+                self.component_counts[axis][i] += 1.0;
+
+                h_index = match (h_index, bin(x, -self.v_max, h_width, self.bins_h)) {
+                    (Some(flat), Some(i)) => Some(flat * self.bins_h + i),
+                    _ => None,
+                };
+            }
+            let h_overflow = self.h_counts.len() - 1;
+            self.h_counts[h_index.unwrap_or(h_overflow)] += 1.0;
+        }
+    }
+
+    /// The number of particle velocities added to the histograms.
+    #[getter]
+    pub fn samples(&self) -> f64 {
+        self.samples
+    }
+
+    /// The largest speed covered by the histograms.
+    #[getter]
+    pub fn v_max(&self) -> f64 {
+        self.v_max
+    }
+
+    /// The measured temperature T = sum m w^2 / (d N), where w is the velocity
+    /// relative to the center of mass and d the number of dimensions.
+    #[getter]
+    pub fn temperature(&self) -> f64 {
+        if self.samples > 0.0 {
+            self.thermal_sum / (self.dimensions as f64 * self.samples)
+        } else {
+            0.0
+        }
+    }
+
+    /// The edges of the speed histogram bins.
+    pub fn speed_edges(&self) -> Vec<f64> {
+        edges(0.0, self.v_max, self.bins)
+    }
+
+    /// The normalized speed histogram, i.e. the estimated probability density of the speeds.
+    pub fn speed_histogram(&self) -> Vec<f64> {
+        let width = self.v_max / self.bins as f64;
+        self.speed_counts[..self.bins]
+            .iter()
+            .map(|c| c / (self.samples.max(1.0) * width))
+            .collect()
+    }
+
+    /// The edges of the velocity component histogram bins.
+    pub fn component_edges(&self) -> Vec<f64> {
+        edges(-self.v_max, self.v_max, self.bins)
+    }
+
+    /// The normalized histogram of the velocity component along `axis` (0, 1 or 2 for x, y or z).
+    pub fn component_histogram(&self, axis: usize) -> PyResult<Vec<f64>> {
+        let counts = self.axis_counts(axis)?;
+        let width = 2.0 * self.v_max / self.bins as f64;
+        Ok(counts[..self.bins]
+            .iter()
+            .map(|c| c / (self.samples.max(1.0) * width))
+            .collect())
+    }
+
+    /// The Maxwell-Boltzmann speed distribution at the measured temperature, evaluated at `v`.
+    pub fn maxwell_boltzmann_speed(&self, v: f64) -> f64 {
+        let a = self.thermal_velocity();
+        if a <= 0.0 || v < 0.0 {
+            return 0.0;
+        }
+        let x = v / a;
+        let norm = if self.dimensions == 2 { 1.0 } else { (2.0 / PI).sqrt() };
+        norm * x.powi(self.dimensions as i32 - 1) * (-0.5 * x * x).exp() / a
+    }
+
+    /// The Maxwell-Boltzmann distribution of a single velocity component
+    /// at the measured temperature, evaluated at `v`.
+    pub fn maxwell_boltzmann_component(&self, v: f64) -> f64 {
+        let a = self.thermal_velocity();
+        if a <= 0.0 {
+            return 0.0;
+        }
+        (-0.5 * (v / a).powi(2)).exp() / ((2.0 * PI).sqrt() * a)
+    }
+
+    /// The Kullback-Leibler divergence of the speed histogram from the
+    /// Maxwell-Boltzmann distribution at the measured temperature.
+    /// Speeds beyond `v_max` are counted in an overflow bin.
+    pub fn kl_divergence_speed(&self) -> f64 {
+        let a = self.thermal_velocity();
+        let d = self.dimensions;
+        let mut edges = self.speed_edges();
+        edges.push(f64::INFINITY);
+        let q: Vec<f64> = edges
+            .windows(2)
+            .map(|e| speed_survival(e[0], a, d) - speed_survival(e[1], a, d))
+            .collect();
+
+        kl_divergence(&self.speed_counts, &q)
+    }
+
+    /// The Kullback-Leibler divergence of the velocity component histogram along `axis` from
+    /// the Maxwell-Boltzmann distribution at the measured temperature.
+    /// Components beyond `v_max` are counted in under- and overflow bins.
+    pub fn kl_divergence_component(&self, axis: usize) -> PyResult<f64> {
+        let counts = self.axis_counts(axis)?;
+        let a = self.thermal_velocity();
+        let mut q: Vec<f64> = self
+            .component_edges()
+            .windows(2)
+            .map(|e| normal_interval(e[0], e[1], a))
+            .collect();
+        q.push(normal_interval(f64::NEG_INFINITY, -self.v_max, a));
+        q.push(normal_interval(self.v_max, f64::INFINITY, a));
+
+        Ok(kl_divergence(counts, &q))
+    }
+
+    /// The Boltzmann H-function H = integral f ln f d^dv of the normalized velocity distribution f,
+    /// estimated from a histogram in velocity space. Velocities outside of the histogram are ignored.
+    /// This is minimal for the Maxwell-Boltzmann distribution, see
+    /// [h_function_equilibrium](#method.h_function_equilibrium).
+    pub fn h_function(&self) -> f64 {
+        let volume = (2.0 * self.v_max / self.bins_h as f64).powi(self.dimensions as i32);
+        let total: f64 = self.h_counts.iter().sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+
+        self.h_counts[..self.h_counts.len() - 1]
+            .iter()
+            .filter(|&&c| c > 0.0)
+            .map(|c| {
+                let p = c / total;
+                p * (p / volume).ln()
+            })
+            .sum()
+    }
+
+    /// The H-function of the Maxwell-Boltzmann distribution at the measured temperature,
+    /// -d/2 ln(2 pi e T / m).
+    pub fn h_function_equilibrium(&self) -> f64 {
+        let a = self.thermal_velocity();
+        -0.5 * self.dimensions as f64 * (2.0 * PI * std::f64::consts::E * a * a).ln()
+    }
+
+    /// Forget all added velocities.
+    pub fn clear(&mut self) {
+        self.samples = 0.0;
+        self.mass_sum = 0.0;
+        self.thermal_sum = 0.0;
+        self.speed_counts.iter_mut().for_each(|c| *c = 0.0);
+        self.component_counts.iter_mut().flatten().for_each(|c| *c = 0.0);
+        self.h_counts.iter_mut().for_each(|c| *c = 0.0);
+    }
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl VelocityDistribution {
+    /// The thermal velocity sqrt(T / m) of the reference Maxwell-Boltzmann distribution
+    fn thermal_velocity(&self) -> f64 {
+        if self.mass_sum > 0.0 {
+            (self.temperature() * self.samples / self.mass_sum).sqrt()
+        } else {
+            0.0
+        }
+    }
+
+    fn axis_counts(&self, axis: usize) -> PyResult<&Vec<f64>> {
+        self.component_counts.get(axis).ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "axis {} is out of range for a distribution with {} dimensions",
+                axis, self.dimensions
+            ))
+        })
+    }
+}
+
+/// Returns the velocities relative to the center of mass velocity.
+fn peculiar_velocities(particles: &Particles) -> Vec<Vec3> {
+    let mass: f64 = particles.state.masses.iter().sum();
+    let momentum: Vec3 = particles
+        .state.velocities
+        .iter()
+        .zip(particles.state.masses.iter())
+        .map(|(v, &m)| v * m)
+        .sum();
+    let u = if mass > 0.0 { momentum / mass } else { Vec3::default() };
+
+    particles.state.velocities.iter().map(|v| v - u).collect()
+}
+
+/// Estimates the maximum speed of the histograms from the thermal velocity of a simulation.
+fn default_v_max(particles: &Particles, dimensions: usize) -> f64 {
+    let peculiar = peculiar_velocities(particles);
+    let thermal_sq: f64 = peculiar
+        .iter()
+        .map(|w| components(w)[..dimensions].iter().map(|x| x * x).sum::<f64>())
+        .sum();
+    let n = peculiar.len().max(1) as f64;
+    let v_max = DEFAULT_RANGE * (thermal_sq / (dimensions as f64 * n)).sqrt();
+    if v_max > 0.0 { v_max } else { 1.0 }
+}
+
+fn components(v: &Vec3) -> [f64; 3] {
+    [v.x, v.y, v.z]
+}
+
+fn bin(x: f64, lower: f64, width: f64, bins: usize) -> Option<usize> {
+    let i = ((x - lower) / width).floor();
+    if i >= 0.0 && (i as usize) < bins {
+        Some(i as usize)
+    } else {
+        None
+    }
+}
+
+fn edges(lower: f64, upper: f64, bins: usize) -> Vec<f64> {
+    let width = (upper - lower) / bins as f64;
+    (0..=bins).map(|i| lower + i as f64 * width).collect()
+}
+
+/// The probability that a normally distributed variable with mean 0 and standard deviation `a` is in [lower, upper].
+/// The tails are computed with erfc, to keep the relative accuracy for small probabilities.
+fn normal_interval(lower: f64, upper: f64, a: f64) -> f64 {
+    let q = |x: f64| 0.5 * erfc(x / (SQRT_2 * a));
+    if lower >= 0.0 {
+        q(lower) - q(upper)
+    } else if upper <= 0.0 {
+        q(-upper) - q(-lower)
+    } else {
+        1.0 - q(-lower) - q(upper)
+    }
+}
+
+/// The probability that a Maxwell-Boltzmann distributed speed in `d` dimensions exceeds `v`.
+fn speed_survival(v: f64, a: f64, d: usize) -> f64 {
+    if v.is_infinite() {
+        return 0.0;
+    }
+    let x = v / a;
+    match d {
+        1 => erfc(x / SQRT_2),
+        2 => (-0.5 * x * x).exp(),
+        _ => erfc(x / SQRT_2) + (2.0 / PI).sqrt() * x * (-0.5 * x * x).exp(),
+    }
+}
+
+/// The Kullback-Leibler divergence D(p || q) of the normalized `counts` from the probabilities `q`.
+fn kl_divergence(counts: &[f64], q: &[f64]) -> f64 {
+    let total: f64 = counts.iter().sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+
+    counts
+        .iter()
+        .zip(q.iter())
+        .filter(|(&c, _)| c > 0.0)
+        .map(|(c, &q)| {
+            let p = c / total;
+            p * (p / q.max(f64::MIN_POSITIVE)).ln()
+        })
+        .sum()
+}
+
+#[pymethods]
+impl Particles {
+    /// Compute the velocity distribution of the particles and compare it to a
+    /// Maxwell-Boltzmann distribution at the measured temperature.
+    /// See [VelocityDistribution](../analysis/velocity_distribution/struct.VelocityDistribution.html).
+    ///
+    /// # Arguments
+    ///
+    /// * `bins` - The number of bins of the speed and velocity component histograms.
+    /// * `v_max` - The largest speed covered by the histograms.
+    /// Defaults to six times the thermal velocity sqrt(T / m).
+    /// * `dimensions` - The number of velocity components to consider, e.g. 2 for a flat cloud.
+    /// * `bins_h` - The number of bins per axis of the histogram used to estimate the H-function.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// dist = particles.velocity_distribution(dimensions=2)
+    /// print(dist.temperature, dist.kl_divergence_speed(), dist.h_function() - dist.h_function_equilibrium())
+    /// ```
+    ///
+    #[args(bins = "50", v_max = "None", dimensions = "3", bins_h = "20")]
+    pub fn velocity_distribution(
+        &self,
+        bins: usize,
+        v_max: Option<f64>,
+        dimensions: usize,
+        bins_h: usize,
+    ) -> PyResult<VelocityDistribution> {
+        let v_max = match v_max {
+            Some(v_max) => v_max,
+            None => default_v_max(self, dimensions.clamp(1, 3)),
+        };
+        let mut distribution = VelocityDistribution::new(v_max, bins, dimensions, bins_h)?;
+        distribution.add(self);
+        Ok(distribution)
+    }
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass]
+#[derive(Debug, Clone)]
+/// A single observation of the deviation from equilibrium.
+pub struct EquilibriumRecord {
+    /// The simulation time of the observation
+    #[pyo3(get)]
+    pub time: f64,
+    /// The measured temperature
+    #[pyo3(get)]
+    pub temperature: f64,
+    /// The Kullback-Leibler divergence of the speeds from the Maxwell-Boltzmann distribution
+    #[pyo3(get)]
+    pub kl_divergence_speed: f64,
+    /// The Kullback-Leibler divergences of the velocity components
+    #[pyo3(get)]
+    pub kl_divergence_components: Vec<f64>,
+    /// The Boltzmann H-function
+    #[pyo3(get)]
+    pub h_function: f64,
+    /// The H-function of the Maxwell-Boltzmann distribution at the measured temperature
+    #[pyo3(get)]
+    pub h_function_equilibrium: f64,
+}
+
+#[derive(Debug)]
+struct EquilibriumRecords {
+    bins: usize,
+    dimensions: usize,
+    bins_h: usize,
+    v_max: Option<f64>,
+    accumulated: Option<VelocityDistribution>,
+    records: Vec<EquilibriumRecord>,
+}
+
+impl Record for EquilibriumRecords {
+    fn record(&mut self, particles: &Particles) -> PyResult<()> {
+        // the histogram range is fixed at the first observation, so histograms can be accumulated
+        let dimensions = self.dimensions;
+        let v_max = *self
+            .v_max
+            .get_or_insert_with(|| default_v_max(particles, dimensions));
+
+        let mut snapshot = VelocityDistribution::new(v_max, self.bins, self.dimensions, self.bins_h)?;
+        snapshot.add(particles);
+
+        self.records.push(EquilibriumRecord {
+            time: particles.state.time,
+            temperature: snapshot.temperature(),
+            kl_divergence_speed: snapshot.kl_divergence_speed(),
+            kl_divergence_components: (0..self.dimensions)
+                .map(|axis| snapshot.kl_divergence_component(axis))
+                .collect::<PyResult<_>>()?,
+            h_function: snapshot.h_function(),
+            h_function_equilibrium: snapshot.h_function_equilibrium(),
+        });
+
+        match &mut self.accumulated {
+            Some(accumulated) => accumulated.add(particles),
+            None => self.accumulated = Some(snapshot),
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.records.clear();
+        self.accumulated = None;
+    }
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(extends=ObserverBase)]
+#[derive(Debug, Clone)]
+/// Records the deviation of the velocity distribution from equilibrium every `interval` steps,
+/// and accumulates the velocity distribution over all observations, when added to a simulation with
+/// [add_observer](../../particles/struct.Particles.html#method.add_observer).
+/// See [velocity_distribution](../../particles/struct.Particles.html#method.velocity_distribution)
+/// for the arguments.
+pub struct VelocityDistributionObserver {
+    recorder: Arc<Mutex<Recorder<EquilibriumRecords>>>,
+}
+
+#[pymethods]
+impl VelocityDistributionObserver {
+    #[new]
+    #[args(interval = "1", bins = "50", v_max = "None", dimensions = "3", bins_h = "20")]
+    pub fn new(
+        interval: usize,
+        bins: usize,
+        v_max: Option<f64>,
+        dimensions: usize,
+        bins_h: usize,
+    ) -> PyResult<(Self, ObserverBase)> {
+        // validate the arguments early rather than on the first observation
+        VelocityDistribution::new(v_max.unwrap_or(1.0), bins, dimensions, bins_h)?;
+
+        let records = EquilibriumRecords {
+            bins,
+            dimensions,
+            bins_h,
+            v_max,
+            accumulated: None,
+            records: Vec::new(),
+        };
+        let (recorder, base) = ObserverBase::share(Recorder::new(interval, records)?);
+        Ok((Self { recorder }, base))
+    }
+
+    /// The recorded deviations from equilibrium, one per observation.
+    #[getter]
+    pub fn records(&self) -> PyResult<Vec<EquilibriumRecord>> {
+        Ok(lock(&self.recorder)?.data.records.clone())
+    }
+
+    /// The velocity distribution accumulated over all observations, if any.
+    #[getter]
+    pub fn distribution(&self) -> PyResult<Option<VelocityDistribution>> {
+        Ok(lock(&self.recorder)?.data.accumulated.clone())
+    }
+}
+
+recorder_methods!(VelocityDistributionObserver);
@@ -0,0 +1,144 @@
+//! Running a simulation in a background thread, which Python can poll while it keeps running,
+//! see [Particles.run_background](../particles/struct.Particles.html#method.run_background).
+
+use crate::particles::Particles;
+use crate::simulation::Simulation;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+/// A simulation run in a background thread, returned by
+/// [Particles.run_background](../particles/struct.Particles.html#method.run_background).
+#[pyclass(module = "particles")]
+#[derive(Debug)]
+pub struct BackgroundRun {
+    /// The number of steps to perform
+    #[pyo3(get)]
+    n: usize,
+    progress: Arc<AtomicUsize>,
+    cancelled: Arc<AtomicBool>,
+    thread: Option<JoinHandle<PyResult<()>>>,
+}
+
+#[pymethods]
+impl BackgroundRun {
+    /// The number of steps completed so far.
+    #[getter]
+    pub fn steps(&self) -> usize {
+        self.progress.load(Ordering::Relaxed)
+    }
+
+    /// Whether the run has finished, and the simulation has been updated.
+    pub fn done(&self) -> bool {
+        self.thread.as_ref().map_or(true, |thread| thread.is_finished())
+    }
+
+    /// Stop the run after the current step. The simulation is left in the state of the
+    /// last completed step, once the run is [done](#method.done).
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Wait until the run has finished, and raise the error of the run, if any.
+    /// Ctrl-C cancels the run and raises `KeyboardInterrupt`.
+    pub fn wait(&mut self, py: Python<'_>) -> PyResult<()> {
+        while !self.done() {
+            py.allow_threads(|| std::thread::sleep(Duration::from_millis(10)));
+            if let Err(e) = py.check_signals() {
+                self.cancel();
+                return Err(e);
+            }
+        }
+
+        match self.thread.take() {
+            None => Ok(()),
+            Some(thread) => thread
+                .join()
+                .map_err(|_| PyRuntimeError::new_err("the background run panicked"))?,
+        }
+    }
+}
+
+#[pymethods]
+impl Particles {
+    /// Like [run](#method.run), but run the simulation in a background thread and return
+    /// immediately with a `BackgroundRun`, which can be polled for its progress, cancelled
+    /// and waited for.
+    ///
+    /// The run works on a copy of the simulation, which replaces the state of this simulation
+    /// when the run is done, so the simulation should not be modified in the meantime.
+    /// Observers are notified from the background thread and can stop the run early,
+    /// but Python callables cannot be used as observers.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// run = particles.run_background(n=10_000, h=0.01)
+    /// while not run.done():
+    ///     print(f"{run.steps} / {run.n}")
+    ///     time.sleep(1.0)
+    /// run.wait()
+    /// ```
+    ///
+    pub fn run_background(slf: PyRef<'_, Self>, n: usize, h: f64) -> PyResult<BackgroundRun> {
+        if !slf.state.callbacks.is_empty() {
+            return Err(PyValueError::new_err(
+                "Python callables cannot observe a background run, use run instead",
+            ));
+        }
+
+        let mut particles = slf.clone();
+        let target: Py<Self> = slf.into();
+        let progress = Arc::new(AtomicUsize::new(0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let (progress, cancelled) = (progress.clone(), cancelled.clone());
+            std::thread::spawn(move || {
+                let result = (|| {
+                    // observe the initial state, like run
+                    if particles.state.initial_observation_due() {
+                        particles.state.observed_step = Some(0);
+                        if particles.notify_observers()? {
+                            return Ok(());
+                        }
+                    }
+                    for _ in 0..n {
+                        if cancelled.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        // the GIL is only acquired to call the potential
+                        particles.update_yoshida(h)?;
+                        let stop = particles.notify_observers()?;
+                        particles.state.observed_step = Some(particles.state.step);
+                        progress.fetch_add(1, Ordering::Relaxed);
+                        if stop {
+                            break;
+                        }
+                    }
+                    Ok(())
+                })();
+
+                // hand the state of the last completed step back to the simulation
+                let update = Python::with_gil(|py| -> PyResult<()> {
+                    let mut target = target.as_ref(py).try_borrow_mut()?;
+                    target.replace_state(particles);
+                    Ok(())
+                });
+                result.and(update)
+            })
+        };
+
+        Ok(BackgroundRun {
+            n,
+            progress,
+            cancelled,
+            thread: Some(thread),
+        })
+    }
+}
@@ -0,0 +1,61 @@
+//! A spatial grid (cell list) for finding all particles within some distance of each other.
+
+use crate::vec3::Vec3;
+use std::collections::HashMap;
+
+/// Sorts particle indices into cubic cells of a fixed size, such that all particles within
+/// a distance of `cell_size` of some point are found in the 27 cells surrounding it.
+/// Only occupied cells are stored, so this works for arbitrarily sparse and spread out clouds.
+#[derive(Debug, Clone)]
+pub struct CellList {
+    cell_size: f64,
+    cells: HashMap<[i64; 3], Vec<usize>>,
+}
+
+impl CellList {
+    /// Create a new cell list with the given cell size from the positions.
+    /// The indices in the cell list refer to the positions.
+    pub fn new(positions: &[Vec3], cell_size: f64) -> Self {
+        let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+        for (i, p) in positions.iter().enumerate() {
+            cells.entry(Self::cell(p, cell_size)).or_default().push(i);
+        }
+
+        Self { cell_size, cells }
+    }
+
+    fn cell(p: &Vec3, cell_size: f64) -> [i64; 3] {
+        [
+            (p.x / cell_size).floor() as i64,
+            (p.y / cell_size).floor() as i64,
+            (p.z / cell_size).floor() as i64,
+        ]
+    }
+
+    /// Returns the indices of all particles in the 27 cells surrounding `p`.
+    /// This includes all particles within a distance of `cell_size` of `p`, but possibly more.
+    pub fn candidates(&self, p: &Vec3) -> impl Iterator<Item = usize> + '_ {
+        let [x, y, z] = Self::cell(p, self.cell_size);
+        let offsets = -1..=1;
+
+        offsets
+            .clone()
+            .flat_map(move |dx| {
+                offsets.clone().flat_map(move |dy| {
+                    (-1..=1).map(move |dz| [x + dx, y + dy, z + dz])
+                })
+            })
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+
+    /// Returns the indices of all particles within a distance of `radius` of `p`.
+    /// `radius` must not exceed the cell size.
+    pub fn within<'a>(&'a self, positions: &'a [Vec3], p: &'a Vec3, radius: f64) -> impl Iterator<Item = usize> + 'a {
+        debug_assert!(radius <= self.cell_size);
+        let radius_sq = radius * radius;
+        self.candidates(p)
+            .filter(move |&j| (positions[j] - p).abs_sq() <= radius_sq)
+    }
+}
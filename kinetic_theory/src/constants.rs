@@ -13,4 +13,6 @@ pub mod potential {
     /// Potential Strength Constants in arbitrary units
     pub const ATTRACTING: f64 = 1.0;
     pub const REPELLING: f64 = 1.0;
+    /// The magnitude of the pair force is capped to this value
+    pub const CAP: f64 = 3.0;
 }
@@ -0,0 +1,108 @@
+//! The forces and the time integration shared by the simulations of every dimension,
+//! i.e. [Particles](../particles/struct.Particles.html), [Particles2D](../particles_nd/struct.Particles2D.html)
+//! and [Particles1D](../particles_nd/struct.Particles1D.html).
+
+use crate::interaction::pair_force;
+use crate::vector::{Scalar, Vector};
+
+use pyo3::prelude::*;
+use rayon::prelude::*;
+
+/// Perform a single time step of size `h` with the
+/// [Yoshida Leapfrog Algorithm](https://en.wikipedia.org/wiki/Leapfrog_integration#Yoshida_algorithms).
+///
+/// The step is integrated into scratch buffers, which only replace the positions and velocities
+/// once all sub-steps succeeded. If the potential raises an error, e.g. `KeyboardInterrupt`,
+/// the particles are left in the state before the step.
+pub fn update_yoshida<V: Vector>(
+    positions: &mut Vec<V>,
+    velocities: &mut Vec<V>,
+    potential: Option<&PyObject>,
+    h: f64,
+) -> PyResult<()> {
+    use crate::constants::yoshida::{C14, C23, D13, D2};
+
+    let (mut x, mut v) = (positions.clone(), velocities.clone());
+
+    update_positions(&mut x, &v, C14, h);
+    update_velocities(&x, &mut v, potential, D13, h)?;
+    update_positions(&mut x, &v, C23, h);
+    update_velocities(&x, &mut v, potential, D2, h)?;
+    update_positions(&mut x, &v, C23, h);
+    update_velocities(&x, &mut v, potential, D13, h)?;
+    update_positions(&mut x, &v, C14, h);
+
+    *positions = x;
+    *velocities = v;
+
+    Ok(())
+}
+
+// the coefficients and the time step are rounded to the precision of the vector type once per sub-step
+fn update_positions<V: Vector>(positions: &mut Vec<V>, velocities: &[V], c: f64, h: f64) {
+    let (c, h) = (V::Scalar::from_f64(c), V::Scalar::from_f64(h));
+    *positions = positions
+        .iter()
+        .zip(velocities.iter())
+        .map(|(&p, &v)| p + v * c * h)
+        .collect();
+}
+
+fn update_velocities<V: Vector>(
+    positions: &[V],
+    velocities: &mut Vec<V>,
+    potential: Option<&PyObject>,
+    d: f64,
+    h: f64,
+) -> PyResult<()> {
+    let forces = forces(positions, potential)?;
+    let (d, h) = (V::Scalar::from_f64(d), V::Scalar::from_f64(h));
+    *velocities = velocities
+        .iter()
+        .zip(forces.iter())
+        .map(|(&v, &f)| v + f * d * h)
+        .collect();
+    Ok(())
+}
+
+/// The total force on each particle, from all other particles and the external potential.
+pub fn forces<V: Vector>(positions: &[V], potential: Option<&PyObject>) -> PyResult<Vec<V>> {
+    let potentials: Vec<V> = potentials(positions, potential)?;
+
+    let forces = positions
+        .par_iter()
+        .zip(potentials.par_iter())
+        .map(|(&p1, &pot)| {
+            // regular iter because the thread creation overhead
+            // outweighs its benefit in the inner loop
+            positions
+                .iter()
+                .map(|&p2| pair_force(&(p2 - p1)))
+                .sum::<V>() + pot
+        })
+        .collect();
+
+    Ok(forces)
+}
+
+/// The force of the external potential, a Python function of the position, on each particle.
+pub fn potentials<V: Vector>(positions: &[V], potential: Option<&PyObject>) -> PyResult<Vec<V>> {
+    match potential {
+        None => Ok(vec![V::default(); positions.len()]),
+        Some(pot) => {
+            // acquire the python global interpreter lock
+            let gil = pyo3::Python::acquire_gil();
+            // acquire the respective python instance
+            let python: Python<'_> = gil.python();
+            positions
+                .iter()
+                .map(|&p1| {
+                    // call the potential python function with the position
+                    // if we encounter an error in Python, move the error up
+                    let obj: PyObject = pot.call1(python, (p1.into_py(python),))?;
+                    obj.extract(python)
+                })
+                .collect()
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! The pair interaction between particles: a Lennard-Jones-like force whose magnitude is capped,
+//! see [constants::potential](../constants/potential/index.html).
+
+use crate::constants::potential::{ATTRACTING, CAP, REPELLING};
+use crate::vector::{Scalar, Vector};
+
+/// The magnitude of the pair force at squared distance `r_sq`, in the precision of `r_sq`.
+/// Positive values are attracting, negative values repelling.
+pub fn pair_force_magnitude<S: Scalar>(r_sq: S) -> S {
+    let f = S::from_f64(ATTRACTING) / r_sq.powf(S::from_f64(3.0)) - S::from_f64(REPELLING) / r_sq.powf(S::from_f64(6.0));
+    let cap = S::from_f64(CAP);
+    crate::utils::cap(f, -cap, cap)
+}
+
+/// The force exerted on a particle by another particle at the relative position `r`,
+/// in any dimension and in the precision of the vector type.
+pub fn pair_force<V: Vector>(r: &V) -> V {
+    let r_sq = r.abs_sq();
+
+    // like utils::approx_equal(r_sq, 0.0) in the precision of the vector type
+    if r_sq < V::Scalar::EPSILON * V::Scalar::MIN_POSITIVE {
+        return V::default();
+    }
+
+    r.unit() * pair_force_magnitude(r_sq)
+}
+
+/// The pair potential energy U(r) of two particles at distance `r`, such that the force is
+/// U'(r) in the direction of the other particle and U vanishes at infinity.
+/// Within the regions where the force is capped, the potential is linear.
+pub fn pair_energy(r: f64) -> f64 {
+    // integral of the uncapped force from r to infinity
+    let integral = |r: f64| ATTRACTING / (5.0 * r.powi(5)) - REPELLING / (11.0 * r.powi(11));
+    // the distance at which the force magnitude equals f, in terms of x = r^-6,
+    // where the force is A x - R x^2
+    let distance = |x: f64| x.powf(-1.0 / 6.0);
+
+    // repelling cap at short distances, where A x - R x^2 = -CAP
+    let x_repelling = (ATTRACTING + (ATTRACTING * ATTRACTING + 4.0 * REPELLING * CAP).sqrt()) / (2.0 * REPELLING);
+    let r_repelling = distance(x_repelling);
+
+    // attracting cap between two distances, where A x - R x^2 = CAP, if the force gets strong enough
+    let discriminant = ATTRACTING * ATTRACTING - 4.0 * REPELLING * CAP;
+    let (r_inner, r_outer) = if discriminant > 0.0 {
+        (
+            distance((ATTRACTING + discriminant.sqrt()) / (2.0 * REPELLING)),
+            distance((ATTRACTING - discriminant.sqrt()) / (2.0 * REPELLING)),
+        )
+    } else {
+        (r_repelling, r_repelling)
+    };
+
+    let above_inner = |r: f64| integral(r) - integral(r_inner) + integral(r_outer) + CAP * (r_outer - r_inner);
+
+    let force_integral = if r >= r_outer {
+        integral(r)
+    } else if r >= r_inner {
+        integral(r_outer) + CAP * (r_outer - r)
+    } else if r >= r_repelling {
+        above_inner(r)
+    } else {
+        above_inner(r_repelling) - CAP * (r_repelling - r)
+    };
+
+    -force_integral
+}
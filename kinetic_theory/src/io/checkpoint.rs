@@ -0,0 +1,248 @@
+//! Checkpoints of the full simulation state, so long runs can be restarted after a crash.
+
+use crate::particles::{check_attribute_name, Particles};
+use crate::simulation::State;
+use crate::vec3::Vec3;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyTuple, PyType};
+
+// identifies checkpoint files, followed by the format version
+const MAGIC: &[u8; 8] = b"KTCHKPT\0";
+const VERSION: u32 = 3;
+
+// the pickled state of a simulation: the serialized checkpoint and the external potential,
+// which is pickled by Python itself
+type PickleState<'py> = (&'py PyBytes, Option<PyObject>);
+
+/// The serialized simulation state.
+/// Floats are stored with their exact bit patterns, so a restarted run is bit-identical
+/// to one that was never interrupted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub positions: Vec<Vec3>,
+    pub velocities: Vec<Vec3>,
+    pub masses: Vec<f64>,
+    pub ids: Vec<u64>,
+    pub next_id: u64,
+    pub attributes: BTreeMap<String, Vec<f64>>,
+    pub time: f64,
+    pub step: u64,
+    /// Whether an external potential was set when saving.
+    /// Python callables cannot be serialized, so it has to be given again when loading.
+    pub has_potential: bool,
+}
+
+impl Checkpoint {
+    pub fn new(particles: &Particles) -> Self {
+        Self {
+            positions: particles.state.positions.clone(),
+            velocities: particles.state.velocities.clone(),
+            masses: particles.state.masses.clone(),
+            ids: particles.ids.clone(),
+            next_id: particles.next_id,
+            attributes: particles.attributes.clone(),
+            time: particles.state.time,
+            step: particles.state.step as u64,
+            has_potential: particles.state.potential.is_some(),
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> PyResult<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, self)
+            .map_err(|e| PyValueError::new_err(format!("could not write checkpoint: {}", e)))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> PyResult<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(PyValueError::new_err("not a checkpoint file"));
+        }
+
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != VERSION {
+            return Err(PyValueError::new_err(format!(
+                "unsupported checkpoint version {}, expected {}",
+                version, VERSION
+            )));
+        }
+
+        let checkpoint: Self = bincode::deserialize_from(reader)
+            .map_err(|e| PyValueError::new_err(format!("corrupt checkpoint: {}", e)))?;
+        checkpoint.validate()
+    }
+
+    /// Serialize the checkpoint without the file header, e.g. for pickling.
+    pub fn to_bytes(&self) -> PyResult<Vec<u8>> {
+        bincode::serialize(self)
+            .map_err(|e| PyValueError::new_err(format!("could not serialize simulation state: {}", e)))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> PyResult<Self> {
+        let checkpoint: Self = bincode::deserialize(bytes)
+            .map_err(|e| PyValueError::new_err(format!("corrupt simulation state: {}", e)))?;
+        checkpoint.validate()
+    }
+
+    fn validate(self) -> PyResult<Self> {
+        let n = self.positions.len();
+        if self.velocities.len() != n
+            || self.masses.len() != n
+            || self.ids.len() != n
+            || self.attributes.values().any(|values| values.len() != n)
+        {
+            return Err(PyValueError::new_err(
+                "corrupt checkpoint: inconsistent number of particles",
+            ));
+        }
+        for name in self.attributes.keys() {
+            check_attribute_name(name).map_err(|_| {
+                PyValueError::new_err(format!("corrupt checkpoint: invalid attribute name '{}'", name))
+            })?;
+        }
+        Ok(self)
+    }
+
+    /// Restore a simulation from the checkpoint, with the external potential if one was saved.
+    pub fn restore(self, potential: Option<PyObject>) -> PyResult<Particles> {
+        match (self.has_potential, potential.is_some()) {
+            (true, false) => {
+                return Err(PyTypeError::new_err(
+                    "the checkpoint was saved with an external potential, which cannot be stored. \
+                     Pass the same potential to load",
+                ))
+            }
+            (false, true) => {
+                return Err(PyTypeError::new_err(
+                    "the checkpoint was saved without an external potential",
+                ))
+            }
+            _ => {}
+        }
+
+        Ok(Particles {
+            state: State {
+                positions: self.positions,
+                velocities: self.velocities,
+                masses: self.masses,
+                potential,
+                time: self.time,
+                step: self.step as usize,
+                ..State::default()
+            },
+            ids: self.ids,
+            next_id: self.next_id,
+            attributes: self.attributes,
+            ..Particles::default()
+        })
+    }
+}
+
+#[pymethods]
+impl Particles {
+    /// Save the complete simulation state to a checkpoint file, from which the
+    /// simulation can be restarted with [load](#method.load).
+    /// A restarted simulation continues bit-identically.
+    ///
+    /// The integrator has no state beyond the positions and velocities. The particle IDs and
+    /// attributes are saved as well, observers are not.
+    /// An external potential is a Python callable and cannot be saved, so the checkpoint only
+    /// records that one was set, and it must be passed to [load](#method.load) again.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the checkpoint file. An existing file is overwritten.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// for i in range(10):
+    ///     particles.run(n=500, h=0.01)
+    ///     particles.save("checkpoint.bin")
+    /// ```
+    ///
+    pub fn save(&self, path: &str) -> PyResult<()> {
+        // write to a temporary file first, so a crash while saving keeps the last checkpoint
+        let temporary = format!("{}.tmp", path);
+        Checkpoint::new(self).write(BufWriter::new(File::create(&temporary)?))?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    /// Load a simulation from a checkpoint file written by [save](#method.save).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the checkpoint file.
+    /// * `potential` - The external potential, required if and only if one was set when saving.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles = Particles.load("checkpoint.bin", potential=potential)
+    /// particles.run(n=500, h=0.01)
+    /// ```
+    ///
+    #[staticmethod]
+    #[args(potential = "None")]
+    pub fn load(path: &str, potential: Option<PyObject>) -> PyResult<Particles> {
+        Checkpoint::read(BufReader::new(File::open(path)?))?.restore(potential)
+    }
+
+    // pickle and copy support
+    // observers, including Python callables, are neither pickled nor copied,
+    // since they would otherwise record several simulations
+
+    pub fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<PickleState<'py>> {
+        let bytes = Checkpoint::new(self).to_bytes()?;
+        Ok((PyBytes::new(py, &bytes), self.state.potential.clone()))
+    }
+
+    pub fn __setstate__(&mut self, state: PickleState<'_>) -> PyResult<()> {
+        let (bytes, potential) = state;
+        *self = Checkpoint::from_bytes(bytes.as_bytes())?.restore(potential)?;
+        Ok(())
+    }
+
+    pub fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<(&'py PyType, &'py PyTuple, PickleState<'py>)> {
+        Ok((py.get_type::<Self>(), PyTuple::empty(py), self.__getstate__(py)?))
+    }
+
+    pub fn __copy__(&self) -> Self {
+        let mut copy = self.clone();
+        copy.observers.clear();
+        copy.state.callbacks.clear();
+        copy
+    }
+
+    pub fn __deepcopy__(&self, py: Python<'_>, memo: &PyAny) -> PyResult<Self> {
+        let potential = match &self.state.potential {
+            None => None,
+            Some(potential) => Some(
+                py.import("copy")?
+                    .call1("deepcopy", (potential, memo))?
+                    .into(),
+            ),
+        };
+        let mut copy = self.__copy__();
+        copy.state.potential = potential;
+        Ok(copy)
+    }
+}
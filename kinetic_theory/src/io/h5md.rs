@@ -0,0 +1,412 @@
+//! Trajectories, observables and parameters in the [H5MD](https://www.nongnu.org/h5md/) format,
+//! a layout of HDF5 files for molecular data. Requires the `h5md` feature.
+//!
+//! The file layout is:
+//! ```text
+//! /h5md                        version, author and creator
+//! /particles/all/box           dimension 3, boundary "none", since the simulation is unbounded
+//! /particles/all/position      step, time and value (frames, N, 3)
+//! /particles/all/velocity      step, time and value (frames, N, 3)
+//! /particles/all/mass          (N)
+//! /particles/all/id            (N) the IDs of the particles
+//! /observables/<name>          step, time and value (frames) for the kinetic, interaction
+//!                              and total energy and the temperature
+//! /parameters                  the pair potential constants and any user parameters as attributes
+//! ```
+//! All time-dependent datasets are chunked and extensible, so frames are appended during a run.
+//! Frames are read back with [read_h5md](../../particles/struct.Particles.html#method.read_h5md).
+
+use crate::analysis::energy::{interaction_energy, kinetic_energy, temperature};
+use crate::constants::potential::{ATTRACTING, CAP, REPELLING};
+use crate::io::frame_index;
+use crate::observer::{check_interval, lock, Observer, ObserverBase};
+use crate::particles::Particles;
+use crate::vec3::Vec3;
+use rust_hdf5::{H5Dataset, H5File, H5Group, Hdf5Error};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use pyo3::exceptions::{PyIOError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+
+const OBSERVABLES: [&str; 4] = ["kinetic_energy", "interaction_energy", "total_energy", "temperature"];
+// the number of frames per chunk of the time series
+const SERIES_CHUNK: usize = 64;
+
+fn h5_error(error: Hdf5Error) -> PyErr {
+    PyIOError::new_err(format!("HDF5 error: {}", error))
+}
+
+/// A time-dependent H5MD element: a group with the datasets `step`, `time` and `value`
+struct TimeSeries {
+    step: H5Dataset,
+    time: H5Dataset,
+    value: H5Dataset,
+}
+
+impl TimeSeries {
+    /// Create the element with values of the given shape per frame.
+    fn create(parent: &H5Group, name: &str, shape: &[usize], compression: Option<u32>) -> PyResult<Self> {
+        let group = parent.create_group(name).map_err(h5_error)?;
+
+        let series = |name: &str| {
+            group
+                .new_dataset::<f64>()
+                .shape([0])
+                .chunk(&[SERIES_CHUNK])
+                .max_shape(&[None])
+                .create(name)
+                .map_err(h5_error)
+        };
+        let step = group
+            .new_dataset::<i64>()
+            .shape([0])
+            .chunk(&[SERIES_CHUNK])
+            .max_shape(&[None])
+            .create("step")
+            .map_err(h5_error)?;
+        let time = series("time")?;
+
+        let value = if shape.is_empty() {
+            series("value")?
+        } else {
+            let dims: Vec<usize> = std::iter::once(0).chain(shape.iter().copied()).collect();
+            let chunk: Vec<usize> = std::iter::once(1).chain(shape.iter().copied()).collect();
+            let max_shape: Vec<Option<usize>> = std::iter::once(None).chain(shape.iter().map(|&d| Some(d))).collect();
+            let mut builder = group
+                .new_dataset::<f64>()
+                .shape(dims)
+                .chunk(&chunk)
+                .max_shape(&max_shape);
+            if let Some(level) = compression {
+                builder = builder.deflate(level);
+            }
+            builder.create("value").map_err(h5_error)?
+        };
+
+        Ok(Self { step, time, value })
+    }
+
+    fn append(&self, particles: &Particles, values: &[f64]) -> PyResult<()> {
+        self.step.append(&[particles.state.step as i64]).map_err(h5_error)?;
+        self.time.append(&[particles.state.time]).map_err(h5_error)?;
+        self.value.append(values).map_err(h5_error)
+    }
+}
+
+/// The open datasets of a file, created with the first frame
+struct Datasets {
+    // the particles of the file, which cannot change between frames
+    ids: Vec<u64>,
+    position: TimeSeries,
+    velocity: TimeSeries,
+    observables: Vec<TimeSeries>,
+}
+
+struct H5mdRecorder {
+    interval: usize,
+    compression: Option<u32>,
+    file: Option<H5File>,
+    datasets: Option<Datasets>,
+    frames: usize,
+}
+
+// the HDF5 handles do not implement Debug
+impl std::fmt::Debug for H5mdRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("H5mdRecorder")
+            .field("interval", &self.interval)
+            .field("compression", &self.compression)
+            .field("open", &self.file.is_some())
+            .field("frames", &self.frames)
+            .finish()
+    }
+}
+
+impl H5mdRecorder {
+    fn create_datasets(file: &H5File, particles: &Particles, compression: Option<u32>) -> PyResult<Datasets> {
+        let n = particles.state.positions.len();
+        let all = file.create_group("particles").map_err(h5_error)?.create_group("all").map_err(h5_error)?;
+
+        let simulation_box = all.create_group("box").map_err(h5_error)?;
+        simulation_box.set_attr_numeric("dimension", &3i32).map_err(h5_error)?;
+        simulation_box
+            .set_attr_string_array("boundary", &["none", "none", "none"])
+            .map_err(h5_error)?;
+
+        // the masses and IDs do not change, so they are time-independent elements
+        all.new_dataset::<f64>()
+            .shape([n])
+            .create("mass")
+            .map_err(h5_error)?
+            .write_raw(&particles.state.masses)
+            .map_err(h5_error)?;
+        let ids: Vec<i64> = particles.ids.iter().map(|&id| id as i64).collect();
+        all.new_dataset::<i64>()
+            .shape([n])
+            .create("id")
+            .map_err(h5_error)?
+            .write_raw(&ids)
+            .map_err(h5_error)?;
+
+        let observables = file.create_group("observables").map_err(h5_error)?;
+        Ok(Datasets {
+            ids: particles.ids.clone(),
+            position: TimeSeries::create(&all, "position", &[n, 3], compression)?,
+            velocity: TimeSeries::create(&all, "velocity", &[n, 3], compression)?,
+            observables: OBSERVABLES
+                .iter()
+                .map(|name| TimeSeries::create(&observables, name, &[], None))
+                .collect::<PyResult<_>>()?,
+        })
+    }
+
+    fn write(&mut self, particles: &Particles) -> PyResult<()> {
+        let file = self
+            .file
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("the H5MD file is closed"))?;
+        if self.datasets.is_none() {
+            self.datasets = Some(Self::create_datasets(file, particles, self.compression)?);
+        }
+        let datasets = self.datasets.as_ref().expect("the datasets were just created");
+
+        if particles.ids != datasets.ids {
+            return Err(PyValueError::new_err(format!(
+                "the H5MD file holds a fixed set of {} particles, but particles were added or removed",
+                datasets.ids.len()
+            )));
+        }
+
+        let components = |vectors: &[Vec3]| -> Vec<f64> {
+            vectors.iter().flat_map(|v| [v.x, v.y, v.z]).collect()
+        };
+        datasets.position.append(particles, &components(&particles.state.positions))?;
+        datasets.velocity.append(particles, &components(&particles.state.velocities))?;
+
+        let kinetic = kinetic_energy(&particles.state.masses, &particles.state.velocities);
+        let interaction = interaction_energy(&particles.state.positions);
+        let values = [
+            kinetic,
+            interaction,
+            kinetic + interaction,
+            temperature(&particles.state.masses, &particles.state.velocities),
+        ];
+        for (series, value) in datasets.observables.iter().zip(values.iter()) {
+            series.append(particles, &[*value])?;
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+impl Observer for H5mdRecorder {
+    fn interval(&self) -> usize {
+        self.interval
+    }
+
+    fn observe(&mut self, particles: &Particles) -> PyResult<()> {
+        self.write(particles)
+    }
+}
+
+fn write_parameters(file: &H5File, parameters: Option<HashMap<String, &PyAny>>) -> PyResult<()> {
+    let group = file.create_group("parameters").map_err(h5_error)?;
+    group.set_attr_numeric("attracting", &ATTRACTING).map_err(h5_error)?;
+    group.set_attr_numeric("repelling", &REPELLING).map_err(h5_error)?;
+    group.set_attr_numeric("force_cap", &CAP).map_err(h5_error)?;
+
+    for (name, value) in parameters.unwrap_or_default() {
+        // bool is a subclass of int in Python, so it is stored as an integer
+        if let Ok(value) = value.extract::<i64>() {
+            group.set_attr_numeric(&name, &value).map_err(h5_error)?;
+        } else if let Ok(value) = value.extract::<f64>() {
+            group.set_attr_numeric(&name, &value).map_err(h5_error)?;
+        } else if let Ok(value) = value.extract::<&str>() {
+            group.set_attr_string(&name, value).map_err(h5_error)?;
+        } else if let Ok(values) = value.extract::<Vec<f64>>() {
+            group.set_attr_array_numeric(&name, &values).map_err(h5_error)?;
+        } else {
+            return Err(PyTypeError::new_err(format!(
+                "parameter '{}' must be a number, a string or a list of numbers",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(extends=ObserverBase)]
+#[derive(Debug, Clone)]
+/// Writes a simulation to an [H5MD file](index.html), either on demand with [write](#method.write)
+/// or every `interval` steps when added to a simulation with
+/// [add_observer](../../particles/struct.Particles.html#method.add_observer).
+/// Each frame holds the positions and velocities, as well as the energies and the temperature.
+///
+/// The file is complete once the writer is closed, or when it is garbage collected.
+/// Requires the `h5md` feature.
+///
+/// # Arguments
+///
+/// * `path` - The path of the H5MD file. An existing file is overwritten.
+/// * `interval` - The number of steps between two frames written during a run.
+/// * `parameters` - A dictionary of simulation parameters, stored as attributes of `/parameters`.
+/// Values may be numbers, strings or lists of numbers.
+/// * `author` - The author of the simulation.
+/// * `compression` - If given, compress the positions and velocities with this deflate level from 0 to 9.
+///
+/// # Examples
+///
+/// Python:
+/// ```python
+/// writer = H5mdWriter("run.h5", interval=10, parameters={"h": 0.01, "n": 10000})
+/// particles.add_observer(writer)
+/// particles.run(n=10000, h=0.01)
+/// writer.close()
+/// ```
+///
+pub struct H5mdWriter {
+    recorder: Arc<Mutex<H5mdRecorder>>,
+}
+
+#[pymethods]
+impl H5mdWriter {
+    #[new]
+    #[args(interval = "1", parameters = "None", author = "\"unknown\"", compression = "None")]
+    pub fn new(
+        path: &str,
+        interval: usize,
+        parameters: Option<HashMap<String, &PyAny>>,
+        author: &str,
+        compression: Option<u32>,
+    ) -> PyResult<(Self, ObserverBase)> {
+        if let Some(level) = compression {
+            if level > 9 {
+                return Err(PyValueError::new_err("the compression level must be between 0 and 9"));
+            }
+        }
+        let interval = check_interval(interval)?;
+
+        let file = H5File::create(path).map_err(h5_error)?;
+        let h5md = file.create_group("h5md").map_err(h5_error)?;
+        h5md.set_attr_array_numeric("version", &[1i32, 1]).map_err(h5_error)?;
+        h5md.create_group("author")
+            .map_err(h5_error)?
+            .set_attr_string("name", author)
+            .map_err(h5_error)?;
+        let creator = h5md.create_group("creator").map_err(h5_error)?;
+        creator.set_attr_string("name", env!("CARGO_PKG_NAME")).map_err(h5_error)?;
+        creator.set_attr_string("version", env!("CARGO_PKG_VERSION")).map_err(h5_error)?;
+
+        write_parameters(&file, parameters)?;
+
+        let (recorder, base) = ObserverBase::share(H5mdRecorder {
+            interval,
+            compression,
+            file: Some(file),
+            datasets: None,
+            frames: 0,
+        });
+        Ok((Self { recorder }, base))
+    }
+
+    /// The number of frames written by this writer.
+    #[getter]
+    pub fn frames(&self) -> PyResult<usize> {
+        Ok(lock(&self.recorder)?.frames)
+    }
+
+    /// Write the current state of a simulation as the next frame.
+    pub fn write(&self, particles: &Particles) -> PyResult<()> {
+        lock(&self.recorder)?.write(particles)
+    }
+
+    /// Finish and close the H5MD file. Writing further frames raises an error.
+    pub fn close(&self) -> PyResult<()> {
+        let mut recorder = lock(&self.recorder)?;
+        // the datasets must be released before the file is finalized
+        recorder.datasets = None;
+        if let Some(file) = recorder.file.take() {
+            file.close().map_err(h5_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read a frame of the time-dependent element at `path`, e.g. `particles/all/position`,
+/// with `count` values per frame, and return its step, time and values.
+fn read_series(file: &H5File, path: &str, frame: isize, count: usize) -> PyResult<(usize, f64, Vec<f64>)> {
+    let dataset = |name: &str| file.dataset(&format!("{}/{}", path, name)).map_err(h5_error);
+    let value = dataset("value")?;
+    let shape = value.shape();
+    let frames = shape.first().copied().unwrap_or(0);
+    if shape.iter().skip(1).product::<usize>() != count {
+        return Err(PyValueError::new_err(format!("unexpected shape {:?} of '{}/value'", shape, path)));
+    }
+    let index = frame_index(frame, frames)?;
+
+    let step = dataset("step")?.read_slice::<i64>(&[index], &[1]).map_err(h5_error)?;
+    let time = dataset("time")?.read_slice::<f64>(&[index], &[1]).map_err(h5_error)?;
+    let mut starts = vec![0; shape.len()];
+    starts[0] = index;
+    let mut counts = shape.clone();
+    counts[0] = 1;
+    let values = value.read_slice::<f64>(&starts, &counts).map_err(h5_error)?;
+    Ok((step[0] as usize, time[0], values))
+}
+
+#[pymethods]
+impl Particles {
+    /// Read a frame of an H5MD file, e.g. as written by an [H5mdWriter](struct.H5mdWriter.html).
+    /// The positions, velocities, masses and IDs are read from `/particles/all`, the simulation
+    /// time and step from the positions. Requires the `h5md` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the H5MD file.
+    /// * `frame` - The index of the frame, negative indices count from the end. Defaults to the last frame.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles = Particles.read_h5md("run.h5", frame=10)
+    /// ```
+    ///
+    #[staticmethod]
+    #[args(frame = "-1")]
+    pub fn read_h5md(path: &str, frame: isize) -> PyResult<Particles> {
+        let file = H5File::open(path).map_err(h5_error)?;
+        let root = file.root_group();
+        let has_attribute = |group: &str, name: &str| -> PyResult<bool> {
+            let names = root.group(group).map_err(h5_error)?.attr_names().map_err(h5_error)?;
+            Ok(names.iter().any(|n| n == name))
+        };
+        if !has_attribute("h5md", "version")? {
+            return Err(PyValueError::new_err(format!("'{}' is not an H5MD file", path)));
+        }
+        if !has_attribute("particles/all/box", "dimension")? {
+            return Err(PyValueError::new_err("the H5MD file has no simulation box"));
+        }
+
+        let masses: Vec<f64> = file.dataset("particles/all/mass").and_then(|d| d.read_raw()).map_err(h5_error)?;
+        let ids: Vec<i64> = file.dataset("particles/all/id").and_then(|d| d.read_raw()).map_err(h5_error)?;
+        let n = masses.len();
+        let (step, time, positions) = read_series(&file, "particles/all/position", frame, 3 * n)?;
+        let (_, _, velocities) = read_series(&file, "particles/all/velocity", frame, 3 * n)?;
+
+        let vectors = |values: &[f64]| -> Vec<Vec3> {
+            values.chunks_exact(3).map(|v| Vec3::new(v[0], v[1], v[2])).collect()
+        };
+        let mut particles = Particles::default();
+        for ((position, velocity), mass) in vectors(&positions).into_iter().zip(vectors(&velocities)).zip(masses) {
+            particles.particle(position, velocity, mass);
+        }
+        particles.set_ids(ids.into_iter().map(|id| id as u64).collect())?;
+        particles.state.step = step;
+        particles.state.time = time;
+        Ok(particles)
+    }
+}
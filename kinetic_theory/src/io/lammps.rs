@@ -0,0 +1,620 @@
+//! [LAMMPS](https://lammps.org) data files and custom dump trajectories,
+//! e.g. to continue a state prepared with LAMMPS or to cross-check results.
+//!
+//! The simulation uses the same reduced units as LAMMPS' `units lj`.
+//! Particles are not distinguished other than by their mass, so LAMMPS atom types map
+//! to distinct masses: when writing, each distinct mass becomes an atom type, in ascending order.
+//! Only orthogonal boxes are supported. Since the simulation is unbounded, positions are
+//! unwrapped with the image flags when reading, and written unwrapped with zero image flags.
+//! LAMMPS atom IDs start at 1, so they are the particle IDs plus one.
+
+use crate::io::frame_index;
+use crate::observer::{check_interval, lock, Observer, ObserverBase};
+use crate::particles::Particles;
+use crate::vec3::Vec3;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+type Bounds = [(f64, f64); 3];
+
+fn invalid(message: String) -> PyErr {
+    PyValueError::new_err(message)
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> PyResult<T> {
+    value
+        .parse()
+        .map_err(|_| invalid(format!("invalid value '{}'", value)))
+}
+
+/// Assign atom types to the particles by their mass.
+/// Returns the mass of each type and the type of each particle, with types starting at 1.
+pub fn atom_types(masses: &[f64]) -> (Vec<f64>, Vec<usize>) {
+    let mut type_masses: Vec<f64> = masses.to_vec();
+    type_masses.sort_by(|a, b| a.total_cmp(b));
+    type_masses.dedup_by(|a, b| a.to_bits() == b.to_bits());
+
+    let types = masses
+        .iter()
+        .map(|m| type_masses.partition_point(|t| t.total_cmp(m).is_lt()) + 1)
+        .collect();
+
+    (type_masses, types)
+}
+
+/// Set the particle IDs from the LAMMPS atom IDs, if they are all positive.
+fn set_atom_ids<I: Iterator<Item = i64>>(particles: &mut Particles, atom_ids: I) -> PyResult<()> {
+    let ids: Vec<i64> = atom_ids.collect();
+    if ids.iter().all(|&id| id >= 1) {
+        particles.set_ids(ids.iter().map(|&id| (id - 1) as u64).collect())?;
+    }
+    Ok(())
+}
+
+/// The box enclosing all particles, with some margin so no particle lies on its boundary.
+fn bounding_box(positions: &[Vec3]) -> Bounds {
+    let mut bounds = [(f64::INFINITY, f64::NEG_INFINITY); 3];
+    for p in positions {
+        for (b, x) in bounds.iter_mut().zip([p.x, p.y, p.z].iter()) {
+            *b = (b.0.min(*x), b.1.max(*x));
+        }
+    }
+    for b in bounds.iter_mut() {
+        if b.0 > b.1 {
+            *b = (-0.5, 0.5);
+        }
+        let margin = 0.01 * (b.1 - b.0) + 0.5;
+        *b = (b.0 - margin, b.1 + margin);
+    }
+    bounds
+}
+
+/// Write a LAMMPS data file with `atom_style atomic`.
+pub fn write_data<W: Write>(writer: &mut W, particles: &Particles, bounds: Bounds) -> PyResult<()> {
+    let (type_masses, types) = atom_types(&particles.state.masses);
+
+    writeln!(writer, "LAMMPS data file written by kinetic_theory, time = {:?}, step = {}", particles.state.time, particles.state.step)?;
+    writeln!(writer)?;
+    writeln!(writer, "{} atoms", particles.state.positions.len())?;
+    writeln!(writer, "{} atom types", type_masses.len().max(1))?;
+    writeln!(writer)?;
+    for (b, axis) in bounds.iter().zip(["x", "y", "z"].iter()) {
+        writeln!(writer, "{:?} {:?} {}lo {}hi", b.0, b.1, axis, axis)?;
+    }
+
+    if !type_masses.is_empty() {
+        writeln!(writer, "\nMasses\n")?;
+        for (t, m) in type_masses.iter().enumerate() {
+            writeln!(writer, "{} {:?}", t + 1, m)?;
+        }
+    }
+
+    writeln!(writer, "\nAtoms # atomic\n")?;
+    for ((x, t), id) in particles.state.positions.iter().zip(types.iter()).zip(particles.ids.iter()) {
+        writeln!(writer, "{} {} {:?} {:?} {:?} 0 0 0", id + 1, t, x.x, x.y, x.z)?;
+    }
+
+    writeln!(writer, "\nVelocities\n")?;
+    for (v, id) in particles.state.velocities.iter().zip(particles.ids.iter()) {
+        writeln!(writer, "{} {:?} {:?} {:?}", id + 1, v.x, v.y, v.z)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// The columns of the atom type and position in the `Atoms` section of a data file.
+fn atom_style_columns(style: &str) -> PyResult<(usize, usize, usize)> {
+    // (type, x, number of columns without image flags)
+    match style {
+        "atomic" => Ok((1, 2, 5)),
+        "charge" => Ok((1, 3, 6)),
+        "bond" | "angle" | "molecular" => Ok((2, 3, 6)),
+        "full" => Ok((2, 4, 7)),
+        _ => Err(invalid(format!(
+            "unsupported atom style '{}', expected atomic, charge, bond, angle, molecular or full",
+            style
+        ))),
+    }
+}
+
+/// Read a LAMMPS data file. Particles are ordered by their atom ID.
+pub fn read_data<R: BufRead>(reader: R, atom_style: Option<&str>) -> PyResult<Particles> {
+    let mut bounds: Bounds = [(0.0, 0.0); 3];
+    let mut sections: HashMap<String, Vec<Vec<String>>> = HashMap::new();
+    let mut style_hint: Option<String> = None;
+    let mut section: Option<String> = None;
+
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        // the first line is always a comment
+        if n == 0 {
+            continue;
+        }
+
+        let (content, comment) = match line.find('#') {
+            Some(i) => (&line[..i], Some(line[i + 1..].trim())),
+            None => (line.as_str(), None),
+        };
+        let words: Vec<&str> = content.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+
+        // section names are words, while all other lines start with a number,
+        // which may also be e.g. `nan` or `inf`
+        if words[0].parse::<f64>().is_err() {
+            let name = words.join(" ");
+            if name == "Atoms" {
+                style_hint = comment.filter(|c| !c.is_empty()).map(|c| c.to_string());
+            }
+            sections.insert(name.clone(), Vec::new());
+            section = Some(name);
+            continue;
+        }
+
+        match &section {
+            Some(name) => sections
+                .get_mut(name)
+                .expect("the current section exists")
+                .push(words.iter().map(|w| w.to_string()).collect()),
+            None => {
+                // header keywords
+                let keyword = words[words.len().saturating_sub(2)..].join(" ");
+                match keyword.as_str() {
+                    "xlo xhi" => bounds[0] = (parse(words[0])?, parse(words[1])?),
+                    "ylo yhi" => bounds[1] = (parse(words[0])?, parse(words[1])?),
+                    "zlo zhi" => bounds[2] = (parse(words[0])?, parse(words[1])?),
+                    _ if words.last() == Some(&"yz") => {
+                        return Err(invalid("triclinic boxes are not supported".to_string()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let style = atom_style
+        .map(|s| s.to_string())
+        .or(style_hint)
+        .unwrap_or_else(|| "atomic".to_string());
+    let (type_column, x_column, columns) = atom_style_columns(&style)?;
+
+    let mut type_masses: HashMap<i64, f64> = HashMap::new();
+    for row in sections.get("Masses").map(|rows| rows.as_slice()).unwrap_or(&[]) {
+        if row.len() < 2 {
+            return Err(invalid(format!("invalid mass '{}'", row.join(" "))));
+        }
+        type_masses.insert(parse(&row[0])?, parse(&row[1])?);
+    }
+
+    let mut velocities: HashMap<i64, Vec3> = HashMap::new();
+    for row in sections.get("Velocities").map(|rows| rows.as_slice()).unwrap_or(&[]) {
+        if row.len() < 4 {
+            return Err(invalid(format!("invalid velocity '{}'", row.join(" "))));
+        }
+        velocities.insert(parse(&row[0])?, Vec3::new(parse(&row[1])?, parse(&row[2])?, parse(&row[3])?));
+    }
+
+    let atoms = sections
+        .get("Atoms")
+        .ok_or_else(|| invalid("the data file has no Atoms section".to_string()))?;
+    let lengths = [bounds[0].1 - bounds[0].0, bounds[1].1 - bounds[1].0, bounds[2].1 - bounds[2].0];
+
+    let mut rows = Vec::with_capacity(atoms.len());
+    for row in atoms {
+        if row.len() < columns {
+            return Err(invalid(format!(
+                "expected {} columns for atom style {} in '{}'",
+                columns,
+                style,
+                row.join(" ")
+            )));
+        }
+        let id: i64 = parse(&row[0])?;
+        let atom_type: i64 = parse(&row[type_column])?;
+        let mass = *type_masses
+            .get(&atom_type)
+            .ok_or_else(|| invalid(format!("no mass given for atom type {}", atom_type)))?;
+
+        let mut x = [0.0; 3];
+        for (k, value) in x.iter_mut().enumerate() {
+            *value = parse(&row[x_column + k])?;
+            if row.len() >= columns + 3 {
+                let image: i64 = parse(&row[columns + k])?;
+                *value += image as f64 * lengths[k];
+            }
+        }
+
+        let v = velocities.get(&id).copied().unwrap_or_default();
+        rows.push((id, Vec3::new(x[0], x[1], x[2]), v, mass));
+    }
+
+    rows.sort_by_key(|&(id, ..)| id);
+    let mut particles = Particles::default();
+    for &(_, x, v, m) in rows.iter() {
+        particles.particle(x, v, m);
+    }
+    set_atom_ids(&mut particles, rows.iter().map(|row| row.0))?;
+    Ok(particles)
+}
+
+/// Write a frame of a custom dump with the columns `id type x y z vx vy vz mass`.
+pub fn write_dump_frame<W: Write>(writer: &mut W, particles: &Particles) -> PyResult<()> {
+    let (_, types) = atom_types(&particles.state.masses);
+    let bounds = bounding_box(&particles.state.positions);
+
+    writeln!(writer, "ITEM: TIME\n{:?}", particles.state.time)?;
+    writeln!(writer, "ITEM: TIMESTEP\n{}", particles.state.step)?;
+    writeln!(writer, "ITEM: NUMBER OF ATOMS\n{}", particles.state.positions.len())?;
+    writeln!(writer, "ITEM: BOX BOUNDS ff ff ff")?;
+    for b in bounds.iter() {
+        writeln!(writer, "{:?} {:?}", b.0, b.1)?;
+    }
+    writeln!(writer, "ITEM: ATOMS id type x y z vx vy vz mass")?;
+    for ((((x, v), m), t), id) in particles
+        .state.positions
+        .iter()
+        .zip(particles.state.velocities.iter())
+        .zip(particles.state.masses.iter())
+        .zip(types.iter())
+        .zip(particles.ids.iter())
+    {
+        writeln!(
+            writer,
+            "{} {} {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+            id + 1, t, x.x, x.y, x.z, v.x, v.y, v.z, m
+        )?;
+    }
+    Ok(())
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> PyResult<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+/// Returns the byte offsets of all frames in a dump file.
+/// A frame starts with its `ITEM: TIME` or `ITEM: TIMESTEP` line.
+pub fn dump_frame_offsets<R: BufRead + Seek>(reader: &mut R) -> PyResult<Vec<u64>> {
+    let mut offsets = Vec::new();
+    // whether the TIME item of the current frame was read, but not yet its TIMESTEP item
+    let mut after_time = false;
+    loop {
+        let offset = reader.stream_position()?;
+        let line = match read_line(reader)? {
+            Some(line) => line,
+            None => break,
+        };
+        if line == "ITEM: TIME" {
+            offsets.push(offset);
+            after_time = true;
+        } else if line == "ITEM: TIMESTEP" {
+            if !after_time {
+                offsets.push(offset);
+            }
+            after_time = false;
+        }
+    }
+    Ok(offsets)
+}
+
+/// Read the dump frame at the current position of the reader. Particles are ordered by their atom ID.
+/// Masses are read from a `mass` column, or else from the masses of the atom types, or default to one.
+/// Unknown items, e.g. `ITEM: UNITS`, are skipped.
+pub fn read_dump_frame<R: BufRead>(reader: &mut R, type_masses: &HashMap<i64, f64>) -> PyResult<Particles> {
+    let incomplete = || invalid("the dump frame is incomplete".to_string());
+    let mut particles = Particles::default();
+    let mut bounds: Bounds = [(0.0, 0.0); 3];
+    let mut n = 0;
+    // the line after the values of a skipped item
+    let mut next: Option<String> = None;
+
+    // items before the atoms
+    let header = loop {
+        let line = match next.take() {
+            Some(line) => line,
+            None => read_line(reader)?.ok_or_else(incomplete)?,
+        };
+        if line == "ITEM: TIME" {
+            particles.state.time = parse(&read_line(reader)?.ok_or_else(incomplete)?)?;
+        } else if line == "ITEM: TIMESTEP" {
+            particles.state.step = parse(&read_line(reader)?.ok_or_else(incomplete)?)?;
+        } else if line == "ITEM: NUMBER OF ATOMS" {
+            n = parse(&read_line(reader)?.ok_or_else(incomplete)?)?;
+        } else if line.starts_with("ITEM: BOX BOUNDS") {
+            if line.contains("xy") {
+                return Err(invalid("triclinic boxes are not supported".to_string()));
+            }
+            for b in bounds.iter_mut() {
+                let line = read_line(reader)?.ok_or_else(incomplete)?;
+                let words: Vec<&str> = line.split_whitespace().collect();
+                if words.len() < 2 {
+                    return Err(invalid(format!("invalid box bounds '{}'", line)));
+                }
+                *b = (parse(words[0])?, parse(words[1])?);
+            }
+        } else if let Some(columns) = line.strip_prefix("ITEM: ATOMS") {
+            break columns.split_whitespace().map(|c| c.to_string()).collect::<Vec<String>>();
+        } else if line.starts_with("ITEM:") {
+            // skip the values of an unknown item, up to the next item
+            loop {
+                let line = read_line(reader)?.ok_or_else(incomplete)?;
+                if line.starts_with("ITEM:") {
+                    next = Some(line);
+                    break;
+                }
+            }
+        } else {
+            return Err(invalid(format!("unexpected line '{}' in dump frame", line)));
+        }
+    };
+
+    let column = |name: &str| header.iter().position(|c| c == name);
+    let any_column = |names: [&str; 3]| -> Option<[usize; 3]> {
+        Some([column(names[0])?, column(names[1])?, column(names[2])?])
+    };
+
+    // prefer unwrapped positions, then wrapped positions with image flags, then scaled positions
+    let (positions, scaled) = if let Some(c) = any_column(["xu", "yu", "zu"]) {
+        (c, false)
+    } else if let Some(c) = any_column(["x", "y", "z"]) {
+        (c, false)
+    } else if let Some(c) = any_column(["xsu", "ysu", "zsu"]).or_else(|| any_column(["xs", "ys", "zs"])) {
+        (c, true)
+    } else {
+        return Err(invalid("the dump has no positions".to_string()));
+    };
+    let images = if column("xu").is_none() && column("xsu").is_none() {
+        any_column(["ix", "iy", "iz"])
+    } else {
+        None
+    };
+    let velocities = any_column(["vx", "vy", "vz"]);
+    let (id_column, type_column, mass_column) = (column("id"), column("type"), column("mass"));
+
+    let lengths = [bounds[0].1 - bounds[0].0, bounds[1].1 - bounds[1].0, bounds[2].1 - bounds[2].0];
+    let mut rows = Vec::with_capacity(n);
+    for i in 0..n {
+        let line = read_line(reader)?.ok_or_else(incomplete)?;
+        let values: Vec<&str> = line.split_whitespace().collect();
+        if values.len() < header.len() {
+            return Err(invalid(format!("expected {} columns in '{}'", header.len(), line)));
+        }
+
+        let mut x = [0.0; 3];
+        let mut v = [0.0; 3];
+        for k in 0..3 {
+            x[k] = parse(values[positions[k]])?;
+            if scaled {
+                x[k] = bounds[k].0 + x[k] * lengths[k];
+            }
+            if let Some(images) = images {
+                x[k] += parse::<i64>(values[images[k]])? as f64 * lengths[k];
+            }
+            if let Some(velocities) = velocities {
+                v[k] = parse(values[velocities[k]])?;
+            }
+        }
+
+        let mass = match (mass_column, type_column) {
+            (Some(c), _) => parse(values[c])?,
+            (None, Some(c)) if !type_masses.is_empty() => {
+                let atom_type: i64 = parse(values[c])?;
+                *type_masses
+                    .get(&atom_type)
+                    .ok_or_else(|| invalid(format!("no mass given for atom type {}", atom_type)))?
+            }
+            _ => 1.0,
+        };
+
+        let id = match id_column {
+            Some(c) => parse(values[c])?,
+            None => i as i64,
+        };
+        rows.push((id, Vec3::new(x[0], x[1], x[2]), Vec3::new(v[0], v[1], v[2]), mass));
+    }
+
+    rows.sort_by_key(|&(id, ..)| id);
+    for &(_, x, v, m) in rows.iter() {
+        particles.particle(x, v, m);
+    }
+    if id_column.is_some() {
+        set_atom_ids(&mut particles, rows.iter().map(|row| row.0))?;
+    }
+    Ok(particles)
+}
+
+#[pymethods]
+impl Particles {
+    /// Write the simulation to a LAMMPS data file with `atom_style atomic`, including velocities.
+    /// Each distinct mass becomes an atom type, in ascending order of the masses.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the data file.
+    /// * `bounds` - The `(lo, hi)` box bounds along each axis.
+    /// Defaults to a box enclosing all particles.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles.write_lammps_data("initial.data", bounds=[(-50.0, 50.0)] * 3)
+    /// ```
+    ///
+    #[args(bounds = "None")]
+    pub fn write_lammps_data(&self, path: &str, bounds: Option<Vec<(f64, f64)>>) -> PyResult<()> {
+        let bounds = match bounds {
+            None => bounding_box(&self.state.positions),
+            Some(b) if b.len() == 3 && b.iter().all(|(lo, hi)| lo < hi) => [b[0], b[1], b[2]],
+            Some(_) => return Err(invalid("bounds must be three (lo, hi) pairs with lo < hi".to_string())),
+        };
+        write_data(&mut BufWriter::new(File::create(path)?), self, bounds)
+    }
+
+    /// Read a LAMMPS data file. Masses are taken from the `Masses` section,
+    /// velocities from the optional `Velocities` section, and positions are unwrapped
+    /// with their image flags. Particles are ordered by their atom ID, and their IDs are the atom IDs minus one.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the data file.
+    /// * `atom_style` - The atom style of the `Atoms` section, one of `atomic`, `charge`,
+    /// `bond`, `angle`, `molecular` or `full`. Defaults to the style given in the comment of the
+    /// section header, or `atomic`.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles = Particles.read_lammps_data("equilibrated.data")
+    /// particles.run(n=1000, h=0.005)
+    /// ```
+    ///
+    #[staticmethod]
+    #[args(atom_style = "None")]
+    pub fn read_lammps_data(path: &str, atom_style: Option<&str>) -> PyResult<Particles> {
+        read_data(BufReader::new(File::open(path)?), atom_style)
+    }
+
+    /// Read a frame of a LAMMPS dump file written with `dump custom`, e.g. as written by a
+    /// [LammpsDumpWriter](../io/lammps/struct.LammpsDumpWriter.html).
+    ///
+    /// Positions are read from the `xu yu zu`, `x y z` (unwrapped with `ix iy iz` if present),
+    /// or scaled `xs ys zs` columns, velocities from the optional `vx vy vz` columns.
+    /// Masses are read from a `mass` column, or from the masses of the atom types.
+    /// Particles are ordered by their atom ID, and their IDs are the atom IDs minus one.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the dump file.
+    /// * `frame` - The index of the frame, negative indices count from the end. Defaults to the last frame.
+    /// * `masses` - A dictionary of the mass of each atom type, used if the dump has no `mass` column.
+    /// Masses default to one if neither is given.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles = Particles.read_lammps_dump("dump.lammpstrj", frame=-1, masses={1: 1.0, 2: 4.0})
+    /// ```
+    ///
+    #[staticmethod]
+    #[args(frame = "-1", masses = "None")]
+    pub fn read_lammps_dump(path: &str, frame: isize, masses: Option<HashMap<i64, f64>>) -> PyResult<Particles> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let offsets = dump_frame_offsets(&mut reader)?;
+        reader.seek(SeekFrom::Start(offsets[frame_index(frame, offsets.len())?]))?;
+        read_dump_frame(&mut reader, &masses.unwrap_or_default())
+    }
+}
+
+#[derive(Debug)]
+struct LammpsDumpRecorder {
+    interval: usize,
+    writer: Option<BufWriter<File>>,
+    frames: usize,
+}
+
+impl LammpsDumpRecorder {
+    fn write(&mut self, particles: &Particles) -> PyResult<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("the dump file is closed"))?;
+        write_dump_frame(writer, particles)?;
+        // flush every frame, so the trajectory is complete up to the last frame after a crash
+        writer.flush()?;
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+impl Observer for LammpsDumpRecorder {
+    fn interval(&self) -> usize {
+        self.interval
+    }
+
+    fn observe(&mut self, particles: &Particles) -> PyResult<()> {
+        self.write(particles)
+    }
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(extends=ObserverBase)]
+#[derive(Debug, Clone)]
+/// Writes frames of a simulation to a LAMMPS dump file with the columns
+/// `id type x y z vx vy vz mass`, either on demand with [write](#method.write) or every `interval` steps
+/// when added to a simulation with [add_observer](../../particles/struct.Particles.html#method.add_observer).
+///
+/// The box of each frame encloses all particles. Each distinct mass becomes an atom type,
+/// in ascending order of the masses.
+///
+/// # Arguments
+///
+/// * `path` - The path of the dump file.
+/// * `interval` - The number of steps between two frames written during a run.
+/// * `append` - Whether to append to an existing file instead of overwriting it.
+///
+/// # Examples
+///
+/// Python:
+/// ```python
+/// writer = LammpsDumpWriter("dump.lammpstrj", interval=100)
+/// particles.add_observer(writer)
+/// particles.run(n=10000, h=0.01)
+/// writer.close()
+/// ```
+///
+pub struct LammpsDumpWriter {
+    recorder: Arc<Mutex<LammpsDumpRecorder>>,
+}
+
+#[pymethods]
+impl LammpsDumpWriter {
+    #[new]
+    #[args(interval = "1", append = "false")]
+    pub fn new(path: &str, interval: usize, append: bool) -> PyResult<(Self, ObserverBase)> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+
+        let (recorder, base) = ObserverBase::share(LammpsDumpRecorder {
+            interval: check_interval(interval)?,
+            writer: Some(BufWriter::new(file)),
+            frames: 0,
+        });
+        Ok((Self { recorder }, base))
+    }
+
+    /// The number of frames written by this writer.
+    #[getter]
+    pub fn frames(&self) -> PyResult<usize> {
+        Ok(lock(&self.recorder)?.frames)
+    }
+
+    /// Write the current state of a simulation as the next frame.
+    pub fn write(&self, particles: &Particles) -> PyResult<()> {
+        lock(&self.recorder)?.write(particles)
+    }
+
+    /// Close the dump file. Writing further frames raises an error.
+    pub fn close(&self) -> PyResult<()> {
+        if let Some(mut writer) = lock(&self.recorder)?.writer.take() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+//! Reading and writing simulation state from and to files.
+
+use pyo3::exceptions::PyIndexError;
+use pyo3::prelude::*;
+
+pub mod checkpoint;
+#[cfg(feature = "h5md")]
+pub mod h5md;
+pub mod lammps;
+pub mod trajectory;
+pub mod vtk;
+pub mod xyz;
+
+/// Convert a possibly negative frame index of a trajectory to an absolute one.
+pub(crate) fn frame_index(frame: isize, frames: usize) -> PyResult<usize> {
+    let index = if frame < 0 { frames as isize + frame } else { frame };
+    if index < 0 || index as usize >= frames {
+        return Err(PyIndexError::new_err(format!(
+            "frame {} out of range for a trajectory with {} frames",
+            frame, frames
+        )));
+    }
+    Ok(index as usize)
+}
@@ -0,0 +1,717 @@
+//! A compact binary trajectory format with random access to its frames.
+//!
+//! A trajectory file starts with a header, followed by any number of frames:
+//! ```text
+//! header:  magic "KTTRAJ\0\0" | version: u32 | precision: u32 (4 or 8 bytes per float)
+//! frame:   magic "FRME" | compressed: u8 | particles: u64 | time: f64 | step: u64 | names: u64 | length: u64
+//!          | attribute names | data
+//! ```
+//! All numbers are little-endian. The attribute names are the `names` bytes of the
+//! [attribute](../../particles/struct.Particles.html#method.attribute_names) names of the frame,
+//! separated by spaces. The frame data holds the positions and velocities as interleaved x, y, z
+//! components, followed by the masses and then the values of each attribute, in the precision of the
+//! file, and the particle IDs as u64. If the frame is compressed, the data is zlib-compressed.
+//!
+//! The writer also keeps an index in a sidecar file next to the trajectory, named like it with
+//! an additional `.idx` extension, which lists the frame headers in order:
+//! ```text
+//! index:   magic "KTINDEX\0" | version: u32 | frame header | frame header | ...
+//! ```
+//! Readers find all frames with a single read of the index, and only fall back to skipping from
+//! one frame header to the next in the trajectory if the index is missing or does not match it,
+//! e.g. because the trajectory was copied without its index. Frames written after the last indexed
+//! one, e.g. when a run crashed between writing a frame and its index entry, are found the same way.
+//!
+//! Frames and index entries are only ever appended, so a file can be read while it is written,
+//! and an interrupted write only loses the incomplete last frame.
+
+use crate::io::frame_index;
+use crate::observer::{check_interval, lock, Observer, ObserverBase};
+use crate::particles::Particles;
+use crate::simulation::State;
+use crate::vec3::Vec3;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+use numpy::{PyArray, PyArrayDyn};
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+
+const MAGIC: &[u8; 8] = b"KTTRAJ\0\0";
+const FRAME_MAGIC: &[u8; 4] = b"FRME";
+const INDEX_MAGIC: &[u8; 8] = b"KTINDEX\0";
+const VERSION: u32 = 3;
+const HEADER_SIZE: u64 = 16;
+const FRAME_HEADER_SIZE: u64 = 45;
+const INDEX_HEADER_SIZE: usize = 12;
+
+/// The precision in which floats are stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    Single,
+    Double,
+}
+
+impl Precision {
+    pub fn parse(precision: &str) -> PyResult<Self> {
+        match precision.to_lowercase().as_str() {
+            "f32" | "single" => Ok(Precision::Single),
+            "f64" | "double" => Ok(Precision::Double),
+            _ => Err(PyValueError::new_err(format!(
+                "unknown precision '{}', expected 'f32' or 'f64'",
+                precision
+            ))),
+        }
+    }
+
+    fn bytes(self) -> usize {
+        match self {
+            Precision::Single => 4,
+            Precision::Double => 8,
+        }
+    }
+
+    fn from_bytes(bytes: u32) -> PyResult<Self> {
+        match bytes {
+            4 => Ok(Precision::Single),
+            8 => Ok(Precision::Double),
+            _ => Err(PyValueError::new_err(format!("invalid float size {} in trajectory", bytes))),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Precision::Single => "f32",
+            Precision::Double => "f64",
+        }
+    }
+
+    fn encode(self, values: impl Iterator<Item = f64>, data: &mut Vec<u8>) {
+        for value in values {
+            match self {
+                Precision::Single => data.extend_from_slice(&(value as f32).to_le_bytes()),
+                Precision::Double => data.extend_from_slice(&value.to_le_bytes()),
+            }
+        }
+    }
+
+    fn decode(self, data: &[u8]) -> Vec<f64> {
+        match self {
+            Precision::Single => data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+                .collect(),
+            Precision::Double => data
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+                .collect(),
+        }
+    }
+}
+
+/// The location and metadata of a frame in a trajectory file
+#[derive(Debug, Clone)]
+pub struct FrameInfo {
+    pub offset: u64,
+    pub compressed: bool,
+    pub num_particles: usize,
+    pub time: f64,
+    pub step: usize,
+    pub names_length: u64,
+    pub length: u64,
+}
+
+impl FrameInfo {
+    /// The offset of the first byte after the frame, or an error if the lengths in its header
+    /// overflow.
+    pub fn end(&self) -> PyResult<u64> {
+        self.offset
+            .checked_add(FRAME_HEADER_SIZE)
+            .and_then(|end| end.checked_add(self.names_length))
+            .and_then(|end| end.checked_add(self.length))
+            .ok_or_else(|| PyValueError::new_err("corrupt frame header"))
+    }
+
+    /// Decode the header of the frame at `offset`.
+    fn parse(offset: u64, header: &[u8]) -> PyResult<Self> {
+        if &header[..4] != FRAME_MAGIC {
+            return Err(PyValueError::new_err(format!("corrupt trajectory: no frame at byte {}", offset)));
+        }
+
+        let word = |i: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&header[i..i + 8]);
+            bytes
+        };
+        Ok(Self {
+            offset,
+            compressed: header[4] != 0,
+            num_particles: u64::from_le_bytes(word(5)) as usize,
+            time: f64::from_le_bytes(word(13)),
+            step: u64::from_le_bytes(word(21)) as usize,
+            names_length: u64::from_le_bytes(word(29)),
+            length: u64::from_le_bytes(word(37)),
+        })
+    }
+
+    /// Encode the header of the frame, as written in front of its data and to the index.
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(FRAME_HEADER_SIZE as usize);
+        header.extend_from_slice(FRAME_MAGIC);
+        header.push(self.compressed as u8);
+        header.extend_from_slice(&(self.num_particles as u64).to_le_bytes());
+        header.extend_from_slice(&self.time.to_le_bytes());
+        header.extend_from_slice(&(self.step as u64).to_le_bytes());
+        header.extend_from_slice(&self.names_length.to_le_bytes());
+        header.extend_from_slice(&self.length.to_le_bytes());
+        header
+    }
+}
+
+/// The decoded data of a frame, with the vector components flattened.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub positions: Vec<f64>,
+    pub velocities: Vec<f64>,
+    pub masses: Vec<f64>,
+    pub ids: Vec<u64>,
+    pub attributes: BTreeMap<String, Vec<f64>>,
+}
+
+fn write_header<W: Write>(writer: &mut W, precision: Precision) -> PyResult<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(precision.bytes() as u32).to_le_bytes())?;
+    Ok(())
+}
+
+fn read_header<R: Read>(reader: &mut R) -> PyResult<Precision> {
+    let mut header = [0u8; HEADER_SIZE as usize];
+    reader.read_exact(&mut header)?;
+    if &header[..8] != MAGIC {
+        return Err(PyValueError::new_err("not a trajectory file"));
+    }
+    let version = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+    if version != VERSION {
+        return Err(PyValueError::new_err(format!(
+            "unsupported trajectory version {}, expected {}",
+            version, VERSION
+        )));
+    }
+    Precision::from_bytes(u32::from_le_bytes([header[12], header[13], header[14], header[15]]))
+}
+
+/// Write a frame at the current position of the writer. Returns its header for the index.
+pub fn write_frame<W: Write>(
+    writer: &mut W,
+    particles: &Particles,
+    precision: Precision,
+    compression: Option<u32>,
+) -> PyResult<Vec<u8>> {
+    let n = particles.state.positions.len();
+    let columns = 7 + particles.attributes.len();
+    let mut data = Vec::with_capacity(columns * n * precision.bytes() + 8 * n);
+    precision.encode(particles.state.positions.iter().flat_map(|v| [v.x, v.y, v.z]), &mut data);
+    precision.encode(particles.state.velocities.iter().flat_map(|v| [v.x, v.y, v.z]), &mut data);
+    precision.encode(particles.state.masses.iter().copied(), &mut data);
+    for values in particles.attributes.values() {
+        precision.encode(values.iter().copied(), &mut data);
+    }
+    for id in particles.ids.iter() {
+        data.extend_from_slice(&id.to_le_bytes());
+    }
+
+    if let Some(level) = compression {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level));
+        encoder.write_all(&data)?;
+        data = encoder.finish()?;
+    }
+
+    let names = particles.attribute_names().join(" ");
+    let header = FrameInfo {
+        offset: 0,
+        compressed: compression.is_some(),
+        num_particles: n,
+        time: particles.state.time,
+        step: particles.state.step,
+        names_length: names.len() as u64,
+        length: data.len() as u64,
+    }
+    .header();
+
+    writer.write_all(&header)?;
+    writer.write_all(names.as_bytes())?;
+    writer.write_all(&data)?;
+    Ok(header)
+}
+
+/// Find all complete frames from `offset` on. An incomplete last frame is ignored,
+/// since it may still be written or its write was interrupted.
+pub fn scan_frames<R: Read + Seek>(reader: &mut R, mut offset: u64) -> PyResult<Vec<FrameInfo>> {
+    let file_length = reader.seek(SeekFrom::End(0))?;
+    let mut frames = Vec::new();
+
+    while offset + FRAME_HEADER_SIZE <= file_length {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; FRAME_HEADER_SIZE as usize];
+        reader.read_exact(&mut header)?;
+        let frame = FrameInfo::parse(offset, &header)?;
+        let end = frame.end()?;
+        if end > file_length {
+            break;
+        }
+        offset = end;
+        frames.push(frame);
+    }
+
+    Ok(frames)
+}
+
+/// The path of the index file of the trajectory at `path`.
+pub fn index_path(path: &str) -> String {
+    format!("{}.idx", path)
+}
+
+/// Write a new index file listing `frames`, and return it for appending further entries.
+fn write_index(path: &str, frames: &[FrameInfo]) -> PyResult<BufWriter<File>> {
+    let mut index = BufWriter::new(File::create(path)?);
+    index.write_all(INDEX_MAGIC)?;
+    index.write_all(&VERSION.to_le_bytes())?;
+    for frame in frames {
+        index.write_all(&frame.header())?;
+    }
+    index.flush()?;
+    Ok(index)
+}
+
+/// Read the frames listed in an index file, or `None` if it is missing or stale, i.e. lists frames
+/// beyond the end of the trajectory or its last frame header differs from the one in the trajectory.
+/// An incomplete last entry is ignored, like an incomplete last frame.
+fn read_index<R: Read + Seek>(reader: &mut R, path: &str) -> PyResult<Option<Vec<FrameInfo>>> {
+    let index = match std::fs::read(path) {
+        Ok(index) => index,
+        Err(_) => return Ok(None),
+    };
+    if index.len() < INDEX_HEADER_SIZE
+        || &index[..8] != INDEX_MAGIC
+        || index[8..12] != VERSION.to_le_bytes()
+    {
+        return Ok(None);
+    }
+
+    let file_length = reader.seek(SeekFrom::End(0))?;
+    let entries: Vec<&[u8]> = index[INDEX_HEADER_SIZE..]
+        .chunks_exact(FRAME_HEADER_SIZE as usize)
+        .collect();
+    let mut frames = Vec::with_capacity(entries.len());
+    let mut offset = HEADER_SIZE;
+    for entry in entries.iter() {
+        let frame = match FrameInfo::parse(offset, entry) {
+            Ok(frame) => frame,
+            Err(_) => return Ok(None),
+        };
+        offset = match frame.end() {
+            Ok(end) if end <= file_length => end,
+            _ => return Ok(None),
+        };
+        frames.push(frame);
+    }
+
+    if let (Some(frame), Some(entry)) = (frames.last(), entries.last()) {
+        reader.seek(SeekFrom::Start(frame.offset))?;
+        let mut header = [0u8; FRAME_HEADER_SIZE as usize];
+        reader.read_exact(&mut header)?;
+        if header[..] != entry[..] {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(frames))
+}
+
+/// Find all complete frames of the trajectory, from its index file at `index_path` if it is
+/// up to date, and by scanning the frame headers otherwise. Frames after the last indexed one
+/// are always found by scanning.
+pub fn find_frames<R: Read + Seek>(reader: &mut R, index_path: &str) -> PyResult<Vec<FrameInfo>> {
+    match read_index(reader, index_path)? {
+        Some(mut frames) => {
+            let end = frames.last().map_or(Ok(HEADER_SIZE), |frame| frame.end())?;
+            frames.extend(scan_frames(reader, end)?);
+            Ok(frames)
+        }
+        None => scan_frames(reader, HEADER_SIZE),
+    }
+}
+
+/// Read and decode the data of a frame.
+pub fn read_frame<R: Read + Seek>(reader: &mut R, frame: &FrameInfo, precision: Precision) -> PyResult<Frame> {
+    reader.seek(SeekFrom::Start(frame.offset + FRAME_HEADER_SIZE))?;
+    let mut names = vec![0u8; frame.names_length as usize];
+    reader.read_exact(&mut names)?;
+    let names = String::from_utf8(names).map_err(|_| {
+        PyValueError::new_err(format!(
+            "corrupt trajectory: invalid attribute names in frame at byte {}",
+            frame.offset
+        ))
+    })?;
+    let names: Vec<&str> = names.split_whitespace().collect();
+
+    let mut data = vec![0u8; frame.length as usize];
+    reader.read_exact(&mut data)?;
+
+    if frame.compressed {
+        let mut decompressed = Vec::new();
+        ZlibDecoder::new(data.as_slice()).read_to_end(&mut decompressed)?;
+        data = decompressed;
+    }
+
+    let n = frame.num_particles;
+    let columns = 7 + names.len();
+    let expected = columns * n * precision.bytes() + 8 * n;
+    if data.len() != expected {
+        return Err(PyValueError::new_err(format!(
+            "corrupt trajectory: frame at byte {} has {} bytes of data, expected {}",
+            frame.offset,
+            data.len(),
+            expected
+        )));
+    }
+
+    let (floats, ids) = data.split_at(columns * n * precision.bytes());
+    let values = precision.decode(floats);
+    Ok(Frame {
+        positions: values[..3 * n].to_vec(),
+        velocities: values[3 * n..6 * n].to_vec(),
+        masses: values[6 * n..7 * n].to_vec(),
+        ids: ids
+            .chunks_exact(8)
+            .map(|b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+            .collect(),
+        attributes: names
+            .iter()
+            .enumerate()
+            .map(|(a, name)| (name.to_string(), values[(7 + a) * n..(8 + a) * n].to_vec()))
+            .collect(),
+    })
+}
+
+#[derive(Debug)]
+struct TrajectoryRecorder {
+    interval: usize,
+    precision: Precision,
+    compression: Option<u32>,
+    // the trajectory and its index file
+    writer: Option<(BufWriter<File>, BufWriter<File>)>,
+    frames: usize,
+}
+
+impl TrajectoryRecorder {
+    fn write(&mut self, particles: &Particles) -> PyResult<()> {
+        let (writer, index) = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("the trajectory file is closed"))?;
+        let header = write_frame(writer, particles, self.precision, self.compression)?;
+        // flush every frame before its index entry, so readers see complete frames while the
+        // simulation runs, and the index never lists a frame which is not written yet
+        writer.flush()?;
+        index.write_all(&header)?;
+        index.flush()?;
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+impl Observer for TrajectoryRecorder {
+    fn interval(&self) -> usize {
+        self.interval
+    }
+
+    fn observe(&mut self, particles: &Particles) -> PyResult<()> {
+        self.write(particles)
+    }
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(extends=ObserverBase)]
+#[derive(Debug, Clone)]
+/// Writes frames of a simulation to a [binary trajectory file](index.html), either on demand with
+/// [write](#method.write) or every `interval` steps when added to a simulation with
+/// [add_observer](../../particles/struct.Particles.html#method.add_observer).
+///
+/// # Arguments
+///
+/// * `path` - The path of the trajectory file.
+/// * `interval` - The number of steps between two frames written during a run.
+/// * `precision` - `"f64"` (default) or `"f32"`, which halves the file size.
+/// When appending, this must match the precision of the file.
+/// * `compression` - If given, compress each frame with this zlib level from 0 to 9.
+/// * `append` - Whether to append to an existing trajectory instead of overwriting it.
+/// An incomplete last frame, e.g. of a crashed run, is discarded.
+///
+/// # Examples
+///
+/// Python:
+/// ```python
+/// writer = TrajectoryWriter("run.traj", interval=10, precision="f32", compression=6)
+/// particles.add_observer(writer)
+/// particles.run(n=10000, h=0.01)
+/// writer.close()
+/// ```
+///
+pub struct TrajectoryWriter {
+    recorder: Arc<Mutex<TrajectoryRecorder>>,
+}
+
+#[pymethods]
+impl TrajectoryWriter {
+    #[new]
+    #[args(interval = "1", precision = "\"f64\"", compression = "None", append = "false")]
+    pub fn new(path: &str, interval: usize, precision: &str, compression: Option<u32>, append: bool) -> PyResult<(Self, ObserverBase)> {
+        let mut precision = Precision::parse(precision)?;
+        if let Some(level) = compression {
+            if level > 9 {
+                return Err(PyValueError::new_err("the compression level must be between 0 and 9"));
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(!append)
+            .open(path)?;
+
+        let index = if file.metadata()?.len() == 0 {
+            write_header(&mut file, precision)?;
+            write_index(&index_path(path), &[])?
+        } else {
+            let existing = read_header(&mut file)?;
+            if existing != precision {
+                return Err(PyValueError::new_err(format!(
+                    "cannot append with precision {} to a trajectory with precision {}",
+                    precision.name(),
+                    existing.name()
+                )));
+            }
+            precision = existing;
+
+            // continue after the last complete frame, with an up to date index
+            let frames = find_frames(&mut file, &index_path(path))?;
+            let end = frames.last().map_or(Ok(HEADER_SIZE), |frame| frame.end())?;
+            file.set_len(end)?;
+            file.seek(SeekFrom::Start(end))?;
+            write_index(&index_path(path), &frames)?
+        };
+
+        let (recorder, base) = ObserverBase::share(TrajectoryRecorder {
+            interval: check_interval(interval)?,
+            precision,
+            compression,
+            writer: Some((BufWriter::new(file), index)),
+            frames: 0,
+        });
+        Ok((Self { recorder }, base))
+    }
+
+    /// The number of frames written by this writer.
+    #[getter]
+    pub fn frames(&self) -> PyResult<usize> {
+        Ok(lock(&self.recorder)?.frames)
+    }
+
+    /// Write the current state of a simulation as the next frame.
+    pub fn write(&self, particles: &Particles) -> PyResult<()> {
+        lock(&self.recorder)?.write(particles)
+    }
+
+    /// Close the trajectory file. Writing further frames raises an error.
+    pub fn close(&self) -> PyResult<()> {
+        if let Some((mut writer, mut index)) = lock(&self.recorder)?.writer.take() {
+            writer.flush()?;
+            index.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(unsendable)]
+#[derive(Debug)]
+/// Reads frames of a [binary trajectory file](index.html) written by a
+/// [TrajectoryWriter](struct.TrajectoryWriter.html), in any order.
+/// Frame indices may be negative to count from the end.
+///
+/// # Examples
+///
+/// Python:
+/// ```python
+/// trajectory = TrajectoryReader("run.traj")
+/// for k in range(trajectory.num_frames):
+///     x = trajectory.positions(k)  # numpy array of shape (N, 3)
+///
+/// particles = trajectory.particles(-1)
+/// ```
+///
+pub struct TrajectoryReader {
+    reader: BufReader<File>,
+    index_path: String,
+    precision: Precision,
+    frames: Vec<FrameInfo>,
+    // the last decoded frame, since its positions, velocities and masses are usually read together
+    cached: Option<(usize, Frame)>,
+}
+
+#[pymethods]
+impl TrajectoryReader {
+    #[new]
+    pub fn new(path: &str) -> PyResult<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let precision = read_header(&mut reader)?;
+        let index_path = index_path(path);
+        let frames = find_frames(&mut reader, &index_path)?;
+
+        Ok(Self {
+            reader,
+            index_path,
+            precision,
+            frames,
+            cached: None,
+        })
+    }
+
+    /// Find frames appended since the trajectory was opened, e.g. by a running simulation.
+    /// Returns the number of new frames.
+    pub fn refresh(&mut self) -> PyResult<usize> {
+        let frames = find_frames(&mut self.reader, &self.index_path)?;
+        let new = frames.len().saturating_sub(self.frames.len());
+        self.frames = frames;
+        Ok(new)
+    }
+
+    /// The number of frames in the trajectory.
+    #[getter]
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The precision of the stored floats, `"f32"` or `"f64"`.
+    #[getter]
+    pub fn precision(&self) -> &'static str {
+        self.precision.name()
+    }
+
+    /// The simulation time of each frame.
+    #[getter]
+    pub fn times(&self) -> Vec<f64> {
+        self.frames.iter().map(|frame| frame.time).collect()
+    }
+
+    /// The time step of each frame.
+    #[getter]
+    pub fn steps(&self) -> Vec<usize> {
+        self.frames.iter().map(|frame| frame.step).collect()
+    }
+
+    /// The number of particles in each frame.
+    #[getter]
+    pub fn num_particles(&self) -> Vec<usize> {
+        self.frames.iter().map(|frame| frame.num_particles).collect()
+    }
+
+    /// The positions of a frame as a numpy array of shape (N, 3).
+    pub fn positions<'py>(&mut self, py: Python<'py>, frame: isize) -> PyResult<&'py PyArrayDyn<f64>> {
+        let frame = self.frame(frame)?;
+        let (n, positions) = (frame.masses.len(), frame.positions.clone());
+        PyArray::from_vec(py, positions).reshape(vec![n, 3])
+    }
+
+    /// The velocities of a frame as a numpy array of shape (N, 3).
+    pub fn velocities<'py>(&mut self, py: Python<'py>, frame: isize) -> PyResult<&'py PyArrayDyn<f64>> {
+        let frame = self.frame(frame)?;
+        let (n, velocities) = (frame.masses.len(), frame.velocities.clone());
+        PyArray::from_vec(py, velocities).reshape(vec![n, 3])
+    }
+
+    /// The masses of a frame as a numpy array of shape (N,).
+    pub fn masses<'py>(&mut self, py: Python<'py>, frame: isize) -> PyResult<&'py PyArrayDyn<f64>> {
+        let masses = self.frame(frame)?.masses.clone();
+        let n = masses.len();
+        PyArray::from_vec(py, masses).reshape(vec![n])
+    }
+
+    /// The particle IDs of a frame.
+    pub fn ids(&mut self, frame: isize) -> PyResult<Vec<u64>> {
+        Ok(self.frame(frame)?.ids.clone())
+    }
+
+    /// The names of the per-particle attributes of a frame, in alphabetical order.
+    pub fn attribute_names(&mut self, frame: isize) -> PyResult<Vec<String>> {
+        Ok(self.frame(frame)?.attributes.keys().cloned().collect())
+    }
+
+    /// The values of an attribute of a frame as a numpy array of shape (N,).
+    pub fn attribute<'py>(
+        &mut self,
+        py: Python<'py>,
+        frame: isize,
+        name: &str,
+    ) -> PyResult<&'py PyArrayDyn<f64>> {
+        let values = self
+            .frame(frame)?
+            .attributes
+            .get(name)
+            .ok_or_else(|| PyKeyError::new_err(format!("no attribute '{}'", name)))?
+            .clone();
+        let n = values.len();
+        PyArray::from_vec(py, values).reshape(vec![n])
+    }
+
+    /// Restore a simulation from a frame, e.g. to continue a run.
+    pub fn particles(&mut self, frame: isize) -> PyResult<Particles> {
+        let info = self.frames[frame_index(frame, self.frames.len())?].clone();
+        let data = self.frame(frame)?;
+
+        let vectors = |components: &[f64]| -> Vec<Vec3> {
+            components
+                .chunks_exact(3)
+                .map(|c| Vec3::new(c[0], c[1], c[2]))
+                .collect()
+        };
+
+        let mut particles = Particles {
+            state: State {
+                positions: vectors(&data.positions),
+                velocities: vectors(&data.velocities),
+                masses: data.masses.clone(),
+                time: info.time,
+                step: info.step,
+                ..State::default()
+            },
+            attributes: data.attributes.clone(),
+            ..Particles::default()
+        };
+        particles.set_ids(data.ids.clone())?;
+        Ok(particles)
+    }
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl TrajectoryReader {
+    /// Decode a frame, or return it from the cache.
+    pub fn frame(&mut self, frame: isize) -> PyResult<&Frame> {
+        let index = frame_index(frame, self.frames.len())?;
+        let cached = matches!(&self.cached, Some((i, _)) if *i == index);
+        if !cached {
+            let data = read_frame(&mut self.reader, &self.frames[index], self.precision)?;
+            self.cached = Some((index, data));
+        }
+        Ok(&self.cached.as_ref().expect("the frame was just cached").1)
+    }
+}
@@ -0,0 +1,443 @@
+//! Particles and grid fields in the XML [VTK formats](https://docs.vtk.org/en/latest/design_documents/VTKFileFormats.html),
+//! which can be opened with ParaView.
+//!
+//! Particles are written as unstructured grids (`.vtu`) of vertex cells, with the velocity,
+//! mass, species, ID and attributes of each particle as point arrays.
+//! [GridFields](../../analysis/grid/struct.GridFields.html) are written as image data (`.vti`),
+//! with the densities, flow velocity and temperature as cell arrays.
+//! A collection file (`.pvd`) links the files of each frame to the simulation time,
+//! so ParaView can play back a run and show particles and fields together.
+//!
+//! All data is written as ASCII, with floats in their shortest representation that reads
+//! back to the same value.
+
+use crate::analysis::grid::{cell_temperature, flow_velocity, Assignment, Grid, GridFields};
+use crate::io::lammps::atom_types;
+use crate::observer::{check_interval, lock, Observer, ObserverBase};
+use crate::particles::Particles;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+// VTK cell type of a single point
+const VTK_VERTEX: u8 = 1;
+// the number of values per line in data arrays
+const VALUES_PER_LINE: usize = 9;
+
+fn header<W: Write>(writer: &mut W, kind: &str) -> PyResult<()> {
+    writeln!(writer, "<?xml version=\"1.0\"?>")?;
+    writeln!(
+        writer,
+        "<VTKFile type=\"{}\" version=\"1.0\" byte_order=\"LittleEndian\" header_type=\"UInt64\">",
+        kind
+    )?;
+    Ok(())
+}
+
+/// Replace the characters with a special meaning in XML attribute values by entities.
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write an ASCII data array. The name is omitted if it is empty, and escaped otherwise.
+fn data_array<W, T, I>(writer: &mut W, kind: &str, name: &str, components: usize, values: I) -> PyResult<()>
+where
+    W: Write,
+    T: Debug,
+    I: IntoIterator<Item = T>,
+{
+    write!(writer, "<DataArray type=\"{}\"", kind)?;
+    if !name.is_empty() {
+        write!(writer, " Name=\"{}\"", escape_xml(name))?;
+    }
+    if components > 1 {
+        write!(writer, " NumberOfComponents=\"{}\"", components)?;
+    }
+    writeln!(writer, " format=\"ascii\">")?;
+
+    for (i, value) in values.into_iter().enumerate() {
+        let separator = if (i + 1) % VALUES_PER_LINE == 0 { "\n" } else { " " };
+        write!(writer, "{:?}{}", value, separator)?;
+    }
+    writeln!(writer)?;
+    writeln!(writer, "</DataArray>")?;
+    Ok(())
+}
+
+/// Write the simulation time and step as field data, which ParaView shows as the time of the data set.
+fn time_fields<W: Write>(writer: &mut W, time: f64, step: usize) -> PyResult<()> {
+    writeln!(writer, "<FieldData>")?;
+    writeln!(
+        writer,
+        "<DataArray type=\"Float64\" Name=\"TimeValue\" NumberOfTuples=\"1\" format=\"ascii\">{:?}</DataArray>",
+        time
+    )?;
+    writeln!(
+        writer,
+        "<DataArray type=\"Int64\" Name=\"Step\" NumberOfTuples=\"1\" format=\"ascii\">{}</DataArray>",
+        step
+    )?;
+    writeln!(writer, "</FieldData>")?;
+    Ok(())
+}
+
+/// Write the particles as an unstructured grid of vertices.
+/// The species of a particle is the index of its mass among the distinct masses, starting at 1.
+pub fn write_vtu<W: Write>(writer: &mut W, particles: &Particles) -> PyResult<()> {
+    let n = particles.state.positions.len();
+    let (_, species) = atom_types(&particles.state.masses);
+    let components = |vectors: &[crate::vec3::Vec3]| -> Vec<f64> {
+        vectors.iter().flat_map(|v| [v.x, v.y, v.z]).collect()
+    };
+
+    header(writer, "UnstructuredGrid")?;
+    writeln!(writer, "<UnstructuredGrid>")?;
+    time_fields(writer, particles.state.time, particles.state.step)?;
+    writeln!(writer, "<Piece NumberOfPoints=\"{}\" NumberOfCells=\"{}\">", n, n)?;
+
+    writeln!(writer, "<PointData Scalars=\"mass\" Vectors=\"velocity\">")?;
+    data_array(writer, "Float64", "velocity", 3, components(&particles.state.velocities))?;
+    data_array(writer, "Float64", "mass", 1, particles.state.masses.iter())?;
+    data_array(writer, "Int32", "species", 1, species)?;
+    data_array(writer, "UInt64", "id", 1, particles.ids.iter())?;
+    for (name, values) in particles.attributes.iter() {
+        data_array(writer, "Float64", name, 1, values.iter())?;
+    }
+    writeln!(writer, "</PointData>")?;
+
+    writeln!(writer, "<Points>")?;
+    data_array(writer, "Float64", "", 3, components(&particles.state.positions))?;
+    writeln!(writer, "</Points>")?;
+
+    writeln!(writer, "<Cells>")?;
+    data_array(writer, "Int64", "connectivity", 1, 0..n)?;
+    data_array(writer, "Int64", "offsets", 1, 1..=n)?;
+    data_array(writer, "UInt8", "types", 1, std::iter::repeat(VTK_VERTEX).take(n))?;
+    writeln!(writer, "</Cells>")?;
+
+    writeln!(writer, "</Piece>")?;
+    writeln!(writer, "</UnstructuredGrid>")?;
+    writeln!(writer, "</VTKFile>")?;
+    Ok(())
+}
+
+/// Write grid fields as image data with one cell per grid cell.
+/// Grids with fewer than three dimensions are flat along the remaining axes.
+pub fn write_vti<W: Write>(writer: &mut W, fields: &GridFields, time: Option<(f64, usize)>) -> PyResult<()> {
+    let grid = fields.grid();
+    let bins = [0, 1, 2].map(|axis| grid.bins.get(axis).copied().unwrap_or(1));
+    let origin: Vec<f64> = (0..3).map(|axis| grid.lower.get(axis).copied().unwrap_or(0.0)).collect();
+    let spacing: Vec<f64> = (0..3)
+        .map(|axis| if axis < grid.ndim() { grid.cell_size(axis) } else { 1.0 })
+        .collect();
+    let extent = |axis: usize| if axis < grid.ndim() { bins[axis] } else { 0 };
+    let extent = format!("0 {} 0 {} 0 {}", extent(0), extent(1), extent(2));
+
+    // the grid is stored in row-major order, while VTK expects the x index to vary fastest
+    let cells: Vec<&[f64]> = fields.cells().collect();
+    let ordered: Vec<&[f64]> = (0..bins[2])
+        .flat_map(|k| (0..bins[1]).flat_map(move |j| (0..bins[0]).map(move |i| (i, j, k))))
+        .map(|(i, j, k)| cells[(i * bins[1] + j) * bins[2] + k])
+        .collect();
+    let volume = grid.cell_volume();
+    let scalar = |f: &dyn Fn(&[f64]) -> f64| -> Vec<f64> { ordered.iter().map(|cell| f(cell)).collect() };
+    let vector = |f: &dyn Fn(&[f64]) -> [f64; 3]| -> Vec<f64> { ordered.iter().flat_map(|cell| f(cell)).collect() };
+
+    header(writer, "ImageData")?;
+    writeln!(
+        writer,
+        "<ImageData WholeExtent=\"{}\" Origin=\"{:?} {:?} {:?}\" Spacing=\"{:?} {:?} {:?}\">",
+        extent, origin[0], origin[1], origin[2], spacing[0], spacing[1], spacing[2]
+    )?;
+    if let Some((time, step)) = time {
+        time_fields(writer, time, step)?;
+    }
+    writeln!(writer, "<Piece Extent=\"{}\">", extent)?;
+
+    writeln!(writer, "<CellData Scalars=\"density\" Vectors=\"velocity\">")?;
+    data_array(writer, "Float64", "density", 1, scalar(&|cell| cell[0] / volume))?;
+    data_array(writer, "Float64", "number_density", 1, scalar(&|cell| cell[1] / volume))?;
+    data_array(
+        writer,
+        "Float64",
+        "momentum_density",
+        3,
+        vector(&|cell| [cell[2] / volume, cell[3] / volume, cell[4] / volume]),
+    )?;
+    data_array(writer, "Float64", "kinetic_energy_density", 1, scalar(&|cell| cell[5] / volume))?;
+    data_array(writer, "Float64", "velocity", 3, vector(&flow_velocity))?;
+    let dimensions = fields.dimensions();
+    data_array(writer, "Float64", "temperature", 1, scalar(&|cell| cell_temperature(cell, dimensions)))?;
+    writeln!(writer, "</CellData>")?;
+
+    writeln!(writer, "</Piece>")?;
+    writeln!(writer, "</ImageData>")?;
+    writeln!(writer, "</VTKFile>")?;
+    Ok(())
+}
+
+/// A data set of a `.pvd` collection: the simulation time, the part and the (relative) file name.
+#[derive(Debug, Clone)]
+pub struct DataSet {
+    pub time: f64,
+    pub part: usize,
+    pub file: String,
+}
+
+/// Write a collection file that links data sets to the simulation time.
+pub fn write_pvd<W: Write>(writer: &mut W, datasets: &[DataSet]) -> PyResult<()> {
+    header(writer, "Collection")?;
+    writeln!(writer, "<Collection>")?;
+    for dataset in datasets {
+        writeln!(
+            writer,
+            "<DataSet timestep=\"{:?}\" group=\"\" part=\"{}\" file=\"{}\"/>",
+            dataset.time, dataset.part, dataset.file
+        )?;
+    }
+    writeln!(writer, "</Collection>")?;
+    writeln!(writer, "</VTKFile>")?;
+    Ok(())
+}
+
+fn create(path: &Path) -> PyResult<BufWriter<File>> {
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+#[pymethods]
+impl Particles {
+    /// Write the particles to a VTK unstructured grid file (`.vtu`), with the velocity, mass,
+    /// species, ID and attributes of each particle as point arrays. The species of a particle is the index of
+    /// its mass among the distinct masses, starting at 1.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles.write_vtu("particles.vtu")
+    /// ```
+    ///
+    pub fn write_vtu(&self, path: &str) -> PyResult<()> {
+        let mut writer = create(Path::new(path))?;
+        write_vtu(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl GridFields {
+    /// Write the fields to a VTK image data file (`.vti`), with the mass, number, momentum and
+    /// kinetic energy densities, the flow velocity and the temperature as cell arrays.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles.grid_fields([64, 64, 64]).write_vti("fields.vti")
+    /// ```
+    ///
+    pub fn write_vti(&self, path: &str) -> PyResult<()> {
+        let mut writer = create(Path::new(path))?;
+        write_vti(&mut writer, self, None)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The grid on which the fields are deposited for each frame, see
+/// [grid_fields](../../particles/struct.Particles.html#method.grid_fields).
+#[derive(Debug)]
+struct GridSettings {
+    bins: Vec<usize>,
+    ranges: Option<Vec<(f64, f64)>>,
+    scheme: Assignment,
+}
+
+impl GridSettings {
+    fn grid(&self, particles: &Particles) -> PyResult<Grid> {
+        match &self.ranges {
+            Some(ranges) => Grid::new(
+                ranges.iter().map(|r| r.0).collect(),
+                ranges.iter().map(|r| r.1).collect(),
+                self.bins.clone(),
+            ),
+            None => Grid::bounding(&particles.state.positions, self.bins.clone()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct VtkRecorder {
+    interval: usize,
+    // the collection file, frames are written next to it
+    path: Option<PathBuf>,
+    grid: Option<GridSettings>,
+    datasets: Vec<DataSet>,
+    frames: usize,
+}
+
+impl VtkRecorder {
+    fn write(&mut self, particles: &Particles) -> PyResult<()> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("the VTK collection is closed"))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+        let file = format!("{}_{:06}.vtu", stem, self.frames);
+        let mut writer = create(&directory.join(&file))?;
+        write_vtu(&mut writer, particles)?;
+        writer.flush()?;
+        let mut datasets = vec![DataSet { time: particles.state.time, part: 0, file }];
+
+        if let Some(settings) = &self.grid {
+            let fields = GridFields::deposit(particles, settings.grid(particles)?, settings.scheme);
+            let file = format!("{}_grid_{:06}.vti", stem, self.frames);
+            let mut writer = create(&directory.join(&file))?;
+            write_vti(&mut writer, &fields, Some((particles.state.time, particles.state.step)))?;
+            writer.flush()?;
+            datasets.push(DataSet { time: particles.state.time, part: 1, file });
+        }
+
+        self.datasets.extend(datasets);
+        self.frames += 1;
+
+        // rewrite the collection every frame, so it is complete up to the last frame after a crash
+        let temporary = path.with_extension("pvd.tmp");
+        let mut writer = create(&temporary)?;
+        write_pvd(&mut writer, &self.datasets)?;
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+}
+
+impl Observer for VtkRecorder {
+    fn interval(&self) -> usize {
+        self.interval
+    }
+
+    fn observe(&mut self, particles: &Particles) -> PyResult<()> {
+        self.write(particles)
+    }
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(extends=ObserverBase)]
+#[derive(Debug, Clone)]
+/// Writes frames of a simulation as a VTK collection for ParaView, either on demand with
+/// [write](#method.write) or every `interval` steps when added to a simulation with
+/// [add_observer](../../particles/struct.Particles.html#method.add_observer).
+///
+/// The particles of each frame are written to `<name>_<frame>.vtu` next to the collection
+/// file `<name>.pvd`. If `bins` are given, the grid fields of each frame are written to
+/// `<name>_grid_<frame>.vti` as the second part of the collection.
+///
+/// # Arguments
+///
+/// * `path` - The path of the `.pvd` collection file.
+/// * `interval` - The number of steps between two frames written during a run.
+/// * `bins` - Optional number of bins per axis of the grid fields,
+/// see [grid_fields](../../particles/struct.Particles.html#method.grid_fields).
+/// * `ranges` - Optional list of `(lower, upper)` bounds per axis of the grid fields.
+/// Defaults to the bounding box of the particles in each frame.
+/// * `scheme` - The assignment scheme of the grid fields, one of `"ngp"`, `"cic"` (default) or `"tsc"`.
+///
+/// # Examples
+///
+/// Python:
+/// ```python
+/// writer = VtkWriter("run.pvd", interval=100, bins=[32, 32, 32], ranges=[(-20.0, 20.0)] * 3)
+/// particles.add_observer(writer)
+/// particles.run(n=10000, h=0.01)
+/// ```
+///
+pub struct VtkWriter {
+    recorder: Arc<Mutex<VtkRecorder>>,
+}
+
+#[pymethods]
+impl VtkWriter {
+    #[new]
+    #[args(interval = "1", bins = "None", ranges = "None", scheme = "\"cic\"")]
+    pub fn new(
+        path: &str,
+        interval: usize,
+        bins: Option<Vec<usize>>,
+        ranges: Option<Vec<(f64, f64)>>,
+        scheme: &str,
+    ) -> PyResult<(Self, ObserverBase)> {
+        let scheme = Assignment::parse(scheme)?;
+        let grid = match bins {
+            Some(bins) => {
+                let settings = GridSettings { bins, ranges, scheme };
+                if let Some(ranges) = &settings.ranges {
+                    // check the grid now instead of failing during a run
+                    Grid::new(
+                        ranges.iter().map(|r| r.0).collect(),
+                        ranges.iter().map(|r| r.1).collect(),
+                        settings.bins.clone(),
+                    )?;
+                }
+                Some(settings)
+            }
+            None if ranges.is_some() => {
+                return Err(PyValueError::new_err("the grid ranges require the number of bins"));
+            }
+            None => None,
+        };
+
+        let path = PathBuf::from(path);
+        if path.file_stem().is_none() {
+            return Err(PyValueError::new_err("the collection path must name a file"));
+        }
+        let mut writer = create(&path)?;
+        write_pvd(&mut writer, &[])?;
+        writer.flush()?;
+
+        let (recorder, base) = ObserverBase::share(VtkRecorder {
+            interval: check_interval(interval)?,
+            path: Some(path),
+            grid,
+            datasets: Vec::new(),
+            frames: 0,
+        });
+        Ok((Self { recorder }, base))
+    }
+
+    /// The number of frames written by this writer.
+    #[getter]
+    pub fn frames(&self) -> PyResult<usize> {
+        Ok(lock(&self.recorder)?.frames)
+    }
+
+    /// Write the current state of a simulation as the next frame.
+    pub fn write(&self, particles: &Particles) -> PyResult<()> {
+        lock(&self.recorder)?.write(particles)
+    }
+
+    /// Close the collection. Writing further frames raises an error.
+    pub fn close(&self) -> PyResult<()> {
+        lock(&self.recorder)?.path = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,381 @@
+//! Trajectories in the [extended XYZ](https://github.com/libAtoms/extxyz) format,
+//! which can be opened with e.g. OVITO or VMD.
+//!
+//! Each frame consists of the number of particles, a comment line with the simulation time,
+//! step and the column layout, and one line per particle with its ID and any attributes
+//! as additional columns:
+//! ```text
+//! 2
+//! Properties=species:S:1:pos:R:3:velo:R:3:mass:R:1:id:I:1 Time=0.5 Step=50
+//! X 0.0 0.0 0.0 1.0 0.0 0.0 1.0 0
+//! X 1.5 0.0 0.0 -1.0 0.0 0.0 1.0 1
+//! ```
+
+use crate::io::frame_index;
+use crate::observer::{check_interval, lock, Observer, ObserverBase};
+use crate::particles::{check_attribute_name, Particles};
+use crate::vec3::Vec3;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+const PROPERTIES: &str = "species:S:1:pos:R:3:velo:R:3:mass:R:1:id:I:1";
+
+/// Write a single frame of the simulation. Floats are written in their shortest
+/// representation that reads back to the same value, so frames round-trip exactly.
+pub fn write_frame<W: Write>(writer: &mut W, particles: &Particles, species: &str) -> PyResult<()> {
+    let mut properties = PROPERTIES.to_string();
+    for name in particles.attributes.keys() {
+        properties.push_str(&format!(":{}:R:1", name));
+    }
+
+    writeln!(writer, "{}", particles.state.positions.len())?;
+    writeln!(
+        writer,
+        "Properties={} Time={:?} Step={}",
+        properties, particles.state.time, particles.state.step
+    )?;
+    for (i, (((x, v), m), id)) in particles
+        .state.positions
+        .iter()
+        .zip(particles.state.velocities.iter())
+        .zip(particles.state.masses.iter())
+        .zip(particles.ids.iter())
+        .enumerate()
+    {
+        write!(
+            writer,
+            "{} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {}",
+            species, x.x, x.y, x.z, v.x, v.y, v.z, m, id
+        )?;
+        for values in particles.attributes.values() {
+            write!(writer, " {:?}", values[i])?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Split the comment line into `key=value` pairs. Values may be quoted with double quotes.
+fn comment_fields(comment: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let mut chars = comment.trim().chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let key: String = std::iter::from_fn(|| chars.next_if(|&c| c != '=' && !c.is_whitespace())).collect();
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                value = std::iter::from_fn(|| chars.next_if(|&c| c != '"')).collect();
+                chars.next();
+            } else {
+                value = std::iter::from_fn(|| chars.next_if(|&c| !c.is_whitespace())).collect();
+            }
+        }
+        fields.push((key, value));
+    }
+
+    fields
+}
+
+/// The columns of the position, velocity, mass, ID and the attributes in a frame,
+/// from the `Properties` field. Any other scalar real or integer property is an attribute.
+#[derive(Debug, Default)]
+struct Columns {
+    pos: Option<usize>,
+    velo: Option<usize>,
+    mass: Option<usize>,
+    id: Option<usize>,
+    attributes: Vec<(String, usize)>,
+    count: usize,
+}
+
+impl Columns {
+    fn parse(properties: Option<&str>) -> PyResult<Self> {
+        // plain XYZ files have a species and the position
+        let properties = properties.unwrap_or("species:S:1:pos:R:3");
+        let parts: Vec<&str> = properties.split(':').collect();
+        if parts.len() % 3 != 0 {
+            return Err(PyValueError::new_err(format!("invalid Properties '{}'", properties)));
+        }
+
+        let mut columns = Self::default();
+        for property in parts.chunks(3) {
+            let width: usize = property[2]
+                .parse()
+                .map_err(|_| PyValueError::new_err(format!("invalid Properties '{}'", properties)))?;
+            let column = Some(columns.count);
+            match (property[0].to_lowercase().as_str(), width) {
+                ("pos", 3) => columns.pos = column,
+                ("velo", 3) | ("vel", 3) | ("velocities", 3) => columns.velo = column,
+                ("mass", 1) | ("masses", 1) => columns.mass = column,
+                ("id", 1) => columns.id = column,
+                (_, 1) if matches!(property[1], "R" | "I") && check_attribute_name(property[0]).is_ok() => {
+                    columns.attributes.push((property[0].to_string(), columns.count))
+                }
+                _ => {}
+            }
+            columns.count += width;
+        }
+
+        if columns.pos.is_none() {
+            return Err(PyValueError::new_err("the frame has no positions"));
+        }
+        Ok(columns)
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> PyResult<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+fn parse_count(line: &str) -> PyResult<usize> {
+    line.trim()
+        .parse()
+        .map_err(|_| PyValueError::new_err(format!("invalid number of particles '{}'", line.trim())))
+}
+
+/// Returns the byte offsets of all frames in an XYZ file.
+pub fn frame_offsets<R: BufRead + Seek>(reader: &mut R) -> PyResult<Vec<u64>> {
+    let mut offsets = Vec::new();
+    loop {
+        let offset = reader.stream_position()?;
+        let line = match read_line(reader)? {
+            Some(line) if !line.trim().is_empty() => line,
+            _ => break,
+        };
+        let n = parse_count(&line)?;
+        // skip the comment and particle lines
+        for _ in 0..n + 1 {
+            if read_line(reader)?.is_none() {
+                return Err(PyValueError::new_err("the last frame is incomplete"));
+            }
+        }
+        offsets.push(offset);
+    }
+    Ok(offsets)
+}
+
+/// Read the frame at the current position of the reader.
+/// Velocities default to zero, masses to one and IDs to the particle index if they are not part of the frame.
+/// Other scalar columns are read as attributes.
+pub fn read_frame<R: BufRead>(reader: &mut R) -> PyResult<Particles> {
+    let incomplete = || PyValueError::new_err("the frame is incomplete");
+    let n = parse_count(&read_line(reader)?.ok_or_else(incomplete)?)?;
+    let comment = read_line(reader)?.ok_or_else(incomplete)?;
+
+    let fields = comment_fields(&comment);
+    let field = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    };
+    let columns = Columns::parse(field("Properties"))?;
+
+    let mut particles = Particles::default();
+    if let Some(time) = field("Time") {
+        particles.state.time = time
+            .parse()
+            .map_err(|_| PyValueError::new_err(format!("invalid time '{}'", time)))?;
+    }
+    if let Some(step) = field("Step") {
+        particles.state.step = step
+            .parse()
+            .map_err(|_| PyValueError::new_err(format!("invalid step '{}'", step)))?;
+    }
+
+    let mut ids = Vec::with_capacity(n);
+    let mut attributes = vec![Vec::with_capacity(n); columns.attributes.len()];
+    for _ in 0..n {
+        let line = read_line(reader)?.ok_or_else(incomplete)?;
+        let values: Vec<&str> = line.split_whitespace().collect();
+        if values.len() < columns.count {
+            return Err(PyValueError::new_err(format!("expected {} columns in '{}'", columns.count, line.trim())));
+        }
+
+        let float = |i: usize| -> PyResult<f64> {
+            values[i]
+                .parse()
+                .map_err(|_| PyValueError::new_err(format!("invalid number '{}'", values[i])))
+        };
+        let vector = |column: Option<usize>| -> PyResult<Vec3> {
+            match column {
+                None => Ok(Vec3::default()),
+                Some(i) => Ok(Vec3::new(float(i)?, float(i + 1)?, float(i + 2)?)),
+            }
+        };
+
+        let mass = match columns.mass {
+            None => 1.0,
+            Some(i) => float(i)?,
+        };
+        particles.particle(vector(columns.pos)?, vector(columns.velo)?, mass);
+
+        for ((_, i), values) in columns.attributes.iter().zip(attributes.iter_mut()) {
+            values.push(float(*i)?);
+        }
+        if let Some(i) = columns.id {
+            ids.push(
+                values[i]
+                    .parse()
+                    .map_err(|_| PyValueError::new_err(format!("invalid ID '{}'", values[i])))?,
+            );
+        }
+    }
+
+    if columns.id.is_some() {
+        particles.set_ids(ids)?;
+    }
+    for ((name, _), values) in columns.attributes.into_iter().zip(attributes) {
+        particles.attributes.insert(name, values);
+    }
+    Ok(particles)
+}
+
+#[pymethods]
+impl Particles {
+    /// Read a frame of an extended XYZ file, e.g. as written by an [XyzWriter](../io/xyz/struct.XyzWriter.html).
+    /// The simulation time and step are read from the `Time` and `Step` fields of the comment line.
+    /// Velocities default to zero, masses to one and IDs to the particle index if they are not part of the file.
+    /// Other scalar columns are read as [attributes](../particles/struct.Particles.html#method.attribute).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the XYZ file.
+    /// * `frame` - The index of the frame, negative indices count from the end. Defaults to the last frame.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles = Particles.read_xyz("trajectory.xyz", frame=10)
+    /// ```
+    ///
+    #[staticmethod]
+    #[args(frame = "-1")]
+    pub fn read_xyz(path: &str, frame: isize) -> PyResult<Particles> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let offsets = frame_offsets(&mut reader)?;
+
+        reader.seek(SeekFrom::Start(offsets[frame_index(frame, offsets.len())?]))?;
+        read_frame(&mut reader)
+    }
+}
+
+#[derive(Debug)]
+struct XyzRecorder {
+    interval: usize,
+    species: String,
+    writer: Option<BufWriter<File>>,
+    frames: usize,
+}
+
+impl XyzRecorder {
+    fn write(&mut self, particles: &Particles) -> PyResult<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("the trajectory file is closed"))?;
+        write_frame(writer, particles, &self.species)?;
+        // flush every frame, so the trajectory is complete up to the last frame after a crash
+        writer.flush()?;
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+impl Observer for XyzRecorder {
+    fn interval(&self) -> usize {
+        self.interval
+    }
+
+    fn observe(&mut self, particles: &Particles) -> PyResult<()> {
+        self.write(particles)
+    }
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(extends=ObserverBase)]
+#[derive(Debug, Clone)]
+/// Writes frames of a simulation to an extended XYZ trajectory file, either on demand with
+/// [write](#method.write) or every `interval` steps when added to a simulation with
+/// [add_observer](../../particles/struct.Particles.html#method.add_observer).
+///
+/// # Arguments
+///
+/// * `path` - The path of the trajectory file.
+/// * `interval` - The number of steps between two frames written during a run.
+/// * `species` - The species name written for all particles, defaults to `"X"`.
+/// * `append` - Whether to append to an existing file instead of overwriting it.
+///
+/// # Examples
+///
+/// Python:
+/// ```python
+/// writer = XyzWriter("trajectory.xyz", interval=100, species="Ar")
+/// particles.add_observer(writer)
+/// particles.run(n=10000, h=0.01)
+/// writer.close()
+/// ```
+///
+pub struct XyzWriter {
+    recorder: Arc<Mutex<XyzRecorder>>,
+}
+
+#[pymethods]
+impl XyzWriter {
+    #[new]
+    #[args(interval = "1", species = "\"X\"", append = "false")]
+    pub fn new(path: &str, interval: usize, species: &str, append: bool) -> PyResult<(Self, ObserverBase)> {
+        if species.is_empty() || species.contains(char::is_whitespace) {
+            return Err(PyValueError::new_err("the species must be a non-empty name without whitespace"));
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+
+        let (recorder, base) = ObserverBase::share(XyzRecorder {
+            interval: check_interval(interval)?,
+            species: species.to_string(),
+            writer: Some(BufWriter::new(file)),
+            frames: 0,
+        });
+        Ok((Self { recorder }, base))
+    }
+
+    /// The number of frames written by this writer.
+    #[getter]
+    pub fn frames(&self) -> PyResult<usize> {
+        Ok(lock(&self.recorder)?.frames)
+    }
+
+    /// Write the current state of a simulation as the next frame.
+    pub fn write(&self, particles: &Particles) -> PyResult<()> {
+        lock(&self.recorder)?.write(particles)
+    }
+
+    /// Close the trajectory file. Writing further frames raises an error.
+    pub fn close(&self) -> PyResult<()> {
+        if let Some(mut writer) = lock(&self.recorder)?.writer.take() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
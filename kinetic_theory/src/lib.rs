@@ -7,9 +7,24 @@
 
 // Include files belonging to this library crate
 pub mod particles;
+pub mod particles_nd;
+pub mod simulation;
+pub mod snapshot;
+pub mod background;
 pub mod utils;
+pub mod vec2;
 pub mod vec3;
+pub mod vec3_f32;
+pub mod vector;
+pub mod mat3;
 pub mod constants;
+pub mod interaction;
+pub mod dynamics;
+pub mod analysis;
+pub mod cell_list;
+pub mod observer;
+pub mod progress;
+pub mod io;
 mod prelude;
 
 // use statements shorten syntax, analogous to C++'s "using"
@@ -35,8 +50,40 @@ fn build_module(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     // these build the python module, by telling PyO3 to create Python classes
     // the ? operator tells the current function to exit
     // with an error if an error happened in the preceding statement
+    m.add_class::<vec2::Vec2>()?;
     m.add_class::<vec3::Vec3>()?;
+    m.add_class::<mat3::Mat3>()?;
     m.add_class::<particles::Particles>()?;
+    m.add_class::<particles_nd::Particles2D>()?;
+    m.add_class::<particles_nd::Particles1D>()?;
+    m.add_class::<particles_nd::ParticlesF32>()?;
+    m.add_class::<background::BackgroundRun>()?;
+    m.add_class::<observer::ObserverBase>()?;
+    m.add_class::<progress::Progress>()?;
+    m.add_class::<snapshot::Snapshot>()?;
+    m.add_class::<snapshot::SnapshotObserver>()?;
+    m.add_class::<analysis::cluster::Clusters>()?;
+    m.add_class::<analysis::cluster_statistics::ClusterStatistics>()?;
+    m.add_class::<analysis::cluster_statistics::ClusterStatisticsObserver>()?;
+    m.add_class::<analysis::cluster_tracking::ClusterEvent>()?;
+    m.add_class::<analysis::cluster_tracking::ClusterTracker>()?;
+    m.add_class::<analysis::correlation::TimeCorrelation>()?;
+    m.add_class::<analysis::energy::EnergyObserver>()?;
+    m.add_class::<analysis::energy::TemperatureObserver>()?;
+    m.add_class::<analysis::grid::GridFields>()?;
+    m.add_class::<analysis::shape::Shape>()?;
+    m.add_class::<analysis::shape::CloudShape>()?;
+    m.add_class::<analysis::shape::ShapeObserver>()?;
+    m.add_class::<analysis::velocity_distribution::VelocityDistribution>()?;
+    m.add_class::<analysis::velocity_distribution::EquilibriumRecord>()?;
+    m.add_class::<analysis::velocity_distribution::VelocityDistributionObserver>()?;
+    #[cfg(feature = "h5md")]
+    m.add_class::<io::h5md::H5mdWriter>()?;
+    m.add_class::<io::lammps::LammpsDumpWriter>()?;
+    m.add_class::<io::trajectory::TrajectoryReader>()?;
+    m.add_class::<io::trajectory::TrajectoryWriter>()?;
+    m.add_class::<io::vtk::VtkWriter>()?;
+    m.add_class::<io::xyz::XyzWriter>()?;
 
     // if everything went fine, return the Ok Result
     Ok(())
@@ -0,0 +1,388 @@
+use crate::utils::symmetric_eigen;
+use crate::vec3::Vec3;
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, Mul, Neg, Sub};
+
+use numpy::PyArray2;
+use pyo3::basic::CompareOp;
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyTuple, PyType};
+use pyo3::{PyMappingProtocol, PyNativeType, PyNumberProtocol, PyObjectProtocol};
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(module = "particles")]
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+/// A 3x3 matrix, e.g. an inertia or stress tensor or a rotation.
+///
+/// # Examples
+///
+/// Python:
+/// ```python
+/// rotation = Mat3.rotation(Vec3(0.0, 0.0, 1.0), math.pi / 2)
+/// rotation * Vec3(1.0, 0.0, 0.0)  # Vec3(0.0, 1.0, 0.0)
+/// inertia = Mat3.identity() * (r * r) - r.outer(r)
+/// ```
+///
+pub struct Mat3 {
+    pub rows: [[f64; 3]; 3],
+}
+
+#[pymethods]
+impl Mat3 {
+    /// Create a matrix from a list of three rows, or a NumPy array of shape (3, 3).
+    #[new]
+    pub fn py_new(rows: &PyAny) -> PyResult<Self> {
+        let rows: Vec<Vec<f64>> = rows.extract()?;
+        if rows.len() != 3 || rows.iter().any(|row| row.len() != 3) {
+            return Err(PyValueError::new_err("expected three rows of three components"));
+        }
+
+        let mut matrix = Self::default();
+        for (target, row) in matrix.rows.iter_mut().zip(rows.iter()) {
+            target.copy_from_slice(row);
+        }
+        Ok(matrix)
+    }
+
+    #[staticmethod]
+    pub fn zeros() -> Self {
+        Self::default()
+    }
+
+    #[staticmethod]
+    pub fn identity() -> Self {
+        Self::diagonal(1.0, 1.0, 1.0)
+    }
+
+    #[staticmethod]
+    pub fn diagonal(x: f64, y: f64, z: f64) -> Self {
+        Self::new([[x, 0.0, 0.0], [0.0, y, 0.0], [0.0, 0.0, z]])
+    }
+
+    /// The matrix with the given vectors as its rows.
+    #[staticmethod]
+    pub fn from_rows(a: Vec3, b: Vec3, c: Vec3) -> Self {
+        Self::new([[a.x, a.y, a.z], [b.x, b.y, b.z], [c.x, c.y, c.z]])
+    }
+
+    /// The matrix with the given vectors as its columns.
+    #[staticmethod]
+    pub fn from_columns(a: Vec3, b: Vec3, c: Vec3) -> Self {
+        Self::from_rows(a, b, c).transpose()
+    }
+
+    /// The rotation by `angle` radians around `axis`, see [Vec3.rotate](../vec3/struct.Vec3.html#method.rotate).
+    #[staticmethod]
+    pub fn rotation(axis: Vec3, angle: f64) -> PyResult<Self> {
+        let u = axis.try_unit()?;
+        let (sin, cos) = angle.sin_cos();
+        let cross = Self::new([[0.0, -u.z, u.y], [u.z, 0.0, -u.x], [-u.y, u.x, 0.0]]);
+        Ok(Self::identity() * cos + cross * sin + u.outer(u) * (1.0 - cos))
+    }
+
+    /// The rotation by a quaternion `(w, x, y, z)`, see
+    /// [Vec3.rotate_quaternion](../vec3/struct.Vec3.html#method.rotate_quaternion).
+    #[staticmethod]
+    pub fn from_quaternion(quaternion: (f64, f64, f64, f64)) -> PyResult<Self> {
+        let (w, x, y, z) = unit_quaternion(quaternion)?;
+        Ok(Self::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+        ]))
+    }
+
+    /// The rows as a list of lists.
+    pub fn to_list(&self) -> Vec<Vec<f64>> {
+        self.rows.iter().map(|row| row.to_vec()).collect()
+    }
+
+    /// The matrix as a NumPy array of shape (3, 3).
+    pub fn to_array<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<f64>> {
+        Ok(PyArray2::from_vec2(py, &self.to_list())?)
+    }
+
+    // lets numpy.asarray convert matrices
+    #[args(dtype = "None")]
+    pub fn __array__<'py>(&self, py: Python<'py>, dtype: Option<&PyAny>) -> PyResult<&'py PyAny> {
+        let array = self.to_array(py)?;
+        match dtype {
+            Some(dtype) => array.call_method1("astype", (dtype,)),
+            None => Ok(array),
+        }
+    }
+
+    // NumPy defers to the operators of this class instead of converting it to an array, so e.g.
+    // `np.float64(2.0) * v` is a matrix and not an ndarray
+    #[classattr]
+    pub fn __array_ufunc__() -> Option<PyObject> {
+        None
+    }
+
+    pub fn row(&self, i: usize) -> PyResult<Vec3> {
+        let row = self.rows.get(i).ok_or_else(|| PyIndexError::new_err("Mat3 row out of range"))?;
+        Ok(Vec3::new(row[0], row[1], row[2]))
+    }
+
+    pub fn column(&self, j: usize) -> PyResult<Vec3> {
+        self.transpose().row(j)
+    }
+
+    pub fn transpose(&self) -> Self {
+        let m = &self.rows;
+        Self::new([
+            [m[0][0], m[1][0], m[2][0]],
+            [m[0][1], m[1][1], m[2][1]],
+            [m[0][2], m[1][2], m[2][2]],
+        ])
+    }
+
+    pub fn trace(&self) -> f64 {
+        self.rows[0][0] + self.rows[1][1] + self.rows[2][2]
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let [a, b, c] = self.row_vectors();
+        a * b.cross(c)
+    }
+
+    /// The inverse matrix, or an error if the matrix is singular.
+    pub fn inverse(&self) -> PyResult<Self> {
+        let determinant = self.determinant();
+        if determinant == 0.0 || !determinant.is_finite() {
+            return Err(PyValueError::new_err("cannot invert a singular matrix"));
+        }
+        // the columns of the inverse are the cross products of the rows
+        let [a, b, c] = self.row_vectors();
+        Ok(Self::from_columns(b.cross(c), c.cross(a), a.cross(b)) * (1.0 / determinant))
+    }
+
+    pub fn is_symmetric(&self) -> bool {
+        *self == self.transpose()
+    }
+
+    /// The eigenvalues in descending order and the corresponding normalized eigenvectors
+    /// of a symmetric matrix, e.g. the principal moments and axes of an inertia tensor.
+    /// Only the upper triangle of the matrix is used.
+    pub fn symmetric_eigen(&self) -> (Vec<f64>, Vec<Vec3>) {
+        let (values, vectors) = symmetric_eigen(self.rows);
+        let vectors = vectors.iter().map(|v| Vec3::new(v[0], v[1], v[2])).collect();
+        (values.to_vec(), vectors)
+    }
+
+    // pickle and copy support, the state is the serialized matrix
+
+    pub fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        let bytes = bincode::serialize(self)
+            .map_err(|e| PyValueError::new_err(format!("could not serialize Mat3: {}", e)))?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    pub fn __setstate__(&mut self, state: &PyBytes) -> PyResult<()> {
+        *self = bincode::deserialize(state.as_bytes())
+            .map_err(|e| PyValueError::new_err(format!("could not deserialize Mat3: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(&'py PyType, &'py PyTuple, &'py PyBytes)> {
+        let args = PyTuple::new(py, &[self.to_list()]);
+        Ok((py.get_type::<Self>(), args, self.__getstate__(py)?))
+    }
+
+    pub fn __copy__(&self) -> Self {
+        *self
+    }
+
+    pub fn __deepcopy__(&self, _memo: &PyAny) -> Self {
+        *self
+    }
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl Mat3 {
+    pub fn new(rows: [[f64; 3]; 3]) -> Self {
+        Self { rows }
+    }
+
+    pub fn row_vectors(&self) -> [Vec3; 3] {
+        self.rows.map(|row| Vec3::new(row[0], row[1], row[2]))
+    }
+
+    fn map(self, f: impl Fn(f64) -> f64) -> Self {
+        Self::new(self.rows.map(|row| row.map(&f)))
+    }
+
+    fn zip(self, other: Self, f: impl Fn(f64, f64) -> f64) -> Self {
+        let mut result = self;
+        for (row, other) in result.rows.iter_mut().zip(other.rows.iter()) {
+            for (a, b) in row.iter_mut().zip(other.iter()) {
+                *a = f(*a, *b);
+            }
+        }
+        result
+    }
+}
+
+/// Normalize a quaternion `(w, x, y, z)`, or return an error for a zero quaternion.
+pub(crate) fn unit_quaternion(q: (f64, f64, f64, f64)) -> PyResult<(f64, f64, f64, f64)> {
+    let norm = (q.0 * q.0 + q.1 * q.1 + q.2 * q.2 + q.3 * q.3).sqrt();
+    if norm == 0.0 || !norm.is_finite() {
+        return Err(PyValueError::new_err("cannot rotate by a zero or non-finite quaternion"));
+    }
+    Ok((q.0 / norm, q.1 / norm, q.2 / norm, q.3 / norm))
+}
+
+/// Wrap a negative row or column index around like Python does, and check its range.
+fn wrap_index(i: isize) -> PyResult<usize> {
+    let i = if i < 0 { i + 3 } else { i };
+    if !(0..3).contains(&i) {
+        return Err(PyIndexError::new_err("Mat3 index out of range"));
+    }
+    Ok(i as usize)
+}
+
+impl Add for Mat3 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.zip(other, |a, b| a + b)
+    }
+}
+
+impl Sub for Mat3 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.zip(other, |a, b| a - b)
+    }
+}
+
+impl Neg for Mat3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        self.map(|a| -a)
+    }
+}
+
+impl Mul<f64> for Mat3 {
+    type Output = Self;
+
+    fn mul(self, other: f64) -> Self {
+        self.map(|a| a * other)
+    }
+}
+
+impl Mul<Mat3> for f64 {
+    type Output = Mat3;
+
+    fn mul(self, other: Mat3) -> Mat3 {
+        other * self
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, other: Vec3) -> Vec3 {
+        let [a, b, c] = self.row_vectors();
+        Vec3::new(a * other, b * other, c * other)
+    }
+}
+
+impl Mul<Mat3> for Mat3 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let columns = other.transpose().row_vectors();
+        Self::new(self.row_vectors().map(|row| columns.map(|column| row * column)))
+    }
+}
+
+// Python operators, see https://docs.python.org/3/reference/datamodel.html#emulating-numeric-types
+#[pyproto]
+impl PyNumberProtocol for Mat3 {
+    fn __add__(lhs: Mat3, rhs: Mat3) -> Mat3 {
+        lhs + rhs
+    }
+
+    fn __sub__(lhs: Mat3, rhs: Mat3) -> Mat3 {
+        lhs - rhs
+    }
+
+    /// Multiplication with a matrix or a vector is the matrix product, with a number it scales the matrix.
+    fn __mul__(lhs: Mat3, rhs: &PyAny) -> PyObject {
+        let py = rhs.py();
+        if let Ok(other) = rhs.extract::<Mat3>() {
+            (lhs * other).into_py(py)
+        } else if let Ok(other) = rhs.extract::<Vec3>() {
+            (lhs * other).into_py(py)
+        } else if let Ok(factor) = rhs.extract::<f64>() {
+            (lhs * factor).into_py(py)
+        } else {
+            py.NotImplemented()
+        }
+    }
+
+    fn __rmul__(&self, factor: f64) -> Mat3 {
+        factor * *self
+    }
+
+    fn __matmul__(lhs: Mat3, rhs: &PyAny) -> PyObject {
+        Self::__mul__(lhs, rhs)
+    }
+
+    fn __truediv__(lhs: Mat3, rhs: f64) -> Mat3 {
+        lhs.map(|a| a / rhs)
+    }
+
+    fn __neg__(&self) -> Mat3 {
+        -*self
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for Mat3 {
+    fn __repr__(&self) -> String {
+        let rows: Vec<String> = self
+            .rows
+            .iter()
+            .map(|row| format!("[{:?}, {:?}, {:?}]", row[0], row[1], row[2]))
+            .collect();
+        format!("Mat3([{}])", rows.join(", "))
+    }
+
+    // matrices are mutable, so they are compared by value but not hashable
+    fn __richcmp__(&self, other: &PyAny, op: CompareOp) -> PyObject {
+        let py = other.py();
+        match (other.extract::<Mat3>(), op) {
+            (Ok(other), CompareOp::Eq) => (*self == other).into_py(py),
+            (Ok(other), CompareOp::Ne) => (*self != other).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+}
+
+#[pyproto]
+impl PyMappingProtocol for Mat3 {
+    fn __len__(&self) -> usize {
+        3
+    }
+
+    /// The element `m[i, j]`, or the row `m[i]` as a `Vec3`.
+    fn __getitem__(&self, index: &PyAny) -> PyResult<PyObject> {
+        let py = index.py();
+        if let Ok((i, j)) = index.extract::<(isize, isize)>() {
+            Ok(self.rows[wrap_index(i)?][wrap_index(j)?].into_py(py))
+        } else {
+            Ok(self.row(wrap_index(index.extract()?)?)?.into_py(py))
+        }
+    }
+
+    fn __setitem__(&mut self, index: (isize, isize), value: f64) -> PyResult<()> {
+        self.rows[wrap_index(index.0)?][wrap_index(index.1)?] = value;
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+//! Observers record quantities of a simulation at regular intervals during
+//! [`Particles::run`](../particles/struct.Particles.html#method.run), and can stop the run early.
+//! Besides the observer classes implemented in Rust, Python callables can be used as observers.
+
+use crate::particles::Particles;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use pyo3::exceptions::{PyRuntimeError, PyTypeError};
+use pyo3::prelude::*;
+
+/// Something that observes the state of a simulation every `interval` steps.
+pub trait Observer: Debug + Send {
+    /// The number of steps between two observations
+    fn interval(&self) -> usize;
+
+    /// Observe the current state of the simulation
+    fn observe(&mut self, particles: &Particles) -> PyResult<()>;
+
+    /// Whether the run should stop after the last observation, e.g. because a limit was exceeded
+    fn stop_requested(&self) -> bool {
+        false
+    }
+}
+
+/// Observers are shared between the Python object that records the results
+/// and the simulation that feeds it.
+pub type SharedObserver = Arc<Mutex<dyn Observer>>;
+
+/// Lock a shared observer or recorder, converting a poisoned lock into a Python error.
+pub fn lock<T: ?Sized>(observer: &Mutex<T>) -> PyResult<MutexGuard<'_, T>> {
+    observer
+        .lock()
+        .map_err(|_| PyRuntimeError::new_err("an observer panicked while recording"))
+}
+
+/// Check that an observation interval is valid.
+pub fn check_interval(interval: usize) -> PyResult<usize> {
+    if interval == 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "the observation interval must be at least 1",
+        ));
+    }
+    Ok(interval)
+}
+
+/// A Python callable, which is called with the simulation every `interval` steps during a run.
+/// It stops the run by returning `True`.
+#[derive(Debug, Clone)]
+pub struct CallbackObserver {
+    pub callback: PyObject,
+    pub interval: usize,
+}
+
+/// Call the callbacks which are due at `step` with the simulation object, and return whether
+/// any of them requested to stop. The simulation must not be borrowed, so the callbacks can use it.
+pub fn notify_callbacks(
+    py: Python<'_>,
+    callbacks: &[CallbackObserver],
+    step: usize,
+    simulation: &PyAny,
+) -> PyResult<bool> {
+    let mut stop = false;
+    for observer in callbacks {
+        if step % observer.interval == 0 {
+            stop |= observer.callback.call1(py, (simulation,))?.as_ref(py).is_true()?;
+        }
+    }
+    Ok(stop)
+}
+
+/// The quantities recorded by an observer class, see [Recorder](struct.Recorder.html).
+pub trait Record: Debug + Send {
+    /// Record the quantities of the current state of the simulation
+    fn record(&mut self, particles: &Particles) -> PyResult<()>;
+
+    /// Forget all recorded quantities
+    fn clear(&mut self);
+
+    /// Whether the run should stop after the last observation, see [Observer](trait.Observer.html)
+    fn stop_requested(&self) -> bool {
+        false
+    }
+}
+
+/// The observer behind the recording observer classes, which records the quantities `T`
+/// and the simulation time every `interval` steps.
+#[derive(Debug)]
+pub struct Recorder<T> {
+    pub interval: usize,
+    pub times: Vec<f64>,
+    pub data: T,
+}
+
+impl<T: Record> Recorder<T> {
+    pub fn new(interval: usize, data: T) -> PyResult<Self> {
+        Ok(Self {
+            interval: check_interval(interval)?,
+            times: Vec::new(),
+            data,
+        })
+    }
+
+    /// Forget all recorded times and quantities.
+    pub fn clear(&mut self) {
+        self.times.clear();
+        self.data.clear();
+    }
+}
+
+impl<T: Record> Observer for Recorder<T> {
+    fn interval(&self) -> usize {
+        self.interval
+    }
+
+    fn observe(&mut self, particles: &Particles) -> PyResult<()> {
+        self.data.record(particles)?;
+        self.times.push(particles.state.time);
+        Ok(())
+    }
+
+    fn stop_requested(&self) -> bool {
+        self.data.stop_requested()
+    }
+}
+
+/// Adds the `times` getter and the `clear` method to an observer class whose `recorder`
+/// is a shared [Recorder](struct.Recorder.html).
+macro_rules! recorder_methods {
+    ($class:ident) => {
+        #[pymethods]
+        impl $class {
+            /// The times of the recorded observations.
+            #[getter]
+            pub fn times(&self) -> PyResult<Vec<f64>> {
+                Ok(lock(&self.recorder)?.times.clone())
+            }
+
+            /// Forget all recorded observations.
+            pub fn clear(&self) -> PyResult<()> {
+                lock(&self.recorder)?.clear();
+                Ok(())
+            }
+        }
+    };
+}
+
+pub(crate) use recorder_methods;
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(subclass)]
+#[derive(Debug)]
+/// The base class of all observer classes implemented in Rust, which holds the observer
+/// notified by the simulation. Use e.g. `isinstance(observer, ObserverBase)` to check for one.
+pub struct ObserverBase {
+    observer: SharedObserver,
+}
+
+#[pymethods]
+impl ObserverBase {
+    /// The number of steps between two observations.
+    #[getter]
+    pub fn interval(&self) -> PyResult<usize> {
+        Ok(lock(&self.observer)?.interval())
+    }
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl ObserverBase {
+    /// Share an observer between an observer class, which keeps the returned handle
+    /// to access its results, and the returned base class, which is handed to the simulation.
+    pub fn share<O: Observer + 'static>(observer: O) -> (Arc<Mutex<O>>, Self) {
+        let observer = Arc::new(Mutex::new(observer));
+        (observer.clone(), Self { observer })
+    }
+}
+
+/// Extract the shared observer from any of the Python observer classes.
+pub fn extract_observer(obj: &PyAny) -> PyResult<SharedObserver> {
+    match obj.extract::<PyRef<'_, ObserverBase>>() {
+        Ok(base) => Ok(base.observer.clone()),
+        Err(_) => Err(PyTypeError::new_err(format!(
+            "{} is not an observer",
+            obj.get_type().name()
+        ))),
+    }
+}
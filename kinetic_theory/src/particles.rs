@@ -1,29 +1,40 @@
 /// This is the actual Particle Simulation Class file
 
-use crate::utils::approx_equal;
+use crate::observer::{check_interval, extract_observer, lock, CallbackObserver, SharedObserver};
+use crate::progress::ProgressReporter;
+use crate::simulation::{run_steps, Simulation, State};
 use crate::vec3::Vec3;
+use crate::vector::Vector;
 use itertools::izip;
-use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::vec::Vec;
 
+use numpy::{PyArray, PyArray1, PyArray2, PyReadonlyArray1, PyReadonlyArray2};
+use pyo3::exceptions::{PyIndexError, PyKeyError, PyValueError};
 use pyo3::prelude::*;
 use pyo3::types::PyTuple;
+use pyo3::PyNativeType;
 
 // Tell PyO3 to make this class accessible from Python
-#[pyclass]
+#[pyclass(module = "particles")]
 // Tell Rust to automatically generate the Debug, Clone and Default Trait
 #[derive(Debug, Clone, Default)]
 /// This struct represents an N-Particle Simulation
 // it uses a data-oriented layout, individual Particles exist only implicitly
 pub struct Particles {
-    // Each Particle has a position, velocity and mass
-    positions: Vec<Vec3>,
-    velocities: Vec<Vec3>,
-    masses: Vec<f64>,
-    // This is the optionally given external Potential
-    potential: Option<PyObject>,
+    // Each Particle has a position, velocity and mass, which are stored together with
+    // the optionally given external Potential, the time and the step like in any dimension
+    pub(crate) state: State<Vec3>,
+    // Stable IDs, which identify particles across removals
+    pub(crate) ids: Vec<u64>,
+    // The ID given to the next added particle
+    pub(crate) next_id: u64,
+    // Named user attributes, with one value per particle
+    pub(crate) attributes: BTreeMap<String, Vec<f64>>,
+    // Observers notified during run, before the Python callables of the state
+    pub(crate) observers: Vec<SharedObserver>,
 }
 
 // These are Python-exposed methods
@@ -39,6 +50,23 @@ impl Particles {
         Self::default()
     }
 
+    /// The simulation time, advanced by every time step
+    #[getter]
+    pub fn time(&self) -> f64 {
+        self.state.time
+    }
+
+    #[setter]
+    pub fn set_time(&mut self, time: f64) {
+        self.state.time = time;
+    }
+
+    /// The number of time steps performed so far
+    #[getter]
+    pub fn step(&self) -> usize {
+        self.state.step
+    }
+
     /// Set the external potential to some Python Function.
     ///
     /// # Arguments
@@ -65,17 +93,17 @@ impl Particles {
     /// ```
     ///
     pub fn set_potential(&mut self, potential: PyObject) {
-        self.potential = Some(potential);
+        self.state.potential = Some(potential);
     }
 
     /// Check if there is an external potential set
     pub fn has_potential(&self) -> bool {
-        self.potential.is_some()
+        self.state.potential.is_some()
     }
 
     /// Unset the external potential.
     pub fn unset_potential(&mut self) {
-        self.potential = None;
+        self.state.potential = None;
     }
 
     /// Add a particle to the Simulation.
@@ -97,8 +125,214 @@ impl Particles {
     /// )
     /// ```
     ///
-    pub fn add_particle(&mut self, x: Vec3, v: Vec3, m: f64) {
+    /// Returns the ID of the new particle, see [ids](#method.ids).
+    pub fn add_particle(&mut self, x: Vec3, v: Vec3, m: f64) -> u64 {
         self.particle(x, v, m);
+        self.next_id - 1
+    }
+
+    /// Add many particles to the Simulation at once.
+    /// Returns the IDs of the new particles, see [ids](#method.ids).
+    ///
+    /// # Arguments
+    ///
+    /// * `positions` - A NumPy array of shape (N, 3), or a sequence of N `Vec3` or
+    /// sequences of three numbers.
+    /// * `velocities` - The velocities, in the same form as the positions.
+    /// * `masses` - A NumPy array of shape (N,), a sequence of N numbers, or a single mass for all particles.
+    /// * `attributes` - An optional dictionary of attribute values of the new particles,
+    /// in the same form as the masses, see [set_attribute](#method.set_attribute).
+    /// Attributes which are not given are zero for the new particles.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// positions = np.random.normal(0.0, 10.0, size=(1000, 3))
+    /// velocities = np.random.normal(0.0, 1.0, size=(1000, 3))
+    /// particles.add_particles(positions, velocities, 1.0)
+    /// ```
+    ///
+    #[args(attributes = "None")]
+    pub fn add_particles(
+        &mut self,
+        positions: &PyAny,
+        velocities: &PyAny,
+        masses: &PyAny,
+        attributes: Option<HashMap<String, &PyAny>>,
+    ) -> PyResult<Vec<u64>> {
+        let positions = extract_vectors(positions, "positions", None)?;
+        let n = positions.len();
+        let velocities = extract_vectors(velocities, "velocities", Some(n))?;
+        let masses = extract_scalars(masses, "masses", n)?;
+        let attributes = attributes
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, values)| {
+                check_attribute_name(&name)?;
+                let values = extract_scalars(values, &name, n)?;
+                Ok((name, values))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let (start, first) = (self.num_particles(), self.next_id);
+        for ((x, v), m) in positions.into_iter().zip(velocities).zip(masses) {
+            self.particle(x, v, m);
+        }
+        for (name, values) in attributes {
+            let column = self
+                .attributes
+                .entry(name)
+                .or_insert_with(|| vec![0.0; start + n]);
+            column[start..].copy_from_slice(&values);
+        }
+        Ok((first..self.next_id).collect())
+    }
+
+    /// Remove particles from the Simulation by their index.
+    /// The remaining particles keep their order and their IDs.
+    /// Returns the number of removed particles.
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - The indices of the particles to remove, negative indices count from the end.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles.remove_particles([0, -1])
+    /// ```
+    ///
+    pub fn remove_particles(&mut self, indices: Vec<isize>) -> PyResult<usize> {
+        let n = self.num_particles();
+        let mut keep = vec![true; n];
+        for index in indices {
+            let i = if index < 0 { n as isize + index } else { index };
+            if i < 0 || i as usize >= n {
+                return Err(PyIndexError::new_err(format!(
+                    "index {} out of range for {} particles",
+                    index, n
+                )));
+            }
+            keep[i as usize] = false;
+        }
+        Ok(self.retain(&keep))
+    }
+
+    /// Remove all particles for which the mask is true, e.g. to model evaporation
+    /// or an absorbing boundary. The remaining particles keep their order and their IDs.
+    /// Returns the number of removed particles.
+    ///
+    /// # Arguments
+    ///
+    /// * `mask` - A boolean NumPy array of shape (N,) or a sequence of N booleans.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// x = particles.positions_array()
+    /// particles.remove_where(np.linalg.norm(x, axis=1) > 100.0)
+    /// ```
+    ///
+    pub fn remove_where(&mut self, mask: &PyAny) -> PyResult<usize> {
+        let n = self.num_particles();
+        let array = if numpy_loaded(mask.py()) {
+            mask.extract::<PyReadonlyArray1<'_, bool>>().ok()
+        } else {
+            None
+        };
+        let mask: Vec<bool> = match array {
+            Some(array) => array.as_array().to_vec(),
+            None => mask.extract()?,
+        };
+        if mask.len() != n {
+            return Err(PyValueError::new_err(format!(
+                "expected a mask of {} booleans, got {}",
+                n,
+                mask.len()
+            )));
+        }
+
+        let keep: Vec<bool> = mask.iter().map(|remove| !remove).collect();
+        Ok(self.retain(&keep))
+    }
+
+    /// Query the IDs of all particles. Each particle is given a unique ID when it is added,
+    /// which it keeps when other particles are removed, so particles can be matched between
+    /// states of the simulation even when their indices change.
+    pub fn ids(&self) -> Vec<u64> {
+        self.ids.clone()
+    }
+
+    /// Set the IDs of all particles, e.g. to match particles with those of another simulation.
+    /// The IDs must be unique. Particles added later are given IDs above the largest one.
+    pub fn set_ids(&mut self, ids: Vec<u64>) -> PyResult<()> {
+        if ids.len() != self.num_particles() {
+            return Err(PyValueError::new_err(format!(
+                "expected {} IDs, got {}",
+                self.num_particles(),
+                ids.len()
+            )));
+        }
+        let mut sorted = ids.clone();
+        sorted.sort_unstable();
+        if sorted.windows(2).any(|w| w[0] == w[1]) {
+            return Err(PyValueError::new_err("particle IDs must be unique"));
+        }
+
+        self.next_id = sorted.last().map_or(0, |id| id + 1);
+        self.ids = ids;
+        Ok(())
+    }
+
+    /// Query the names of all per-particle attributes, in alphabetical order.
+    pub fn attribute_names(&self) -> Vec<String> {
+        self.attributes.keys().cloned().collect()
+    }
+
+    /// Query the values of a per-particle attribute, in the order of the particles.
+    pub fn attribute(&self, name: &str) -> PyResult<Vec<f64>> {
+        Ok(self.attribute_values(name)?.clone())
+    }
+
+    /// Analogous to [attribute](#method.attribute), as a NumPy array of shape (N,).
+    pub fn attribute_array<'py>(&self, py: Python<'py>, name: &str) -> PyResult<&'py PyArray1<f64>> {
+        Ok(PyArray::from_slice(py, self.attribute_values(name)?))
+    }
+
+    /// Set a named per-particle attribute, e.g. a charge or a tag, which is kept when particles
+    /// are removed and stored with the simulation state. Particles added later have the value zero,
+    /// unless given to [add_particles](#method.add_particles).
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the attribute. Names consist of letters, digits and underscores,
+    /// and do not start with a digit. Names of the built-in data, like `mass` or `id`, are reserved.
+    /// * `values` - A NumPy array of shape (N,), a sequence of N numbers, or a single value for all particles.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles.set_attribute("initial_energy", 0.5 * m * (v ** 2).sum(axis=1))
+    /// particles.set_attribute("tag", 0.0)
+    /// ```
+    ///
+    pub fn set_attribute(&mut self, name: &str, values: &PyAny) -> PyResult<()> {
+        check_attribute_name(name)?;
+        let values = extract_scalars(values, name, self.num_particles())?;
+        self.attributes.insert(name.to_string(), values);
+        Ok(())
+    }
+
+    /// Remove a per-particle attribute.
+    pub fn remove_attribute(&mut self, name: &str) -> PyResult<()> {
+        self.attributes
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| PyKeyError::new_err(format!("no attribute '{}'", name)))
     }
 
 
@@ -106,32 +340,116 @@ impl Particles {
     /// This method is preferred to manually updating in a Python `for`-loop, since
     /// it prevents swapping between Rust and Python Execution in every step.
     ///
+    /// The GIL is released during the time steps if no Python potential is set, so other Python
+    /// threads keep running. Ctrl-C raises `KeyboardInterrupt` after the current step, leaving the
+    /// simulation in the state of the last completed step. See also [run_background](#method.run_background).
     ///
     /// # Arguments
     ///
     /// * `n` - The number of time steps to perform
     /// * `h` - The fixed size of the time step
     ///
-    pub fn run(&mut self, n: usize, h: f64) -> PyResult<()> {
-        for _ in 0..n {
-            self.update_yoshida(h)?;
-        }
+    pub fn run(slf: &PyCell<Self>, n: usize, h: f64) -> PyResult<()> {
+        run_steps(slf, n, h, |_| Ok(()))
+    }
 
+    /// Add an observer which records the state of the simulation at its interval during
+    /// [run](#method.run). Observers are notified after every step whose number is a multiple
+    /// of their interval, including the initial state at step 0.
+    ///
+    /// Observers can stop the run early, e.g. a `ShapeObserver` with a `max_radius`. The run then
+    /// ends after the step at which the observer requested it.
+    ///
+    /// # Arguments
+    ///
+    /// * `observer` - The observer, e.g. a `ShapeObserver`, `EnergyObserver` or `SnapshotObserver`,
+    /// or a Python callable, which is called with the simulation and stops the run by returning `True`.
+    /// * `interval` - The number of steps between two calls of a Python callable, 1 by default.
+    /// The other observers are given their interval when they are created.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// observer = ShapeObserver(interval=10)
+    /// particles.add_observer(observer)
+    /// particles.add_observer(lambda ps: ps.shape().position.rms_radius > 100.0, interval=10)
+    /// particles.run(n=1000, h=0.01)
+    /// aspect_ratios = [s.position.aspect_ratio for s in observer.shapes]
+    /// ```
+    ///
+    #[args(interval = "None")]
+    pub fn add_observer(&mut self, observer: &PyAny, interval: Option<usize>) -> PyResult<()> {
+        match extract_observer(observer) {
+            Ok(shared) => {
+                if interval.is_some() {
+                    return Err(PyValueError::new_err(
+                        "the interval of an observer is given when it is created",
+                    ));
+                }
+                self.observers.push(shared);
+            }
+            Err(_) if observer.is_callable() => self.state.callbacks.push(CallbackObserver {
+                callback: observer.into(),
+                interval: check_interval(interval.unwrap_or(1))?,
+            }),
+            Err(e) => return Err(e),
+        }
         Ok(())
     }
 
-    // hidden doc until this function is actually implemented
-    // currently using the utils::timer function conflicts with the python gil,
-    // which can never be acquired from a threaded context
-    // but acquiring the gil is necessary to call the python-defined potential
-    #[doc(hidden)]
-    /// Run the simulation and display a continually updating timer.
-    /// See also:
-    /// - [run](#method.run)
-    /// - [utils::timer](../utils/fn.timer.html)
+    /// Remove all observers, including Python callables, from the simulation.
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+        self.state.callbacks.clear();
+    }
+
+    /// Like [run](#method.run), but report the progress: the completed percentage, the number
+    /// of steps per second and the estimated time remaining.
+    ///
+    /// By default, the progress is shown in a continually updated line on `sys.stdout`.
+    /// If a `callback` is given, it is called with a `Progress` instead, e.g. to update a progress bar.
+    /// Progress is reported at the start, at most every `interval` seconds and once more when the run
+    /// ends, with the final number of steps and the `success` of the run, also if an observer stopped it
+    /// early or it failed. If the run fails, the error is raised after this final report.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of time steps to perform
+    /// * `h` - The fixed size of the time step
+    /// * `callback` - An optional function which takes a `Progress`
+    /// * `interval` - The minimum time between two reports in seconds, `inf` only reports the start
+    ///   and the end
     ///
-    pub fn run_timer(&mut self, n: usize, h: f64) -> PyResult<()> {
-        unimplemented!()
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles.run_timer(n=1000, h=0.01)
+    ///
+    /// with tqdm(total=1000) as bar:
+    ///     particles.run_timer(n=1000, h=0.01, callback=lambda p: bar.update(p.steps - bar.n))
+    /// ```
+    ///
+    #[args(callback = "None", interval = "0.25")]
+    pub fn run_timer(
+        slf: &PyCell<Self>,
+        n: usize,
+        h: f64,
+        callback: Option<PyObject>,
+        interval: f64,
+    ) -> PyResult<()> {
+        if interval.is_nan() || interval < 0.0 {
+            return Err(PyValueError::new_err(format!(
+                "interval must be a non-negative number of seconds, got {}",
+                interval
+            )));
+        }
+        let py = slf.py();
+        let mut reporter = ProgressReporter::new(py, n, interval, callback)?;
+        let result = run_steps(slf, n, h, |steps| reporter.update(py, steps));
+        reporter.finish(py, &result)?;
+        result
     }
 
     /// Update the state of the simulation by performing a single time step of given size.
@@ -148,38 +466,90 @@ impl Particles {
     /// * `h` - The size of the time step
     ///
     pub fn update_yoshida(&mut self, h: f64) -> PyResult<()> {
-        use crate::constants::yoshida::{C14, D13, C23, D2};
-
-        self.update_yoshida_positions(C14, h);
-        self.update_yoshida_velocities(D13, h)?;
-        self.update_yoshida_positions(C23, h);
-        self.update_yoshida_velocities(D2, h)?;
-        self.update_yoshida_positions(C23, h);
-        self.update_yoshida_velocities(D13, h)?;
-        self.update_yoshida_positions(C14, h);
-
-        Ok(())
+        self.state.update_yoshida(h)
     }
 
     /// Query the positions of all particles in the simulation.
     /// Positions are returned in the same ordering as particles were originally defined.
     pub fn positions(&self) -> Vec<Vec3> {
-        self.positions.clone()
+        self.state.positions.clone()
     }
 
     /// Analogous to [positions](#method.positions).
     pub fn velocities(&self) -> Vec<Vec3> {
-        self.velocities.clone()
+        self.state.velocities.clone()
     }
 
     /// Analogous to [positions](#method.positions).
     pub fn masses(&self) -> Vec<f64> {
-        self.masses.clone()
+        self.state.masses.clone()
+    }
+
+    /// Query the positions of all particles as a NumPy array of shape (N, 3).
+    /// This is much faster than [positions](#method.positions) for many particles,
+    /// since no `Vec3` objects are created.
+    ///
+    /// The array is a copy, because the simulation replaces its buffers during every time step
+    /// and whenever particles are added, which would invalidate a view.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// x = particles.positions_array()
+    /// plt.plot(x[:, 0], x[:, 1], lw=0, marker=".")
+    /// ```
+    ///
+    pub fn positions_array<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<f64>> {
+        vector_array(py, &self.state.positions)
+    }
+
+    /// Analogous to [positions_array](#method.positions_array).
+    pub fn velocities_array<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<f64>> {
+        vector_array(py, &self.state.velocities)
+    }
+
+    /// Query the masses of all particles as a NumPy array of shape (N,).
+    pub fn masses_array<'py>(&self, py: Python<'py>) -> &'py PyArray1<f64> {
+        PyArray::from_slice(py, &self.state.masses)
+    }
+
+    /// Set the positions of all particles at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `positions` - A NumPy array of shape (N, 3), or a sequence of N `Vec3` or
+    /// sequences of three numbers, where N is the number of particles.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// x = particles.positions_array()
+    /// particles.set_positions(x - x.mean(axis=0))
+    /// ```
+    ///
+    pub fn set_positions(&mut self, positions: &PyAny) -> PyResult<()> {
+        self.state.positions = extract_vectors(positions, "positions", Some(self.num_particles()))?;
+        Ok(())
+    }
+
+    /// Analogous to [set_positions](#method.set_positions).
+    pub fn set_velocities(&mut self, velocities: &PyAny) -> PyResult<()> {
+        self.state.velocities = extract_vectors(velocities, "velocities", Some(self.num_particles()))?;
+        Ok(())
+    }
+
+    /// Set the masses of all particles at once, from a NumPy array of shape (N,), a sequence of N numbers
+    /// or a single mass for all particles.
+    pub fn set_masses(&mut self, masses: &PyAny) -> PyResult<()> {
+        self.state.masses = extract_scalars(masses, "masses", self.num_particles())?;
+        Ok(())
     }
 
     /// Query the number of particles currently present in the simulation
     pub fn num_particles(&self) -> usize {
-        self.positions.len()
+        self.state.positions.len()
     }
 }
 
@@ -191,96 +561,193 @@ impl Particles {
     #[doc(hidden)]
     pub fn particle(&mut self, x: Vec3, v: Vec3, m: f64) -> &mut Self {
         // this struct is its own builder
-        self.positions.push(x);
-        self.velocities.push(v);
-        self.masses.push(m);
+        self.state.particle(x, v, m);
+        self.ids.push(self.next_id);
+        self.next_id += 1;
+        for values in self.attributes.values_mut() {
+            values.push(0.0);
+        }
 
         self
     }
 
     #[doc(hidden)]
-    fn update_yoshida_positions(&mut self, c: f64, h: f64) {
-    self.positions = self
-        .positions
-        .iter()
-        .zip(self.velocities.iter())
-        .map(|(p, v)| p + c * v * h)
-        .collect();
+    pub fn attribute_values(&self, name: &str) -> PyResult<&Vec<f64>> {
+        self.attributes
+            .get(name)
+            .ok_or_else(|| PyKeyError::new_err(format!("no attribute '{}'", name)))
     }
 
+    /// Keep only the particles for which `keep` is true, returns the number of removed particles.
     #[doc(hidden)]
-    fn update_yoshida_velocities(&mut self, d: f64, h: f64) -> PyResult<()> {
-        let forces = self.forces()?;
-        self.velocities = self
-            .velocities
-            .iter()
-            .zip(forces.iter())
-            .map(|(v, f)| v + d * f * h)
-            .collect();
-        Ok(())
+    pub fn retain(&mut self, keep: &[bool]) -> usize {
+        fn filter<T: Copy>(values: &mut Vec<T>, keep: &[bool]) {
+            let mut keep = keep.iter();
+            values.retain(|_| *keep.next().unwrap_or(&true));
+        }
+
+        let n = self.num_particles();
+        filter(&mut self.state.positions, keep);
+        filter(&mut self.state.velocities, keep);
+        filter(&mut self.state.masses, keep);
+        filter(&mut self.ids, keep);
+        for values in self.attributes.values_mut() {
+            filter(values, keep);
+        }
+        n - self.num_particles()
     }
 
+    /// Take over the particles and the time of `other`, keeping the potential and the observers.
+    #[doc(hidden)]
+    pub fn replace_state(&mut self, other: Particles) {
+        self.state.replace_particles(other.state);
+        self.ids = other.ids;
+        self.next_id = other.next_id;
+        self.attributes = other.attributes;
+    }
+
+}
+
+impl Simulation for Particles {
+    type Vector = Vec3;
+
+    fn state(&self) -> &State<Vec3> {
+        &self.state
+    }
 
-    fn potentials(&self) -> PyResult<Vec<Vec3>> {
-        match &self.potential {
-            None => Ok(vec![Vec3::default(); self.positions.len()]),
-            Some(pot) => {
-                // acquire the python global interpreter lock
-                let gil = pyo3::Python::acquire_gil();
-                // acquire the respective python instance
-                let python: Python<'_> = gil.python();
-                self.positions
-                    .iter()
-                    .map(|p1| {
-                        // convert the vector to a python object tuple
-                        let arg: PyObject = p1.into_py(python);
-                        let args = (arg,);
-
-                        // call the potential python function with args
-                        // if we encounter an error in Python, move the error up
-                        let call_result: PyResult<PyObject> = pot.call1(python, args);
-                        let obj: PyObject = call_result?;
-                        let vec: Vec3 = FromPyObject::extract(obj.as_ref(python))?;
-
-                        Ok(vec)
-                    }).collect()
+    fn state_mut(&mut self) -> &mut State<Vec3> {
+        &mut self.state
+    }
+
+    fn notify_observers(&self) -> PyResult<bool> {
+        let mut stop = false;
+        for observer in self.observers.iter() {
+            let mut observer = lock(observer)?;
+            if self.state.step % observer.interval() == 0 {
+                observer.observe(self)?;
+                stop |= observer.stop_requested();
             }
         }
+        Ok(stop)
     }
+}
 
-    #[doc(hidden)]
-    fn forces(&self) -> PyResult<Vec<Vec3>> {
-
-        let potentials: Vec<Vec3> = self.potentials()?;
-
-        let forces = self.positions
-            .par_iter()
-            .zip(potentials.par_iter())
-            .map(|(p1, pot)| {
-                // regular iter because the thread creation overhead
-                // outweighs its benefit in the inner loop
-                self.positions
-                    .iter()
-                    .map(|p2| {
-                        let r = p2 - p1;
-                        let r_sq = r.abs_sq();
-
-                        if approx_equal(r_sq, 0.0) {
-                            return Vec3::default();
-                        }
-
-                        // Lennard-Jones Potential
-                        use crate::constants::potential::{ATTRACTING, REPELLING};
-                        let mut f = ATTRACTING / r_sq.powf(3.0) - REPELLING / r_sq.powf(6.0);
-
-                        f = crate::utils::cap(f, -3.0, 3.0);
-
-                        r.unit() * f
-                    })
-                    .sum::<Vec3>() + pot
-            })
-            .collect();
+/// Extract N vectors from a NumPy array of shape (N, DIM) or a sequence of N vectors or of sequences
+/// of DIM numbers. One-dimensional vectors can also be given as an array of shape (N,) or a sequence
+/// of numbers. If `n` is given, the number of vectors must match.
+pub(crate) fn extract_vectors<V: Vector>(obj: &PyAny, name: &str, n: Option<usize>) -> PyResult<Vec<V>> {
+    let wrong_shape = |shape: String| {
+        let expected = n.map_or("N".to_string(), |n| n.to_string());
+        PyValueError::new_err(format!(
+            "expected {} of shape ({}, {}), got shape {}",
+            name, expected, V::DIM, shape
+        ))
+    };
+
+    let (array, scalars) = if numpy_loaded(obj.py()) {
+        let scalars = if V::DIM == 1 {
+            obj.extract::<PyReadonlyArray1<'_, f64>>().ok()
+        } else {
+            None
+        };
+        (obj.extract::<PyReadonlyArray2<'_, f64>>().ok(), scalars)
+    } else {
+        (None, None)
+    };
+
+    let vectors: Vec<V> = if let Some(array) = array {
+        let array = array.as_array();
+        if array.ncols() != V::DIM {
+            return Err(wrong_shape(format!("{:?}", array.shape())));
+        }
+        array.outer_iter().map(|row| V::from_components(&row.to_vec())).collect()
+    } else if let Some(scalars) = scalars {
+        scalars.as_array().iter().map(|&x| V::from_components(&[x])).collect()
+    } else if let Ok(vectors) = obj.extract::<Vec<V>>() {
+        vectors
+    } else {
+        let rows: Vec<Vec<f64>> = obj.extract()?;
+        if let Some(row) = rows.iter().find(|row| row.len() != V::DIM) {
+            return Err(wrong_shape(format!("({}, {})", rows.len(), row.len())));
+        }
+        rows.iter().map(|row| V::from_components(row)).collect()
+    };
+
+    match n {
+        Some(n) if vectors.len() != n => Err(wrong_shape(format!("({}, {})", vectors.len(), V::DIM))),
+        _ => Ok(vectors),
+    }
+}
+
+// names of the built-in per-particle data, which attributes cannot use in file formats
+const RESERVED_NAMES: [&str; 11] = [
+    "id", "mass", "masses", "pos", "position", "species", "type", "vel", "velo", "velocities", "velocity",
+];
+
+/// Attribute names are identifiers, so they can be used as column names in all file formats.
+pub(crate) fn check_attribute_name(name: &str) -> PyResult<()> {
+    if RESERVED_NAMES.contains(&name.to_lowercase().as_str()) {
+        return Err(PyValueError::new_err(format!(
+            "'{}' is reserved for the built-in particle data",
+            name
+        )));
+    }
+
+    let mut chars = name.chars();
+    let valid = match chars.next() {
+        Some(c) => (c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        None => false,
+    };
+    if !valid {
+        return Err(PyValueError::new_err(format!(
+            "invalid attribute name '{}', names consist of letters, digits and underscores \
+             and do not start with a digit",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Extract `n` numbers from a NumPy array of shape (N,), a sequence or a single number for all.
+pub(crate) fn extract_scalars(obj: &PyAny, name: &str, n: usize) -> PyResult<Vec<f64>> {
+    if let Ok(value) = obj.extract::<f64>() {
+        return Ok(vec![value; n]);
+    }
+
+    let array = if numpy_loaded(obj.py()) {
+        obj.extract::<PyReadonlyArray1<'_, f64>>().ok()
+    } else {
+        None
+    };
+    let values: Vec<f64> = match array {
+        Some(array) => array.as_array().to_vec(),
+        None => obj.extract()?,
+    };
+    if values.len() != n {
+        return Err(PyValueError::new_err(format!(
+            "expected {} {}, got {}",
+            n,
+            name,
+            values.len()
+        )));
+    }
+    Ok(values)
+}
+
+/// Whether NumPy has been imported. Checking for NumPy arrays otherwise fails,
+/// since the NumPy C-API cannot be loaded.
+pub(crate) fn numpy_loaded(py: Python<'_>) -> bool {
+    py.import("sys")
+        .and_then(|sys| sys.getattr("modules"))
+        .and_then(|modules| modules.get_item("numpy"))
+        .is_ok()
+}
 
-        Ok(forces)
+/// Copy vectors into a NumPy array of shape (N, DIM).
+pub(crate) fn vector_array<'py, V: Vector>(py: Python<'py>, vectors: &[V]) -> PyResult<&'py PyArray2<f64>> {
+    let mut components = Vec::with_capacity(V::DIM * vectors.len());
+    for v in vectors {
+        components.extend((0..V::DIM).map(|i| v.component(i)));
     }
+    PyArray::from_vec(py, components).reshape([vectors.len(), V::DIM])
 }
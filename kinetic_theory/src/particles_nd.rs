@@ -0,0 +1,286 @@
+//! Simulations in one and two dimensions and in single precision, which share the state, the run loop
+//! and the integrator with [Particles](../particles/struct.Particles.html), see [simulation](../simulation/index.html)
+//! and [dynamics](../dynamics/index.html).
+//! Positions and velocities are `float`s in one and [Vec2](../vec2/struct.Vec2.html)s in two dimensions,
+//! so a planar cloud stays exactly planar and does not pay for a third component.
+//! [ParticlesF32](struct.ParticlesF32.html) stores and integrates its state in f32, but takes and
+//! returns `Vec3`s and computes energies in f64.
+//!
+//! These cover the core simulation only: Python callables can observe them, but the observer classes,
+//! attributes, particle IDs and the file formats are available for [Particles](../particles/struct.Particles.html).
+//! A ParticlesF32 is converted from and to Particles with
+//! [from_particles](struct.ParticlesF32.html#method.from_particles) and
+//! [to_particles](struct.ParticlesF32.html#method.to_particles), e.g. to write a frame or analyse it.
+
+use crate::analysis::energy::{interaction_energy, kinetic_energy, temperature};
+use crate::observer::{check_interval, CallbackObserver};
+use crate::particles::{extract_scalars, extract_vectors, vector_array, Particles};
+use crate::simulation::{run_steps, Simulation, State};
+use crate::vec2::Vec2;
+use crate::vec3::Vec3;
+use crate::vec3_f32::Vec3F32;
+use crate::vector::Scalar;
+
+use numpy::{PyArray, PyArray1, PyArray2};
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+
+// pyclasses cannot be generic, so the Python classes for each vector type are generated by this macro
+macro_rules! particles_nd {
+    ($(#[$meta:meta])* $name:ident, $vector:ty) => {
+        $(#[$meta])*
+        #[pyclass(module = "particles")]
+        #[derive(Debug, Clone, Default)]
+        pub struct $name {
+            pub state: State<$vector>,
+        }
+
+        #[pymethods]
+        #[allow(dead_code)]
+        impl $name {
+            #[new]
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            /// The simulation time, advanced by every time step
+            #[getter]
+            pub fn time(&self) -> f64 {
+                self.state.time
+            }
+
+            #[setter]
+            pub fn set_time(&mut self, time: f64) {
+                self.state.time = time;
+            }
+
+            /// The number of time steps performed so far
+            #[getter]
+            pub fn step(&self) -> usize {
+                self.state.step
+            }
+
+            /// Set the external potential, a function which takes the position of a particle
+            /// and returns the force on it, see [Particles.set_potential](../particles/struct.Particles.html#method.set_potential).
+            pub fn set_potential(&mut self, potential: PyObject) {
+                self.state.potential = Some(potential);
+            }
+
+            pub fn has_potential(&self) -> bool {
+                self.state.potential.is_some()
+            }
+
+            pub fn unset_potential(&mut self) {
+                self.state.potential = None;
+            }
+
+            /// Add a particle with position `x`, velocity `v` and mass `m` to the Simulation.
+            pub fn add_particle(&mut self, x: $vector, v: $vector, m: f64) {
+                self.state.particle(x, v, Scalar::from_f64(m));
+            }
+
+            /// Add many particles at once, from NumPy arrays of shape (N, DIM) or sequences of vectors,
+            /// see [Particles.add_particles](../particles/struct.Particles.html#method.add_particles).
+            pub fn add_particles(&mut self, positions: &PyAny, velocities: &PyAny, masses: &PyAny) -> PyResult<()> {
+                let positions: Vec<$vector> = extract_vectors(positions, "positions", None)?;
+                let n = positions.len();
+                let velocities: Vec<$vector> = extract_vectors(velocities, "velocities", Some(n))?;
+                let masses = extract_scalars(masses, "masses", n)?;
+                for ((x, v), m) in positions.into_iter().zip(velocities).zip(masses) {
+                    self.state.particle(x, v, Scalar::from_f64(m));
+                }
+                Ok(())
+            }
+
+            /// Run the simulation with the specified number of steps `n` and a fixed time step `h`.
+            /// Like [Particles.run](../particles/struct.Particles.html#method.run), this releases the GIL
+            /// if no potential is set, can be interrupted with Ctrl-C and notifies the observers.
+            pub fn run(slf: &PyCell<Self>, n: usize, h: f64) -> PyResult<()> {
+                run_steps(slf, n, h, |_| Ok(()))
+            }
+
+            /// Add a Python callable, which is called with the simulation every `interval` steps
+            /// during [run](#method.run) and stops the run by returning `True`, see
+            /// [Particles.add_observer](../particles/struct.Particles.html#method.add_observer).
+            #[args(interval = "1")]
+            pub fn add_observer(&mut self, callback: &PyAny, interval: usize) -> PyResult<()> {
+                if !callback.is_callable() {
+                    return Err(PyTypeError::new_err(format!(
+                        "{} is not callable",
+                        callback.get_type().name()
+                    )));
+                }
+                self.state.callbacks.push(CallbackObserver {
+                    callback: callback.into(),
+                    interval: check_interval(interval)?,
+                });
+                Ok(())
+            }
+
+            pub fn clear_observers(&mut self) {
+                self.state.callbacks.clear();
+            }
+
+            /// Perform a single time step of size `h`,
+            /// see [Particles.update_yoshida](../particles/struct.Particles.html#method.update_yoshida).
+            pub fn update_yoshida(&mut self, h: f64) -> PyResult<()> {
+                self.state.update_yoshida(h)
+            }
+
+            pub fn positions(&self) -> Vec<$vector> {
+                self.state.positions.clone()
+            }
+
+            pub fn velocities(&self) -> Vec<$vector> {
+                self.state.velocities.clone()
+            }
+
+            pub fn masses(&self) -> Vec<f64> {
+                self.state.masses.iter().map(|m| m.to_f64()).collect()
+            }
+
+            /// Query the positions of all particles as a NumPy array of shape (N, DIM).
+            pub fn positions_array<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<f64>> {
+                vector_array(py, &self.state.positions)
+            }
+
+            /// Analogous to [positions_array](#method.positions_array).
+            pub fn velocities_array<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<f64>> {
+                vector_array(py, &self.state.velocities)
+            }
+
+            pub fn masses_array<'py>(&self, py: Python<'py>) -> &'py PyArray1<f64> {
+                PyArray::from_vec(py, self.masses())
+            }
+
+            /// Set the positions of all particles at once, in the same form as for
+            /// [add_particles](#method.add_particles).
+            pub fn set_positions(&mut self, positions: &PyAny) -> PyResult<()> {
+                self.state.positions = extract_vectors(positions, "positions", Some(self.num_particles()))?;
+                Ok(())
+            }
+
+            /// Analogous to [set_positions](#method.set_positions).
+            pub fn set_velocities(&mut self, velocities: &PyAny) -> PyResult<()> {
+                self.state.velocities = extract_vectors(velocities, "velocities", Some(self.num_particles()))?;
+                Ok(())
+            }
+
+            pub fn set_masses(&mut self, masses: &PyAny) -> PyResult<()> {
+                let masses = extract_scalars(masses, "masses", self.num_particles())?;
+                self.state.masses = masses.into_iter().map(Scalar::from_f64).collect();
+                Ok(())
+            }
+
+            pub fn num_particles(&self) -> usize {
+                self.state.positions.len()
+            }
+
+            /// The total kinetic energy of all particles.
+            pub fn kinetic_energy(&self) -> f64 {
+                kinetic_energy(&self.state.masses, &self.state.velocities)
+            }
+
+            /// The total pair interaction energy of all particles.
+            pub fn interaction_energy(&self) -> f64 {
+                interaction_energy(&self.state.positions)
+            }
+
+            /// The kinetic temperature, see [Particles.temperature](../particles/struct.Particles.html#method.temperature).
+            pub fn temperature(&self) -> f64 {
+                temperature(&self.state.masses, &self.state.velocities)
+            }
+        }
+
+        impl Simulation for $name {
+            type Vector = $vector;
+
+            fn state(&self) -> &State<$vector> {
+                &self.state
+            }
+
+            fn state_mut(&mut self) -> &mut State<$vector> {
+                &mut self.state
+            }
+        }
+    };
+}
+
+particles_nd!(
+    /// A simulation in two dimensions, with [Vec2](../vec2/struct.Vec2.html) positions and velocities.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles = Particles2D()
+    /// particles.add_particles(np.random.normal(0.0, 10.0, size=(500, 2)), np.zeros((500, 2)), 1.0)
+    /// particles.set_potential(lambda p: -0.01 * p)
+    /// particles.run(n=1000, h=0.01)
+    /// ```
+    ///
+    Particles2D,
+    Vec2
+);
+
+particles_nd!(
+    /// A simulation in one dimension, with `float` positions and velocities.
+    /// NumPy arrays of positions may have shape (N,) or (N, 1), but are returned with shape (N, 1).
+    Particles1D,
+    f64
+);
+
+particles_nd!(
+    /// A three-dimensional simulation in single precision, for large exploratory runs.
+    /// Positions, velocities and masses are stored as f32 and converted from and to `Vec3`, `float`
+    /// and float64 NumPy arrays. The forces and the time steps are computed in f32,
+    /// while energies and the temperature are computed and accumulated in f64.
+    ///
+    /// Only the simulation itself runs in single precision. The observer classes and the file formats
+    /// take a [Particles](../particles/struct.Particles.html), which [to_particles](#method.to_particles)
+    /// creates from the current state.
+    ///
+    /// # Examples
+    ///
+    /// Python:
+    /// ```python
+    /// particles = ParticlesF32()
+    /// particles.add_particles(np.random.normal(0.0, 10.0, size=(100_000, 3)), np.zeros((100_000, 3)), 1.0)
+    /// particles.run(n=10, h=0.01)
+    /// XyzWriter("final.xyz").write(particles.to_particles())
+    /// ```
+    ///
+    ParticlesF32,
+    Vec3F32
+);
+
+#[pymethods]
+impl ParticlesF32 {
+    /// Create a single precision copy of a simulation, with its particles, potential, time and step.
+    /// Its IDs, attributes and observers are not copied.
+    #[staticmethod]
+    pub fn from_particles(particles: &Particles) -> Self {
+        let state = &particles.state;
+        let mut copy = Self::default();
+        for ((x, v), m) in state.positions.iter().zip(&state.velocities).zip(&state.masses) {
+            copy.state.particle((*x).into(), (*v).into(), *m as f32);
+        }
+        copy.state.potential = state.potential.clone();
+        copy.state.time = state.time;
+        copy.state.step = state.step;
+        copy
+    }
+
+    /// Create a double precision copy of the simulation, with its particles, potential, time and step,
+    /// e.g. to write it to a file or to add it to an analysis. The particles get the IDs 0 to N - 1.
+    pub fn to_particles(&self) -> Particles {
+        let mut particles = Particles::default();
+        for ((x, v), m) in self.state.positions.iter().zip(&self.state.velocities).zip(&self.state.masses) {
+            particles.particle(Vec3::from(*x), Vec3::from(*v), *m as f64);
+        }
+        particles.state.potential = self.state.potential.clone();
+        particles.state.time = self.state.time;
+        particles.state.step = self.state.step;
+        particles
+    }
+}
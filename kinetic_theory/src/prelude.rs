@@ -1,3 +1,6 @@
 #[doc(hidden)]
 pub use crate::vec3::Vec3;
-pub use crate::particles::Particles;
\ No newline at end of file
+pub use crate::particles::Particles;
+pub use crate::mat3::Mat3;
+pub use crate::vec2::Vec2;
+pub use crate::particles_nd::{Particles1D, Particles2D, ParticlesF32};
@@ -0,0 +1,172 @@
+//! Progress reports for long runs, see [Particles.run_timer](../particles/struct.Particles.html#method.run_timer).
+
+use std::time::{Duration, Instant};
+
+use pyo3::prelude::*;
+use pyo3::PyObjectProtocol;
+
+/// The progress of a run after some of its steps, as passed to the callback of
+/// [Particles.run_timer](../particles/struct.Particles.html#method.run_timer).
+#[pyclass(module = "particles")]
+#[derive(Debug, Copy, Clone)]
+pub struct Progress {
+    /// The number of completed steps
+    #[pyo3(get)]
+    pub steps: usize,
+    /// The total number of steps of the run
+    #[pyo3(get)]
+    pub n: usize,
+    /// The time since the start of the run in seconds
+    #[pyo3(get)]
+    pub elapsed: f64,
+    /// `None` while the run is in progress. In the final report after the run ended, `True` if it
+    /// completed or was stopped by an observer, and `False` if it failed, e.g. with `KeyboardInterrupt`
+    #[pyo3(get)]
+    pub success: Option<bool>,
+}
+
+#[pymethods]
+impl Progress {
+    /// The completed percentage of the run.
+    #[getter]
+    pub fn percent(&self) -> f64 {
+        if self.n == 0 {
+            100.0
+        } else {
+            100.0 * self.steps as f64 / self.n as f64
+        }
+    }
+
+    /// The average number of steps per second so far.
+    #[getter]
+    pub fn steps_per_second(&self) -> f64 {
+        if self.elapsed > 0.0 {
+            self.steps as f64 / self.elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// The estimated time remaining in seconds, or `None` before the first step.
+    #[getter]
+    pub fn eta(&self) -> Option<f64> {
+        if self.steps == 0 {
+            return None;
+        }
+        Some(self.elapsed / self.steps as f64 * (self.n - self.steps) as f64)
+    }
+
+    /// The progress as a single line of text.
+    pub fn message(&self) -> String {
+        let eta = self.eta().map_or("-".to_string(), |eta| format!("{:.2} s", eta));
+        format!(
+            "Progress: {:.2}% ({}/{} steps, {:.1} steps/s). Estimated time remaining: {}",
+            self.percent(),
+            self.steps,
+            self.n,
+            self.steps_per_second(),
+            eta
+        )
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for Progress {
+    fn __repr__(&self) -> String {
+        format!(
+            "Progress(steps={}, n={}, elapsed={:.3})",
+            self.steps, self.n, self.elapsed
+        )
+    }
+}
+
+/// Reports the progress of a run at most every `interval`, and always once more when the run ends,
+/// either to a Python callback or as a continually updated line on Python's `sys.stdout`.
+/// An interval of `None` only reports the start and the end.
+#[derive(Debug)]
+pub struct ProgressReporter {
+    n: usize,
+    steps: usize,
+    start: Instant,
+    last_report: Instant,
+    interval: Option<Duration>,
+    callback: Option<PyObject>,
+}
+
+impl ProgressReporter {
+    /// Create a reporter for a run of `n` steps, which starts now, and report the start.
+    /// `interval` must not be negative or NaN, an infinite interval only reports the start and the end.
+    pub fn new(py: Python<'_>, n: usize, interval: f64, callback: Option<PyObject>) -> PyResult<Self> {
+        let now = Instant::now();
+        let reporter = Self {
+            n,
+            steps: 0,
+            start: now,
+            last_report: now,
+            interval: Duration::try_from_secs_f64(interval).ok(),
+            callback,
+        };
+        reporter.report(py, reporter.progress(None))?;
+        Ok(reporter)
+    }
+
+    /// The progress after the completed steps, with the `success` of the run if it ended.
+    pub fn progress(&self, success: Option<bool>) -> Progress {
+        Progress {
+            steps: self.steps,
+            n: self.n,
+            elapsed: self.start.elapsed().as_secs_f64(),
+            success,
+        }
+    }
+
+    /// Notify the reporter that `steps` steps are complete. The last step is reported by
+    /// [finish](#method.finish).
+    pub fn update(&mut self, py: Python<'_>, steps: usize) -> PyResult<()> {
+        self.steps = steps;
+        let now = Instant::now();
+        let due = self.interval.is_some_and(|interval| now - self.last_report >= interval);
+        if steps < self.n && due {
+            self.last_report = now;
+            self.report(py, self.progress(None))?;
+        }
+        Ok(())
+    }
+
+    /// Report the final step count after the run, successful or not, which also stops runs
+    /// ended early by an observer or an error at the right step, and end the progress line.
+    /// An error of the run takes precedence over one of the final report.
+    pub fn finish(&self, py: Python<'_>, result: &PyResult<()>) -> PyResult<()> {
+        let reported = self.report(py, self.progress(Some(result.is_ok())));
+        if result.is_err() {
+            // keep the last progress line and start a new one for the error
+            if self.callback.is_none() {
+                write_stdout(py, "\n")?;
+            }
+            return Ok(());
+        }
+        reported?;
+        match self.callback {
+            Some(_) => Ok(()),
+            None => write_stdout(
+                py,
+                &format!("\rDone. Execution took {:.2} s\n", self.start.elapsed().as_secs_f64()),
+            ),
+        }
+    }
+
+    fn report(&self, py: Python<'_>, progress: Progress) -> PyResult<()> {
+        match &self.callback {
+            Some(callback) => callback.call1(py, (progress,)).map(|_| ()),
+            None => write_stdout(py, &format!("\r{}", progress.message())),
+        }
+    }
+}
+
+// writing to Python's stdout instead of the process' stdout also displays the progress in Jupyter
+fn write_stdout(py: Python<'_>, text: &str) -> PyResult<()> {
+    let stdout = py.import("sys")?.getattr("stdout")?;
+    stdout.call_method1("write", (text,))?;
+    stdout.call_method0("flush")?;
+    Ok(())
+}
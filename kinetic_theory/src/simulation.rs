@@ -0,0 +1,135 @@
+//! The dimension-generic core shared by all simulation classes: the [State](struct.State.html) of the
+//! particles and the run loop, which performs the time steps, notifies the observers and handles Ctrl-C.
+//! [Particles](../particles/struct.Particles.html) adds particle IDs, attributes and the observer
+//! classes on top, see also [particles_nd](../particles_nd/index.html).
+
+use crate::dynamics;
+use crate::observer::{notify_callbacks, CallbackObserver};
+use crate::vector::Vector;
+
+use pyo3::prelude::*;
+use pyo3::{PyClass, PyNativeType};
+
+/// The particles, the external potential, the time and the Python callbacks of a simulation
+/// in any dimension.
+#[derive(Debug, Clone, Default)]
+pub struct State<V: Vector> {
+    pub positions: Vec<V>,
+    pub velocities: Vec<V>,
+    pub masses: Vec<V::Scalar>,
+    pub potential: Option<PyObject>,
+    pub time: f64,
+    pub step: usize,
+    pub callbacks: Vec<CallbackObserver>,
+    /// The last step at which the observers were notified, so the initial state is not observed
+    /// again by a later run
+    pub observed_step: Option<usize>,
+}
+
+impl<V: Vector> State<V> {
+    pub fn particle(&mut self, x: V, v: V, m: V::Scalar) -> &mut Self {
+        self.positions.push(x);
+        self.velocities.push(v);
+        self.masses.push(m);
+        self
+    }
+
+    pub fn num_particles(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Whether the observers still have to be notified of the initial state at step 0,
+    /// which is not the case after e.g. `run(0, h)` or a run cancelled before its first step.
+    pub fn initial_observation_due(&self) -> bool {
+        self.step == 0 && self.observed_step != Some(0)
+    }
+
+    /// Perform a single time step of size `h`, see [dynamics::update_yoshida](../dynamics/fn.update_yoshida.html).
+    pub fn update_yoshida(&mut self, h: f64) -> PyResult<()> {
+        dynamics::update_yoshida(&mut self.positions, &mut self.velocities, self.potential.as_ref(), h)?;
+        self.time += h;
+        self.step += 1;
+        Ok(())
+    }
+
+    /// Perform a time step with the GIL released, unless the Python potential needs it.
+    pub fn update_allowing_threads(&mut self, py: Python<'_>, h: f64) -> PyResult<()> {
+        if self.potential.is_some() {
+            self.update_yoshida(h)
+        } else {
+            py.allow_threads(|| self.update_yoshida(h))
+        }
+    }
+
+    /// Take over the particles, the time and the step of `other`, keeping the potential and the callbacks.
+    pub fn replace_particles(&mut self, other: State<V>) {
+        self.positions = other.positions;
+        self.velocities = other.velocities;
+        self.masses = other.masses;
+        self.time = other.time;
+        self.step = other.step;
+        self.observed_step = other.observed_step;
+    }
+}
+
+/// A Python simulation class, which wraps a [State](struct.State.html) and can be
+/// [run](fn.run_steps.html).
+pub trait Simulation: PyClass {
+    type Vector: Vector;
+
+    fn state(&self) -> &State<Self::Vector>;
+
+    fn state_mut(&mut self) -> &mut State<Self::Vector>;
+
+    /// Notify the observers implemented in Rust which are due at the current step,
+    /// and return whether any of them requested to stop.
+    fn notify_observers(&self) -> PyResult<bool> {
+        Ok(false)
+    }
+}
+
+/// Perform `n` time steps of size `h`, calling `after_step` with the number of completed steps
+/// after each one, until an observer requests to stop. The observers are notified of the initial
+/// state, unless an earlier run already did, and after each step. The GIL is released during
+/// the steps if no Python potential is set, and Ctrl-C raises `KeyboardInterrupt` after the current step.
+///
+/// The simulation is only borrowed during the steps, so Python callbacks can use it in between.
+pub fn run_steps<S: Simulation>(
+    slf: &PyCell<S>,
+    n: usize,
+    h: f64,
+    mut after_step: impl FnMut(usize) -> PyResult<()>,
+) -> PyResult<()> {
+    let py = slf.py();
+
+    // observe the initial state, later states are observed after each step
+    if slf.try_borrow()?.state().initial_observation_due() && notify_all(slf)? {
+        return Ok(());
+    }
+
+    for i in 0..n {
+        slf.try_borrow_mut()?.state_mut().update_allowing_threads(py, h)?;
+        let stop = notify_all(slf)?;
+        py.check_signals()?;
+        after_step(i + 1)?;
+        if stop {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Notify the observers and then the Python callbacks which are due at the current step,
+/// and return whether any of them requested to stop.
+fn notify_all<S: Simulation>(slf: &PyCell<S>) -> PyResult<bool> {
+    let (stop, callbacks, step) = {
+        let simulation = slf.try_borrow()?;
+        let state = simulation.state();
+        (simulation.notify_observers()?, state.callbacks.clone(), state.step)
+    };
+    // the simulation is not borrowed anymore, so the callbacks can use it
+    let stop = notify_callbacks(slf.py(), &callbacks, step, slf)? || stop;
+    slf.try_borrow_mut()?.state_mut().observed_step = Some(step);
+    Ok(stop)
+}
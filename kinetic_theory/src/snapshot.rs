@@ -0,0 +1,132 @@
+//! In-memory copies of the simulation state, recorded during a run by a
+//! [SnapshotObserver](struct.SnapshotObserver.html). For long runs, write a trajectory file instead,
+//! see [TrajectoryWriter](../io/trajectory/struct.TrajectoryWriter.html).
+
+use crate::observer::{lock, recorder_methods, ObserverBase, Record, Recorder};
+use crate::particles::{vector_array, Particles};
+use crate::vec3::Vec3;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use numpy::{PyArray, PyArray1, PyArray2};
+use pyo3::prelude::*;
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass]
+#[derive(Debug, Clone)]
+/// The positions, velocities, masses, IDs and attributes of all particles at some step.
+pub struct Snapshot {
+    /// The simulation time of the snapshot
+    #[pyo3(get)]
+    pub time: f64,
+    /// The step of the snapshot
+    #[pyo3(get)]
+    pub step: usize,
+    #[pyo3(get)]
+    pub positions: Vec<Vec3>,
+    #[pyo3(get)]
+    pub velocities: Vec<Vec3>,
+    #[pyo3(get)]
+    pub masses: Vec<f64>,
+    /// The particle IDs, see [Particles.ids](../particles/struct.Particles.html#method.ids)
+    #[pyo3(get)]
+    pub ids: Vec<u64>,
+    /// The per-particle attributes by name, see
+    /// [Particles.attribute](../particles/struct.Particles.html#method.attribute)
+    #[pyo3(get)]
+    pub attributes: BTreeMap<String, Vec<f64>>,
+}
+
+#[pymethods]
+impl Snapshot {
+    /// The positions as a NumPy array of shape (N, 3).
+    pub fn positions_array<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<f64>> {
+        vector_array(py, &self.positions)
+    }
+
+    /// The velocities as a NumPy array of shape (N, 3).
+    pub fn velocities_array<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<f64>> {
+        vector_array(py, &self.velocities)
+    }
+
+    /// The masses as a NumPy array of shape (N,).
+    pub fn masses_array<'py>(&self, py: Python<'py>) -> &'py PyArray1<f64> {
+        PyArray::from_slice(py, &self.masses)
+    }
+
+    pub fn num_particles(&self) -> usize {
+        self.positions.len()
+    }
+}
+
+// Non-Python (Rust-only) Methods
+#[doc(hidden)]
+impl Snapshot {
+    pub fn new(particles: &Particles) -> Self {
+        Self {
+            time: particles.state.time,
+            step: particles.state.step,
+            positions: particles.state.positions.clone(),
+            velocities: particles.state.velocities.clone(),
+            masses: particles.state.masses.clone(),
+            ids: particles.ids.clone(),
+            attributes: particles.attributes.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Snapshots {
+    snapshots: Vec<Snapshot>,
+}
+
+impl Record for Snapshots {
+    fn record(&mut self, particles: &Particles) -> PyResult<()> {
+        self.snapshots.push(Snapshot::new(particles));
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+// Tell PyO3 to make this class accessible from Python
+#[pyclass(extends=ObserverBase)]
+#[derive(Debug, Clone)]
+/// Records a [Snapshot](struct.Snapshot.html) of the simulation every `interval` steps when added to
+/// a simulation with [add_observer](../particles/struct.Particles.html#method.add_observer).
+///
+/// # Examples
+///
+/// Python:
+/// ```python
+/// snapshots = SnapshotObserver(interval=100)
+/// particles.add_observer(snapshots)
+/// particles.run(n=1000, h=0.01)
+/// for snapshot in snapshots.snapshots:
+///     x = snapshot.positions_array()
+///     plt.plot(x[:, 0], x[:, 1], lw=0, marker=".", label=f"t = {snapshot.time}")
+/// ```
+///
+pub struct SnapshotObserver {
+    recorder: Arc<Mutex<Recorder<Snapshots>>>,
+}
+
+#[pymethods]
+impl SnapshotObserver {
+    #[new]
+    #[args(interval = "1")]
+    pub fn new(interval: usize) -> PyResult<(Self, ObserverBase)> {
+        let (recorder, base) = ObserverBase::share(Recorder::new(interval, Snapshots::default())?);
+        Ok((Self { recorder }, base))
+    }
+
+    /// All recorded snapshots.
+    #[getter]
+    pub fn snapshots(&self) -> PyResult<Vec<Snapshot>> {
+        Ok(lock(&self.recorder)?.data.snapshots.clone())
+    }
+}
+
+recorder_methods!(SnapshotObserver);
@@ -2,7 +2,10 @@ use std::io::stdout;
 use std::io::Write;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::thread;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::progress::Progress;
 
 /// Checks if two floating point numbers are approximately equal. This is preferred to `a == b`.
 ///
@@ -24,7 +27,11 @@ pub fn approx_equal(a: f64, b: f64) -> bool {
     }
 }
 
-/// Times a function with progress reports including an estimation for time remaining
+/// Times a function with progress reports including an estimation for time remaining.
+/// The reports stop as soon as all calls are done or one of them fails.
+///
+/// This is for Rust code, which does not need the GIL. From Python,
+/// use [Particles.run_timer](../particles/struct.Particles.html#method.run_timer) instead.
 ///
 /// # Arguments
 ///
@@ -43,57 +50,42 @@ where
 
     // spawn two threads, one running the function and one for progress output
 
-    // atomic index
+    // atomic index of completed calls
     let idx1 = Arc::new(AtomicUsize::new(0));
     let idx2 = Arc::clone(&idx1);
+    // the function thread drops the sender when it returns, successfully or not,
+    // which wakes up the progress thread immediately
+    let (sender, receiver) = mpsc::channel::<()>();
 
-    let start_time: std::time::Instant = std::time::Instant::now();
+    let start_time = Instant::now();
 
     let (a, b) = join(
-        || -> Result<(), E> {
-            loop {
-                // run f
+        move || -> Result<(), E> {
+            let _sender = sender;
+            for _ in 0..n {
                 f()?;
-
-                // increment
-                if idx1.fetch_add(1, Ordering::Relaxed) == n {
-                    break;
-                }
+                idx1.fetch_add(1, Ordering::Relaxed);
             }
             Ok(())
         },
-        || -> Result<(), E> {
+        move || -> Result<(), E> {
             loop {
-
-                let i = idx2.load(Ordering::Relaxed);
-                if i >= n {
-                    // this must be greater or equal since
-                    // 1: we want to avoid overflow, which happens when i == n
-                    // 2: after the final function thread loop, i == n + 1
-                    break;
-                }
                 // calculate and print progress
-                let progress = 100.0 * (i + 1) as f32 / n as f32;
-                let time_per_call = (std::time::Instant::now() - start_time) / (i + 1) as u32;
-                let time_remaining = (n - i - 1) as u32 * time_per_call;
-                print!(
-                    "\rProgress: {:.2}%. Estimated time remaining: {:.2} s",
-                    progress,
-                    time_remaining.as_millis() as f32 / 1000.0
-                );
+                let progress = Progress {
+                    steps: idx2.load(Ordering::Relaxed),
+                    n,
+                    elapsed: start_time.elapsed().as_secs_f64(),
+                    success: None,
+                };
+                print!("\r{}", progress.message());
                 // need to flush here to display the text
                 stdout().flush()?;
 
-                // TODO: This thread will continue sleeping even when the function thread has
-                //  already finished executing, possibly resulting in overhead up to 250ms long.
-                //  A possible solution is to send an exit signal to this thread from the function
-                //  thread but this may require the Sync Trait for the function f
-
-                // FIXME: Possible bug when f() errors that this thread will continue looping and sleeping forever
-                //   proposed fix: share result instead of atomic index between threads
-
-                // sleep for a quarter second
-                thread::sleep(std::time::Duration::from_millis(250));
+                // wait for a quarter second, or until the function thread is done
+                match receiver.recv_timeout(Duration::from_millis(250)) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    _ => break,
+                }
             }
 
             Ok(())
@@ -101,12 +93,15 @@ where
     );
 
     // if any of the functions returned an Err, show it here
+    if a.is_err() {
+        println!();
+    }
     a?;
     b?;
 
     println!(
         "\rDone. Execution took {:.2} s",
-        (std::time::Instant::now() - start_time).as_millis() as f32 / 1000.0
+        start_time.elapsed().as_millis() as f32 / 1000.0
     );
 
     Ok(())
@@ -116,7 +111,7 @@ where
 /// Adjust a number to be within a certain interval.
 /// Values below (above) the lower (upper) bound will be set to the lower (upper) bound.
 ///
-pub fn cap(f: f64, min: f64, max: f64) -> f64 {
+pub fn cap<T: PartialOrd>(f: T, min: T, max: T) -> T {
     if f > max {
         max
     } else if f < min {
@@ -124,4 +119,97 @@ pub fn cap(f: f64, min: f64, max: f64) -> f64 {
     } else {
         f
     }
-}
\ No newline at end of file
+}
+
+/// Computes the eigenvalues and eigenvectors of a symmetric 3x3 matrix
+/// with the [Jacobi Eigenvalue Algorithm](https://en.wikipedia.org/wiki/Jacobi_eigenvalue_algorithm).
+///
+/// Returns the eigenvalues in descending order and the corresponding normalized eigenvectors.
+///
+/// # Arguments
+///
+/// `m` - The symmetric matrix, only its upper triangle is used
+///
+pub fn symmetric_eigen(m: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut a = [
+        [m[0][0], m[0][1], m[0][2]],
+        [m[0][1], m[1][1], m[1][2]],
+        [m[0][2], m[1][2], m[2][2]],
+    ];
+    // the columns of v are the eigenvectors
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    for _ in 0..64 {
+        let off_diagonal = a[0][1].powi(2) + a[0][2].powi(2) + a[1][2].powi(2);
+        if off_diagonal < 1e-30 * (a[0][0].powi(2) + a[1][1].powi(2) + a[2][2].powi(2)).max(f64::MIN_POSITIVE) {
+            break;
+        }
+
+        for &(p, q) in &[(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+            // rotate rows and columns p and q to annihilate a[p][q]
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            for row in a.iter_mut() {
+                let (akp, akq) = (row[p], row[q]);
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            for (k, (apk, aqk)) in row_p.iter().zip(row_q.iter()).enumerate() {
+                a[p][k] = c * apk - s * aqk;
+                a[q][k] = s * apk + c * aqk;
+            }
+            for row in v.iter_mut() {
+                let (vkp, vkq) = (row[p], row[q]);
+                row[p] = c * vkp - s * vkq;
+                row[q] = s * vkp + c * vkq;
+            }
+        }
+    }
+
+    let mut order = [0, 1, 2];
+    order.sort_by(|&i, &j| a[j][j].partial_cmp(&a[i][i]).unwrap_or(std::cmp::Ordering::Equal));
+
+    let values = [a[order[0]][order[0]], a[order[1]][order[1]], a[order[2]][order[2]]];
+    let vectors = [
+        [v[0][order[0]], v[1][order[0]], v[2][order[0]]],
+        [v[0][order[1]], v[1][order[1]], v[2][order[1]]],
+        [v[0][order[2]], v[1][order[2]], v[2][order[2]]],
+    ];
+
+    (values, vectors)
+}
+
+
+/// The complementary error function erfc(x) = 1 - erf(x), with a relative error below 1.2e-7 everywhere.
+/// See Numerical Recipes, Section 6.2.
+///
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t * (-z * z - 1.26551223
+        + t * (1.00002368
+        + t * (0.37409196
+        + t * (0.09678418
+        + t * (-0.18628806
+        + t * (0.27886807
+        + t * (-1.13520398
+        + t * (1.48851587
+        + t * (-0.82215223
+        + t * 0.17087277))))))))).exp();
+
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+
+/// The error function erf(x), see [erfc](fn.erfc.html).
+///
+pub fn erf(x: f64) -> f64 {
+    1.0 - erfc(x)
+}
@@ -0,0 +1,286 @@
+use serde::{Deserialize, Serialize};
+use std::default::Default;
+use std::ops::{Div, DivAssign, Mul, MulAssign};
+
+use derive_more::{Add, AddAssign, Neg, Sub, SubAssign};
+use numpy::PyArray1;
+use pyo3::basic::CompareOp;
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyTuple, PyType};
+use pyo3::{PyIterProtocol, PyNativeType, PyNumberProtocol, PyObjectProtocol, PySequenceProtocol};
+
+/// The two-dimensional counterpart of [Vec3](../vec3/struct.Vec3.html), used by
+/// [Particles2D](../particles_nd/struct.Particles2D.html).
+#[pyclass(module = "particles")]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Default, Neg, Add, AddAssign, Sub, SubAssign, Serialize, Deserialize,
+)]
+pub struct Vec2 {
+    #[pyo3(get, set)]
+    pub x: f64,
+    #[pyo3(get, set)]
+    pub y: f64,
+}
+
+#[pymethods]
+impl Vec2 {
+    #[new]
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    pub fn abs_sq(&self) -> f64 {
+        self * self
+    }
+
+    pub fn abs(&self) -> f64 {
+        self.abs_sq().sqrt()
+    }
+
+    pub fn unit(&self) -> Self {
+        self / self.abs()
+    }
+
+    /// Like [unit](#method.unit), but returns an error instead of NaNs for zero or non-finite vectors.
+    pub fn try_unit(&self) -> PyResult<Self> {
+        let abs = self.abs();
+        if abs == 0.0 || !abs.is_finite() {
+            return Err(PyValueError::new_err(format!("cannot normalize {:?}", self)));
+        }
+        Ok(self / abs)
+    }
+
+    /// The z component of the cross product of the two vectors embedded in the xy plane.
+    pub fn cross(&self, other: Vec2) -> f64 {
+        self.x * other.y - self.y * other.x
+    }
+
+    /// Rotate counterclockwise by `angle` radians.
+    pub fn rotate(&self, angle: f64) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self::new(cos * self.x - sin * self.y, sin * self.x + cos * self.y)
+    }
+
+    // pickle and copy support, the state is the serialized vector
+
+    pub fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        let bytes = bincode::serialize(self)
+            .map_err(|e| PyValueError::new_err(format!("could not serialize Vec2: {}", e)))?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    pub fn __setstate__(&mut self, state: &PyBytes) -> PyResult<()> {
+        *self = bincode::deserialize(state.as_bytes())
+            .map_err(|e| PyValueError::new_err(format!("could not deserialize Vec2: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(&'py PyType, (f64, f64), &'py PyBytes)> {
+        Ok((py.get_type::<Self>(), (0.0, 0.0), self.__getstate__(py)?))
+    }
+
+    pub fn __copy__(&self) -> Self {
+        *self
+    }
+
+    pub fn __deepcopy__(&self, _memo: &PyAny) -> Self {
+        *self
+    }
+
+    // conversion from and to tuples and numpy arrays
+
+    /// Create a vector from a tuple, or any other sequence, of two numbers.
+    #[staticmethod]
+    pub fn from_tuple(values: &PyAny) -> PyResult<Self> {
+        let values: Vec<f64> = values.extract()?;
+        match values.as_slice() {
+            &[x, y] => Ok(Self::new(x, y)),
+            _ => Err(PyValueError::new_err(format!(
+                "expected two components, got {}",
+                values.len()
+            ))),
+        }
+    }
+
+    pub fn to_tuple(&self) -> (f64, f64) {
+        (self.x, self.y)
+    }
+
+    /// The components as a numpy array of shape (2,).
+    pub fn to_array<'py>(&self, py: Python<'py>) -> &'py PyArray1<f64> {
+        PyArray1::from_slice(py, &[self.x, self.y])
+    }
+
+    // lets numpy.asarray convert vectors
+    #[args(dtype = "None")]
+    pub fn __array__<'py>(&self, py: Python<'py>, dtype: Option<&PyAny>) -> PyResult<&'py PyAny> {
+        let array = self.to_array(py);
+        match dtype {
+            Some(dtype) => array.call_method1("astype", (dtype,)),
+            None => Ok(array),
+        }
+    }
+
+    // NumPy defers to the operators of this class instead of converting it to an array, so e.g.
+    // `np.float64(2.0) * v` is a vector and not an ndarray
+    #[classattr]
+    pub fn __array_ufunc__() -> Option<PyObject> {
+        None
+    }
+}
+
+// Python operators, analogous to Vec3
+#[pyproto]
+impl PyNumberProtocol for Vec2 {
+    fn __add__(lhs: Vec2, rhs: Vec2) -> Vec2 {
+        lhs + rhs
+    }
+
+    fn __sub__(lhs: Vec2, rhs: Vec2) -> Vec2 {
+        lhs - rhs
+    }
+
+    /// Multiplication with a number scales the vector, multiplication with a vector is the dot product.
+    fn __mul__(lhs: Vec2, rhs: &PyAny) -> PyObject {
+        let py = rhs.py();
+        if let Ok(other) = rhs.extract::<Vec2>() {
+            (lhs * other).into_py(py)
+        } else if let Ok(factor) = rhs.extract::<f64>() {
+            (lhs * factor).into_py(py)
+        } else {
+            py.NotImplemented()
+        }
+    }
+
+    fn __rmul__(&self, factor: f64) -> Vec2 {
+        factor * *self
+    }
+
+    fn __truediv__(lhs: Vec2, rhs: f64) -> Vec2 {
+        lhs / rhs
+    }
+
+    fn __neg__(&self) -> Vec2 {
+        -*self
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for Vec2 {
+    fn __repr__(&self) -> String {
+        format!("Vec2({:?}, {:?})", self.x, self.y)
+    }
+
+    // vectors are mutable, so they are compared by value but not hashable
+    fn __richcmp__(&self, other: &PyAny, op: CompareOp) -> PyObject {
+        let py = other.py();
+        match (other.extract::<Vec2>(), op) {
+            (Ok(other), CompareOp::Eq) => (*self == other).into_py(py),
+            (Ok(other), CompareOp::Ne) => (*self != other).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+}
+
+#[pyproto]
+impl PySequenceProtocol for Vec2 {
+    fn __len__(&self) -> usize {
+        2
+    }
+
+    // negative indices are already adjusted with the length by Python
+    fn __getitem__(&self, index: isize) -> PyResult<f64> {
+        match index {
+            0 => Ok(self.x),
+            1 => Ok(self.y),
+            _ => Err(PyIndexError::new_err("Vec2 index out of range")),
+        }
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for Vec2 {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<PyObject> {
+        let py = slf.py();
+        let components = PyTuple::new(py, [slf.x, slf.y]);
+        Ok(components.call_method0("__iter__")?.into())
+    }
+}
+
+// only the reference variants which are actually needed, unlike for Vec3
+
+impl Mul<Vec2> for Vec2 {
+    type Output = f64;
+
+    fn mul(self, other: Self) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+}
+
+impl Mul<&Vec2> for &Vec2 {
+    type Output = f64;
+
+    fn mul(self, other: &Vec2) -> f64 {
+        *self * *other
+    }
+}
+
+impl Mul<f64> for Vec2 {
+    type Output = Self;
+
+    fn mul(self, other: f64) -> Self {
+        Self::new(self.x * other, self.y * other)
+    }
+}
+
+impl Mul<Vec2> for f64 {
+    type Output = Vec2;
+
+    fn mul(self, other: Vec2) -> Vec2 {
+        other * self
+    }
+}
+
+impl MulAssign<f64> for Vec2 {
+    fn mul_assign(&mut self, other: f64) {
+        self.x *= other;
+        self.y *= other;
+    }
+}
+
+impl Div<f64> for Vec2 {
+    type Output = Self;
+
+    fn div(self, other: f64) -> Self {
+        Self::new(self.x / other, self.y / other)
+    }
+}
+
+impl Div<f64> for &Vec2 {
+    type Output = Vec2;
+
+    fn div(self, other: f64) -> Vec2 {
+        *self / other
+    }
+}
+
+impl DivAssign<f64> for Vec2 {
+    fn div_assign(&mut self, other: f64) {
+        self.x /= other;
+        self.y /= other;
+    }
+}
+
+impl std::iter::Sum for Vec2 {
+    fn sum<I>(iter: I) -> Self
+    where
+        I: Iterator<Item = Self>,
+    {
+        let mut result = Vec2::default();
+        for v in iter {
+            result += v;
+        }
+        result
+    }
+}
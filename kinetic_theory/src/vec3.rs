@@ -1,11 +1,17 @@
+use crate::mat3::{unit_quaternion, Mat3};
 use serde::{Deserialize, Serialize};
 use std::default::Default;
 use std::ops::{Div, DivAssign, Mul, MulAssign};
 
 use derive_more::{Add, AddAssign, Neg, Sub, SubAssign};
+use numpy::PyArray1;
+use pyo3::basic::CompareOp;
+use pyo3::exceptions::{PyIndexError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyTuple, PyType};
+use pyo3::{PyIterProtocol, PyNativeType, PyNumberProtocol, PyObjectProtocol, PySequenceProtocol};
 
-#[pyclass]
+#[pyclass(module = "particles")]
 #[derive(
     Debug, Copy, Clone, PartialEq, Default, Neg, Add, AddAssign, Sub, SubAssign, Serialize, Deserialize,
 )]
@@ -37,6 +43,219 @@ impl Vec3 {
     pub fn unit(&self) -> Self {
         self / self.abs()
     }
+
+    /// Like [unit](#method.unit), but returns an error instead of NaNs for zero or non-finite vectors.
+    pub fn try_unit(&self) -> PyResult<Self> {
+        let abs = self.abs();
+        if abs == 0.0 || !abs.is_finite() {
+            return Err(PyValueError::new_err(format!("cannot normalize {:?}", self)));
+        }
+        Ok(self / abs)
+    }
+
+    pub fn cross(&self, other: Vec3) -> Self {
+        Self::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    /// The outer product `self ⊗ other`, whose element `[i, j]` is `self[i] * other[j]`.
+    pub fn outer(&self, other: Vec3) -> Mat3 {
+        let b = [other.x, other.y, other.z];
+        Mat3::new([self.x, self.y, self.z].map(|ai| b.map(|bj| ai * bj)))
+    }
+
+    /// The component of this vector parallel to `onto`.
+    pub fn project(&self, onto: Vec3) -> PyResult<Self> {
+        let u = onto.try_unit()?;
+        Ok((self * u) * u)
+    }
+
+    /// The component of this vector perpendicular to `other`, such that
+    /// `v.project(other) + v.reject(other) == v`.
+    pub fn reject(&self, other: Vec3) -> PyResult<Self> {
+        Ok(self - self.project(other)?)
+    }
+
+    /// The angle between this vector and `other` in radians, between 0 and pi.
+    pub fn angle(&self, other: Vec3) -> PyResult<f64> {
+        if self.abs_sq() == 0.0 || other.abs_sq() == 0.0 {
+            return Err(PyValueError::new_err("the angle with a zero vector is undefined"));
+        }
+        // atan2 stays accurate for nearly (anti-)parallel vectors, unlike acos of the dot product
+        Ok(self.cross(other).abs().atan2(self * other))
+    }
+
+    /// Rotate by `angle` radians around `axis`, counterclockwise when the axis points
+    /// towards the viewer. The axis does not need to be normalized.
+    pub fn rotate(&self, axis: Vec3, angle: f64) -> PyResult<Self> {
+        // Rodrigues' rotation formula
+        let k = axis.try_unit()?;
+        let (sin, cos) = angle.sin_cos();
+        Ok(self * cos + k.cross(*self) * sin + k * (k * self) * (1.0 - cos))
+    }
+
+    /// Rotate by the quaternion `(w, x, y, z)`, which is normalized first.
+    pub fn rotate_quaternion(&self, quaternion: (f64, f64, f64, f64)) -> PyResult<Self> {
+        let (w, x, y, z) = unit_quaternion(quaternion)?;
+        let u = Self::new(x, y, z);
+        let t = 2.0 * u.cross(*self);
+        Ok(self + w * t + u.cross(t))
+    }
+
+    // pickle and copy support, the state is the serialized vector
+
+    pub fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        let bytes = bincode::serialize(self)
+            .map_err(|e| PyValueError::new_err(format!("could not serialize Vec3: {}", e)))?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    pub fn __setstate__(&mut self, state: &PyBytes) -> PyResult<()> {
+        *self = bincode::deserialize(state.as_bytes())
+            .map_err(|e| PyValueError::new_err(format!("could not deserialize Vec3: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(&'py PyType, (f64, f64, f64), &'py PyBytes)> {
+        Ok((py.get_type::<Self>(), (0.0, 0.0, 0.0), self.__getstate__(py)?))
+    }
+
+    pub fn __copy__(&self) -> Self {
+        *self
+    }
+
+    pub fn __deepcopy__(&self, _memo: &PyAny) -> Self {
+        *self
+    }
+
+    // conversion from and to tuples and numpy arrays
+
+    /// Create a vector from a tuple, or any other sequence, of three numbers.
+    #[staticmethod]
+    pub fn from_tuple(values: &PyAny) -> PyResult<Self> {
+        let values: Vec<f64> = values.extract()?;
+        match values.as_slice() {
+            &[x, y, z] => Ok(Self::new(x, y, z)),
+            _ => Err(PyValueError::new_err(format!(
+                "expected three components, got {}",
+                values.len()
+            ))),
+        }
+    }
+
+    /// Create a vector from a numpy array of shape (3,).
+    #[staticmethod]
+    pub fn from_array(array: &PyAny) -> PyResult<Self> {
+        Self::from_tuple(array)
+    }
+
+    pub fn to_tuple(&self) -> (f64, f64, f64) {
+        (self.x, self.y, self.z)
+    }
+
+    /// The components as a numpy array of shape (3,).
+    pub fn to_array<'py>(&self, py: Python<'py>) -> &'py PyArray1<f64> {
+        PyArray1::from_slice(py, &[self.x, self.y, self.z])
+    }
+
+    // lets numpy.asarray convert vectors
+    #[args(dtype = "None")]
+    pub fn __array__<'py>(&self, py: Python<'py>, dtype: Option<&PyAny>) -> PyResult<&'py PyAny> {
+        let array = self.to_array(py);
+        match dtype {
+            Some(dtype) => array.call_method1("astype", (dtype,)),
+            None => Ok(array),
+        }
+    }
+
+    // NumPy defers to the operators of this class instead of converting it to an array, so e.g.
+    // `np.float64(2.0) * v` is a vector and not an ndarray
+    #[classattr]
+    pub fn __array_ufunc__() -> Option<PyObject> {
+        None
+    }
+}
+
+// Python operators, see https://docs.python.org/3/reference/datamodel.html#emulating-numeric-types
+#[pyproto]
+impl PyNumberProtocol for Vec3 {
+    fn __add__(lhs: Vec3, rhs: Vec3) -> Vec3 {
+        lhs + rhs
+    }
+
+    fn __sub__(lhs: Vec3, rhs: Vec3) -> Vec3 {
+        lhs - rhs
+    }
+
+    /// Multiplication with a number scales the vector, multiplication with a vector is the dot product.
+    fn __mul__(lhs: Vec3, rhs: &PyAny) -> PyObject {
+        let py = rhs.py();
+        if let Ok(other) = rhs.extract::<Vec3>() {
+            (lhs * other).into_py(py)
+        } else if let Ok(factor) = rhs.extract::<f64>() {
+            (lhs * factor).into_py(py)
+        } else {
+            py.NotImplemented()
+        }
+    }
+
+    fn __rmul__(&self, factor: f64) -> Vec3 {
+        factor * self
+    }
+
+    fn __truediv__(lhs: Vec3, rhs: f64) -> Vec3 {
+        lhs / rhs
+    }
+
+    fn __neg__(&self) -> Vec3 {
+        -self
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for Vec3 {
+    fn __repr__(&self) -> String {
+        format!("Vec3({:?}, {:?}, {:?})", self.x, self.y, self.z)
+    }
+
+    // vectors are mutable, so they are compared by value but not hashable
+    fn __richcmp__(&self, other: &PyAny, op: CompareOp) -> PyObject {
+        let py = other.py();
+        match (other.extract::<Vec3>(), op) {
+            (Ok(other), CompareOp::Eq) => (*self == other).into_py(py),
+            (Ok(other), CompareOp::Ne) => (*self != other).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+}
+
+#[pyproto]
+impl PySequenceProtocol for Vec3 {
+    fn __len__(&self) -> usize {
+        3
+    }
+
+    // negative indices are already adjusted with the length by Python
+    fn __getitem__(&self, index: isize) -> PyResult<f64> {
+        match index {
+            0 => Ok(self.x),
+            1 => Ok(self.y),
+            2 => Ok(self.z),
+            _ => Err(PyIndexError::new_err("Vec3 index out of range")),
+        }
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for Vec3 {
+    fn __iter__(slf: PyRef<Self>) -> PyResult<PyObject> {
+        let py = slf.py();
+        let components = PyTuple::new(py, [slf.x, slf.y, slf.z]);
+        Ok(components.call_method0("__iter__")?.into())
+    }
 }
 
 // typically, you would blanket implement all of these with a macro
@@ -0,0 +1,95 @@
+//! A single precision [Vec3](../vec3/struct.Vec3.html), for which the forces and the time steps of
+//! [ParticlesF32](../particles_nd/struct.ParticlesF32.html) are computed in f32.
+//! It is converted from and to `Vec3` at the Python boundary, so it is not a Python class itself.
+
+use crate::vec3::Vec3;
+use crate::vector::Vector;
+use std::ops::{Div, Mul};
+
+use derive_more::{Add, AddAssign, Neg, Sub, SubAssign};
+use pyo3::prelude::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default, Neg, Add, AddAssign, Sub, SubAssign)]
+pub struct Vec3F32 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3F32 {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl From<Vec3> for Vec3F32 {
+    fn from(v: Vec3) -> Self {
+        Self::new(v.x as f32, v.y as f32, v.z as f32)
+    }
+}
+
+impl From<Vec3F32> for Vec3 {
+    fn from(v: Vec3F32) -> Self {
+        Self::new(v.x as f64, v.y as f64, v.z as f64)
+    }
+}
+
+impl Vector for Vec3F32 {
+    type Scalar = f32;
+    const DIM: usize = 3;
+
+    fn dot(&self, other: &Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    fn component(&self, i: usize) -> f64 {
+        [self.x, self.y, self.z][i] as f64
+    }
+
+    fn from_components(components: &[f64]) -> Self {
+        Self::new(components[0] as f32, components[1] as f32, components[2] as f32)
+    }
+}
+
+impl Mul<f32> for Vec3F32 {
+    type Output = Self;
+
+    fn mul(self, other: f32) -> Self {
+        Self::new(self.x * other, self.y * other, self.z * other)
+    }
+}
+
+impl Div<f32> for Vec3F32 {
+    type Output = Self;
+
+    fn div(self, other: f32) -> Self {
+        Self::new(self.x / other, self.y / other, self.z / other)
+    }
+}
+
+impl std::iter::Sum for Vec3F32 {
+    fn sum<I>(iter: I) -> Self
+    where
+        I: Iterator<Item = Self>,
+    {
+        let mut result = Self::default();
+        for v in iter {
+            result += v;
+        }
+        result
+    }
+}
+
+// Python sees Vec3
+
+impl IntoPy<PyObject> for Vec3F32 {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        Vec3::from(self).into_py(py)
+    }
+}
+
+impl<'a> FromPyObject<'a> for Vec3F32 {
+    fn extract(obj: &'a PyAny) -> PyResult<Self> {
+        Ok(obj.extract::<Vec3>()?.into())
+    }
+}
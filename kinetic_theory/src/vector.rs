@@ -0,0 +1,166 @@
+//! The vector types of every dimension, which the dimension-generic simulation code is written against:
+//! `f64` in one dimension, [Vec2](../vec2/struct.Vec2.html) in two and [Vec3](../vec3/struct.Vec3.html) in three,
+//! and [Vec3F32](../vec3_f32/struct.Vec3F32.html) in three dimensions and single precision.
+
+use crate::vec2::Vec2;
+use crate::vec3::Vec3;
+use std::fmt::Debug;
+use std::iter::Sum;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use pyo3::prelude::*;
+
+/// The floating point type of the components of a vector, `f32` or `f64`.
+/// The forces and the time steps are computed in this precision.
+pub trait Scalar:
+    Copy
+    + Default
+    + PartialOrd
+    + Debug
+    + Send
+    + Sync
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + Sum
+    + IntoPy<PyObject>
+    + for<'a> FromPyObject<'a>
+{
+    const EPSILON: Self;
+    const MIN_POSITIVE: Self;
+
+    /// Convert a constant or a value from Python, rounding it to this precision.
+    fn from_f64(x: f64) -> Self;
+
+    fn to_f64(self) -> f64;
+
+    fn sqrt(self) -> Self;
+
+    fn powf(self, n: Self) -> Self;
+}
+
+macro_rules! impl_scalar {
+    ($t:ty) => {
+        impl Scalar for $t {
+            const EPSILON: Self = <$t>::EPSILON;
+            const MIN_POSITIVE: Self = <$t>::MIN_POSITIVE;
+
+            fn from_f64(x: f64) -> Self {
+                x as $t
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn sqrt(self) -> Self {
+                <$t>::sqrt(self)
+            }
+
+            fn powf(self, n: Self) -> Self {
+                <$t>::powf(self, n)
+            }
+        }
+    };
+}
+
+impl_scalar!(f32);
+impl_scalar!(f64);
+
+/// A vector of `DIM` components, which can be passed to and from Python,
+/// e.g. to call the external potential.
+pub trait Vector:
+    Copy
+    + Default
+    + PartialEq
+    + Debug
+    + Send
+    + Sync
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Neg<Output = Self>
+    + Mul<<Self as Vector>::Scalar, Output = Self>
+    + Div<<Self as Vector>::Scalar, Output = Self>
+    + Sum
+    + IntoPy<PyObject>
+    + for<'a> FromPyObject<'a>
+{
+    /// The type of the components, in which the vectors are stored and computed with.
+    type Scalar: Scalar;
+
+    /// The number of components.
+    const DIM: usize;
+
+    fn dot(&self, other: &Self) -> Self::Scalar;
+
+    /// The `i`-th component in double precision, where `i < DIM`.
+    fn component(&self, i: usize) -> f64;
+
+    /// The vector with the first `DIM` of the given components.
+    fn from_components(components: &[f64]) -> Self;
+
+    fn abs_sq(&self) -> Self::Scalar {
+        self.dot(self)
+    }
+
+    fn abs(&self) -> Self::Scalar {
+        self.abs_sq().sqrt()
+    }
+
+    fn unit(&self) -> Self {
+        *self / Vector::abs(self)
+    }
+}
+
+impl Vector for f64 {
+    type Scalar = f64;
+    const DIM: usize = 1;
+
+    fn dot(&self, other: &Self) -> f64 {
+        self * other
+    }
+
+    fn component(&self, _i: usize) -> f64 {
+        *self
+    }
+
+    fn from_components(components: &[f64]) -> Self {
+        components[0]
+    }
+}
+
+impl Vector for Vec2 {
+    type Scalar = f64;
+    const DIM: usize = 2;
+
+    fn dot(&self, other: &Self) -> f64 {
+        self * other
+    }
+
+    fn component(&self, i: usize) -> f64 {
+        [self.x, self.y][i]
+    }
+
+    fn from_components(components: &[f64]) -> Self {
+        Vec2::new(components[0], components[1])
+    }
+}
+
+impl Vector for Vec3 {
+    type Scalar = f64;
+    const DIM: usize = 3;
+
+    fn dot(&self, other: &Self) -> f64 {
+        self * other
+    }
+
+    fn component(&self, i: usize) -> f64 {
+        [self.x, self.y, self.z][i]
+    }
+
+    fn from_components(components: &[f64]) -> Self {
+        Vec3::new(components[0], components[1], components[2])
+    }
+}
@@ -1,10 +1,358 @@
 
 import unittest
 
+try:
+    import numpy as np
+except ImportError:
+    np = None
+
+
+def _vtk_arrays(path):
+    # the named data arrays of an ASCII VTK XML file, the points array is named None
+    import xml.etree.ElementTree as ET
+    root = ET.parse(path).getroot()
+    return {a.get("Name"): [float(x) for x in a.text.split()] for a in root.iter("DataArray")}
+
+
+def _harmonic_potential(v):
+    # module level, so it can be pickled
+    from particles import Vec3
+    return Vec3(-v.x, -v.y, -v.z)
+
 
 class TestVec3(unittest.TestCase):
-    # TODO
-    pass
+
+    def test_operators(self):
+        from particles import Vec3
+
+        a = Vec3(1.0, 2.0, 3.0)
+        b = Vec3(4.0, 5.0, 6.0)
+        self.assertEqual(a + b, Vec3(5.0, 7.0, 9.0))
+        self.assertEqual(b - a, Vec3(3.0, 3.0, 3.0))
+        self.assertEqual(a * 2, Vec3(2.0, 4.0, 6.0))
+        self.assertEqual(2.0 * a, Vec3(2.0, 4.0, 6.0))
+        self.assertEqual(a * b, 32.0)
+        self.assertEqual(a / 2, Vec3(0.5, 1.0, 1.5))
+        self.assertEqual(-a, Vec3(-1.0, -2.0, -3.0))
+        self.assertNotEqual(a, b)
+        self.assertNotEqual(a, (1.0, 2.0, 3.0))
+
+        with self.assertRaises(TypeError):
+            a + 1.0
+        with self.assertRaises(TypeError):
+            a * "a"
+        with self.assertRaises(TypeError):
+            hash(a)
+
+    def test_conversion(self):
+        from particles import Vec3
+
+        a = Vec3(1.0, 2.0, 3.0)
+        self.assertEqual(repr(a), "Vec3(1.0, 2.0, 3.0)")
+        self.assertEqual(tuple(a), (1.0, 2.0, 3.0))
+        self.assertEqual(a.to_tuple(), (1.0, 2.0, 3.0))
+        self.assertEqual((a[0], a[1], a[2], a[-1]), (1.0, 2.0, 3.0, 3.0))
+        self.assertEqual(len(a), 3)
+        with self.assertRaises(IndexError):
+            a[3]
+
+        x, y, z = a
+        self.assertEqual(Vec3.from_tuple((x, y, z)), a)
+        self.assertEqual(Vec3.from_tuple([1, 2, 3]), a)
+        with self.assertRaises(ValueError):
+            Vec3.from_tuple((1.0, 2.0))
+
+    @unittest.skipIf(np is None, "requires numpy")
+    def test_numpy(self):
+        from particles import Vec3
+
+        a = Vec3(1.0, 2.0, 3.0)
+        self.assertEqual(list(a.to_array()), [1.0, 2.0, 3.0])
+        self.assertEqual(np.asarray(a).dtype, np.float64)
+        self.assertEqual(np.asarray(a, dtype=np.float32).dtype, np.float32)
+        self.assertEqual(Vec3.from_array(np.array([1.0, 2.0, 3.0])), a)
+        self.assertEqual(a * np.float64(2.0), Vec3(2.0, 4.0, 6.0))
+
+        # NumPy operands on the left defer to Vec3 instead of converting it to an array
+        product = np.float64(2.0) * a
+        self.assertIsInstance(product, Vec3)
+        self.assertEqual(product, Vec3(2.0, 4.0, 6.0))
+        self.assertIsInstance(np.ones(3)[0] * a, Vec3)
+        with self.assertRaises(TypeError):
+            np.ones(3) + a
+
+    @unittest.skipIf(np is None, "requires numpy")
+    def test_numpy_reversed_operands(self):
+        from particles import Mat3, Vec2
+
+        v = Vec2(1.0, 2.0)
+        self.assertIsInstance(np.float64(3.0) * v, Vec2)
+        self.assertEqual(np.float64(3.0) * v, Vec2(3.0, 6.0))
+        m = Mat3.identity()
+        self.assertIsInstance(np.float64(2.0) * m, Mat3)
+        self.assertEqual(np.float64(2.0) * m, Mat3.diagonal(2.0, 2.0, 2.0))
+
+    def assertVecAlmostEqual(self, a, b):
+        for x, y in zip(a, b):
+            self.assertAlmostEqual(x, y)
+
+    def test_geometry(self):
+        import math
+        from particles import Vec3
+
+        x, y, z = Vec3(1.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0), Vec3(0.0, 0.0, 1.0)
+        self.assertEqual(x.cross(y), z)
+        self.assertEqual(y.cross(x), -z)
+
+        a = Vec3(3.0, 4.0, 5.0)
+        self.assertEqual(a.project(x * 2), Vec3(3.0, 0.0, 0.0))
+        self.assertEqual(a.reject(x * 2), Vec3(0.0, 4.0, 5.0))
+        self.assertAlmostEqual(x.angle(y), math.pi / 2)
+        self.assertAlmostEqual(x.angle(-x), math.pi)
+        self.assertEqual(a.angle(a), 0.0)
+
+        self.assertVecAlmostEqual(x.rotate(z * 3, math.pi / 2), y)
+        self.assertVecAlmostEqual(a.rotate(a, 1.0), a)
+        # a rotation by pi / 2 around z as a quaternion, unnormalized
+        q = (2 * math.cos(math.pi / 4), 0.0, 0.0, 2 * math.sin(math.pi / 4))
+        self.assertVecAlmostEqual(x.rotate_quaternion(q), y)
+        self.assertVecAlmostEqual(a.rotate_quaternion(q), a.rotate(z, math.pi / 2))
+
+        self.assertEqual(Vec3(0.0, 0.0, 2.0).try_unit(), z)
+        zero = Vec3(0.0, 0.0, 0.0)
+        with self.assertRaises(ValueError):
+            zero.try_unit()
+        with self.assertRaises(ValueError):
+            a.project(zero)
+        with self.assertRaises(ValueError):
+            a.angle(zero)
+        with self.assertRaises(ValueError):
+            a.rotate(zero, 1.0)
+        with self.assertRaises(ValueError):
+            a.rotate_quaternion((0.0, 0.0, 0.0, 0.0))
+
+
+class TestMat3(unittest.TestCase):
+
+    def test_operators(self):
+        from particles import Mat3, Vec3
+
+        m = Mat3([[1.0, 2.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 2.0]])
+        v = Vec3(1.0, 1.0, 1.0)
+        self.assertEqual(m * v, Vec3(3.0, 1.0, 2.0))
+        self.assertEqual(m * Mat3.identity(), m)
+        self.assertEqual(Mat3.identity() @ m, m)
+        self.assertEqual(2.0 * m, m + m)
+        self.assertEqual(m - m, Mat3.zeros())
+        self.assertEqual(-m / 2, m * -0.5)
+        self.assertEqual(m[0, 1], 2.0)
+        self.assertEqual(m[-1], Vec3(0.0, 0.0, 2.0))
+        self.assertEqual(m.column(1), Vec3(2.0, 1.0, 0.0))
+        self.assertEqual(m.transpose()[1, 0], 2.0)
+        self.assertEqual(m.trace(), 4.0)
+        self.assertEqual(m.determinant(), 2.0)
+        self.assertEqual(m * m.inverse(), Mat3.identity())
+        self.assertEqual(repr(Mat3.diagonal(1, 2, 3)), "Mat3([[1.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 3.0]])")
+        with self.assertRaises(ValueError):
+            Mat3.zeros().inverse()
+        with self.assertRaises(IndexError):
+            m[3, 0]
+        with self.assertRaises(ValueError):
+            Mat3([[1.0, 2.0], [3.0, 4.0]])
+
+        m[2, 2] = 5.0
+        self.assertEqual(m.to_list()[2], [0.0, 0.0, 5.0])
+
+    def test_rotation(self):
+        import copy
+        import math
+        import pickle
+        from particles import Mat3, Vec3
+
+        axis, a = Vec3(1.0, 2.0, 3.0), Vec3(-1.0, 0.5, 2.0)
+        r = Mat3.rotation(axis, 0.7)
+        for x, y in zip(r * a, a.rotate(axis, 0.7)):
+            self.assertAlmostEqual(x, y)
+        q = (math.cos(0.35), *(math.sin(0.35) * axis.unit()))
+        for x, y in zip(Mat3.from_quaternion(q).to_list(), r.to_list()):
+            for xi, yi in zip(x, y):
+                self.assertAlmostEqual(xi, yi)
+        self.assertAlmostEqual(r.determinant(), 1.0)
+
+        self.assertEqual(pickle.loads(pickle.dumps(r)), r)
+        self.assertEqual(copy.deepcopy(r), r)
+
+    def test_eigen(self):
+        from particles import Mat3, Vec3
+
+        # the inertia tensor of two unit masses on the x axis
+        r = Vec3(1.0, 0.0, 0.0)
+        inertia = 2 * (Mat3.identity() * (r * r) - r.outer(r))
+        self.assertTrue(inertia.is_symmetric())
+        values, vectors = inertia.symmetric_eigen()
+        self.assertEqual(len(vectors), 3)
+        for x, y in zip(values, [2.0, 2.0, 0.0]):
+            self.assertAlmostEqual(x, y)
+        self.assertAlmostEqual(abs(vectors[2] * r), 1.0)
+
+
+class TestParticlesND(unittest.TestCase):
+
+    def test_vec2(self):
+        import math
+        from particles import Vec2
+
+        a = Vec2(3.0, 4.0)
+        self.assertEqual(a.abs(), 5.0)
+        self.assertEqual(a + a, 2 * a)
+        self.assertEqual(a * a, 25.0)
+        self.assertEqual(repr(-a), "Vec2(-3.0, -4.0)")
+        self.assertEqual(tuple(a), (3.0, 4.0))
+        self.assertEqual(Vec2(1.0, 0.0).cross(Vec2(0.0, 1.0)), 1.0)
+        for x, y in zip(Vec2(1.0, 0.0).rotate(math.pi / 2), (0.0, 1.0)):
+            self.assertAlmostEqual(x, y)
+        with self.assertRaises(ValueError):
+            Vec2(0.0, 0.0).try_unit()
+
+    def test_callable_observer(self):
+        from particles import Particles1D, Particles2D, Vec2
+
+        ps = Particles2D()
+        ps.add_particle(Vec2(1.0, 0.0), Vec2(0.0, 1.0), 1.0)
+        ps.set_potential(lambda p: -1.0 * p)
+        seen = []
+
+        def callback(simulation):
+            seen.append(simulation.step)
+            if simulation.step == 4:
+                simulation.unset_potential()
+            return simulation.step >= 6
+
+        ps.add_observer(callback, interval=2)
+        ps.run(n=10, h=0.01)
+        self.assertEqual(seen, [0, 2, 4, 6])
+        self.assertEqual(ps.step, 6)
+        self.assertFalse(ps.has_potential())
+
+        with self.assertRaises(TypeError):
+            Particles1D().add_observer(1)
+
+    def test_matches_3d(self):
+        # a planar cloud in 3D and in 2D must evolve identically
+        from particles import Particles, Particles2D, Vec2, Vec3
+
+        ps3, ps2 = Particles(), Particles2D()
+        for i in range(5):
+            ps3.add_particle(Vec3(i, 0.5 * i * i, 0.0), Vec3(0.1, -0.2 * i, 0.0), 1.0)
+            ps2.add_particle(Vec2(i, 0.5 * i * i), Vec2(0.1, -0.2 * i), 1.0)
+        ps3.set_potential(lambda p: -0.01 * p)
+        ps2.set_potential(lambda p: -0.01 * p)
+        ps3.run(n=20, h=0.01)
+        ps2.run(n=20, h=0.01)
+
+        self.assertEqual(ps2.step, 20)
+        self.assertAlmostEqual(ps2.time, 0.2)
+        for p3, p2 in zip(ps3.positions(), ps2.positions()):
+            self.assertEqual((p3.x, p3.y), tuple(p2))
+            self.assertEqual(p3.z, 0.0)
+
+    def test_1d(self):
+        from particles import Particles1D
+
+        ps = Particles1D()
+        ps.add_particles([-1.0, 1.0], [0.0, 0.0], 1.0)
+        ps.add_particle(5.0, 0.0, 2.0)
+        ps.run(n=10, h=0.01)
+        x = ps.positions()
+        self.assertEqual(len(x), 3)
+        # the pair attracts each other
+        self.assertGreater(x[0], -1.0)
+        self.assertLess(x[1], 1.0)
+        self.assertEqual(ps.masses(), [1.0, 1.0, 2.0])
+
+        ps.set_velocities([[0.0], [0.0], [1.0]])
+        self.assertEqual(ps.velocities()[2], 1.0)
+        with self.assertRaises(ValueError):
+            ps.set_positions([0.0, 1.0])
+
+        ps.set_potential(lambda x: "not a float")
+        with self.assertRaises(TypeError):
+            ps.update_yoshida(0.01)
+
+    def test_f32(self):
+        from particles import Particles, ParticlesF32, Vec3
+
+        ps64, ps32 = Particles(), ParticlesF32()
+        for i in range(5):
+            for ps in (ps64, ps32):
+                ps.add_particle(Vec3(i, 0.5 * i, -0.25 * i), Vec3(0.1, -0.2 * i, 0.0), 1.0)
+        # 0.1 is not representable in single precision
+        self.assertNotEqual(ps32.velocities()[0].x, 0.1)
+        self.assertAlmostEqual(ps32.velocities()[0].x, 0.1)
+        # so are masses, which are stored in single precision as well
+        ps32.add_particle(Vec3(10.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 0.1)
+        self.assertNotEqual(ps32.masses()[-1], 0.1)
+        self.assertAlmostEqual(ps32.masses()[-1], 0.1)
+        ps32.set_masses(1.0)
+        ps64.add_particle(Vec3(10.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+
+        ps64.run(n=20, h=0.01)
+        ps32.run(n=20, h=0.01)
+        self.assertIsInstance(ps32.positions()[0], Vec3)
+        for p64, p32 in zip(ps64.positions(), ps32.positions()):
+            for x64, x32 in zip(p64, p32):
+                self.assertAlmostEqual(x64, x32, places=4)
+        self.assertAlmostEqual(ps64.kinetic_energy(), ps32.kinetic_energy(), places=4)
+        self.assertAlmostEqual(ps64.interaction_energy(), ps32.interaction_energy(), places=4)
+        self.assertAlmostEqual(ps64.temperature(), ps32.temperature(), places=4)
+
+        # observers and file formats take a double precision copy
+        import os
+        import tempfile
+        from particles import XyzWriter
+
+        copy = ps32.to_particles()
+        self.assertEqual((copy.step, copy.time), (ps32.step, ps32.time))
+        self.assertEqual(copy.ids(), list(range(6)))
+        self.assertEqual([tuple(p) for p in copy.positions()], [tuple(p) for p in ps32.positions()])
+        path = os.path.join(tempfile.mkdtemp(), "f32.xyz")
+        writer = XyzWriter(path)
+        writer.write(copy)
+        writer.close()
+        self.assertEqual(Particles.read_xyz(path).num_particles(), 6)
+
+        restored = ParticlesF32.from_particles(ps64)
+        self.assertEqual(restored.step, 20)
+        self.assertEqual(restored.masses(), ps64.masses())
+        for p64, p32 in zip(ps64.velocities(), restored.velocities()):
+            for x64, x32 in zip(p64, p32):
+                self.assertAlmostEqual(x64, x32, places=6)
+
+    def test_energies(self):
+        from particles import Particles2D, Vec2
+
+        ps = Particles2D()
+        ps.add_particle(Vec2(0.0, 0.0), Vec2(1.0, 0.0), 2.0)
+        ps.add_particle(Vec2(1.5, 0.0), Vec2(-1.0, 0.0), 2.0)
+        self.assertEqual(ps.kinetic_energy(), 2.0)
+        # 2 (N - 1) degrees of freedom in 2D
+        self.assertEqual(ps.temperature(), 2.0)
+        self.assertLess(ps.interaction_energy(), 0.0)
+
+    @unittest.skipIf(np is None, "requires numpy")
+    def test_numpy(self):
+        from particles import Particles1D, Particles2D
+
+        ps = Particles2D()
+        ps.add_particles(np.zeros((4, 2)), np.ones((4, 2)), 1.0)
+        self.assertEqual(ps.positions_array().shape, (4, 2))
+        with self.assertRaises(ValueError):
+            ps.set_positions(np.zeros((4, 3)))
+
+        ps = Particles1D()
+        ps.add_particles(np.arange(3.0), np.zeros(3), 1.0)
+        self.assertEqual(ps.positions_array().shape, (3, 1))
+        self.assertEqual(list(ps.positions_array()[:, 0]), [0.0, 1.0, 2.0])
 
 
 class TestParticles(unittest.TestCase):
@@ -49,6 +397,1195 @@ class TestParticles(unittest.TestCase):
 
         self.instance.unset_potential()
 
+    def _cloud(self, n):
+        from particles import Particles, Vec3
+
+        ps = Particles()
+        for i in range(n):
+            ps.add_particle(Vec3(i % 10, i // 10, 0.5 * i), Vec3(0.0, 0.1, 0.0), 1.0)
+        return ps
+
+    def test_interrupt(self):
+        import _thread
+        import threading
+
+        ps = self._cloud(200)
+        # the timer thread can only interrupt if the GIL is released during the run
+        timer = threading.Timer(0.05, _thread.interrupt_main)
+        timer.start()
+        with self.assertRaises(KeyboardInterrupt):
+            ps.run(n=20_000, h=0.001)
+        timer.join()
+
+        self.assertGreater(ps.step, 0)
+        self.assertLess(ps.step, 20_000)
+        self.assertAlmostEqual(ps.time, ps.step * 0.001)
+
+    def test_background(self):
+        import time
+        from particles import ShapeObserver
+
+        ps, reference = self._cloud(20), self._cloud(20)
+        observer = ShapeObserver(interval=10)
+        ps.add_observer(observer)
+        run = ps.run_background(n=50, h=0.01)
+        self.assertEqual(run.n, 50)
+        run.wait()
+        self.assertTrue(run.done())
+        self.assertEqual(run.steps, 50)
+        run.wait()
+
+        reference.run(n=50, h=0.01)
+        self.assertEqual(ps.step, 50)
+        self.assertEqual(ps.positions(), reference.positions())
+        self.assertEqual(len(observer.shapes), 6)
+
+        # a cancelled run leaves the state of the last completed step
+        ps = self._cloud(200)
+        run = ps.run_background(n=20_000, h=0.001)
+        while run.steps == 0:
+            time.sleep(0.001)
+        run.cancel()
+        run.wait()
+        self.assertEqual(ps.step, run.steps)
+        self.assertLess(ps.step, 20_000)
+
+    def test_run_timer(self):
+        import contextlib
+        import io
+
+        ps = self._cloud(10)
+        reports = []
+        ps.run_timer(n=20, h=0.01, callback=reports.append, interval=0.0)
+        self.assertEqual(ps.step, 20)
+        self.assertEqual([p.steps for p in reports], list(range(21)))
+        self.assertIsNone(reports[0].eta)
+        last = reports[-1]
+        self.assertEqual((last.n, last.percent, last.eta), (20, 100.0, 0.0))
+        self.assertGreater(last.steps_per_second, 0.0)
+        self.assertEqual([p.success for p in reports], [None] * 20 + [True])
+
+        # the last step is always reported
+        reports.clear()
+        ps.run_timer(n=5, h=0.01, callback=reports.append, interval=1000.0)
+        self.assertEqual([p.steps for p in reports], [0, 5])
+
+        reports.clear()
+        ps.run_timer(n=5, h=0.01, callback=reports.append, interval=float("inf"))
+        self.assertEqual([p.steps for p in reports], [0, 5])
+        for interval in (-1.0, float("nan")):
+            with self.assertRaises(ValueError):
+                ps.run_timer(n=5, h=0.01, callback=reports.append, interval=interval)
+
+        output = io.StringIO()
+        with contextlib.redirect_stdout(output):
+            ps.run_timer(n=5, h=0.01)
+        self.assertIn("Progress: 0.00%", output.getvalue())
+        self.assertIn("Done.", output.getvalue())
+
+    def test_run_timer_stopped(self):
+        # the final report has the step at which an observer stopped the run
+        ps = self._cloud(5)
+        ps.add_observer(lambda simulation: simulation.step >= 3)
+        reports = []
+        ps.run_timer(n=10, h=0.01, callback=reports.append, interval=float("inf"))
+        self.assertEqual([(p.steps, p.success) for p in reports], [(0, None), (3, True)])
+
+    def test_run_timer_error(self):
+        import contextlib
+        import io
+
+        def potential_err(_v):
+            raise ValueError("I can't compute that")
+
+        ps = self._cloud(5)
+        ps.set_potential(potential_err)
+        output = io.StringIO()
+        with contextlib.redirect_stdout(output), self.assertRaises(ValueError):
+            ps.run_timer(n=10, h=0.01)
+        self.assertNotIn("Done.", output.getvalue())
+
+        reports = []
+        with self.assertRaises(ValueError):
+            ps.run_timer(n=10, h=0.01, callback=reports.append, interval=float("inf"))
+        self.assertEqual([(p.steps, p.success) for p in reports], [(0, None), (0, False)])
+        ps.unset_potential()
+
+        def callback(progress):
+            if progress.steps == 3:
+                raise RuntimeError("stop")
+
+        with self.assertRaises(RuntimeError):
+            ps.run_timer(n=10, h=0.01, callback=callback, interval=0.0)
+        self.assertEqual(ps.step, 3)
+
+    def test_background_error(self):
+        def potential_err(_v):
+            raise ValueError("I can't compute that")
+
+        ps = self._cloud(5)
+        ps.set_potential(potential_err)
+        initial = ps.positions()
+        run = ps.run_background(n=10, h=0.01)
+        with self.assertRaises(ValueError):
+            run.wait()
+        self.assertEqual(ps.step, 0)
+        # the failed step does not leave the particles half-updated
+        self.assertEqual(ps.positions(), initial)
+        self.assertEqual(ps.time, 0.0)
+
+    def test_step_error(self):
+        def potential_err(_v):
+            raise ValueError("I can't compute that")
+
+        ps = self._cloud(5)
+        ps.run(n=1, h=0.01)
+        positions, velocities = ps.positions(), ps.velocities()
+        ps.set_potential(potential_err)
+        with self.assertRaises(ValueError):
+            ps.update_yoshida(0.01)
+        with self.assertRaises(ValueError):
+            ps.run(n=2, h=0.01)
+        self.assertEqual((ps.step, ps.positions(), ps.velocities()), (1, positions, velocities))
+
+    def test_interrupt_potential(self):
+        import _thread
+
+        calls = []
+
+        def potential(p):
+            calls.append(p)
+            # interrupt in the middle of the second step, Ctrl-C is raised in the potential
+            if len(calls) == 5 * 3 + 2:
+                _thread.interrupt_main()
+                for _ in range(1000):
+                    pass
+            return -0.01 * p
+
+        ps, reference = self._cloud(5), self._cloud(5)
+        ps.set_potential(potential)
+        reference.set_potential(lambda p: -0.01 * p)
+        with self.assertRaises(KeyboardInterrupt):
+            ps.run(n=10, h=0.01)
+        reference.run(n=1, h=0.01)
+
+        self.assertEqual(ps.step, 1)
+        self.assertEqual(ps.positions(), reference.positions())
+        self.assertEqual(ps.velocities(), reference.velocities())
+
+    def test_bulk_setters(self):
+        from particles import Vec3
+
+        self.instance.add_particle(Vec3(1.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+        self.instance.set_positions([Vec3(0.0, 1.0, 2.0), Vec3(3.0, 4.0, 5.0)])
+        self.assertEqual([(p.x, p.y, p.z) for p in self.instance.positions()], [(0.0, 1.0, 2.0), (3.0, 4.0, 5.0)])
+        self.instance.set_velocities([(1, 0, 0), [0.0, 1.0, 0.0]])
+        self.assertEqual([v.y for v in self.instance.velocities()], [0.0, 1.0])
+        self.instance.set_masses([2.0, 3.0])
+        self.assertEqual(self.instance.masses(), [2.0, 3.0])
+
+        with self.assertRaises(ValueError):
+            self.instance.set_positions([Vec3(0.0, 0.0, 0.0)])
+        with self.assertRaises(ValueError):
+            self.instance.set_velocities([(1.0, 0.0), (0.0, 1.0)])
+        with self.assertRaises(ValueError):
+            self.instance.set_masses([1.0])
+
+    def test_add_remove(self):
+        import os
+        import pickle
+        import tempfile
+        from particles import Particles, Vec3
+
+        ids = self.instance.add_particles([(i, 0, 0) for i in range(1, 6)], [Vec3(0.0, 0.0, 0.0)] * 5, 2.0)
+        self.assertEqual(ids, [1, 2, 3, 4, 5])
+        self.assertEqual(self.instance.num_particles(), 6)
+        self.assertEqual(self.instance.masses(), [1.0] + [2.0] * 5)
+        self.assertEqual(self.instance.add_particle(Vec3(6.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0), 6)
+
+        self.assertEqual(self.instance.remove_particles([0, -1, 2]), 3)
+        self.assertEqual(self.instance.ids(), [1, 3, 4, 5])
+        self.assertEqual([p.x for p in self.instance.positions()], [1.0, 3.0, 4.0, 5.0])
+        self.assertEqual(self.instance.remove_where([False, True, False, False]), 1)
+        self.assertEqual(self.instance.ids(), [1, 4, 5])
+
+        # IDs are never reused
+        self.assertEqual(self.instance.add_particle(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0), 7)
+        self.assertEqual(pickle.loads(pickle.dumps(self.instance)).ids(), [1, 4, 5, 7])
+
+        directory = tempfile.mkdtemp()
+        self.instance.save(os.path.join(directory, "state.chk"))
+        restored = Particles.load(os.path.join(directory, "state.chk"))
+        self.assertEqual(restored.ids(), [1, 4, 5, 7])
+        self.assertEqual(restored.add_particle(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0), 8)
+
+        self.instance.write_lammps_data(os.path.join(directory, "state.data"))
+        self.assertEqual(Particles.read_lammps_data(os.path.join(directory, "state.data")).ids(), [1, 4, 5, 7])
+
+        with self.assertRaises(IndexError):
+            self.instance.remove_particles([4])
+        with self.assertRaises(ValueError):
+            self.instance.remove_where([True])
+        with self.assertRaises(ValueError):
+            self.instance.add_particles([(0, 0, 0)], [(0, 0, 0), (0, 0, 0)], 1.0)
+        with self.assertRaises(ValueError):
+            self.instance.add_particles([(0, 0, 0)], [(0, 0, 0)], [1.0, 2.0])
+        self.assertEqual(self.instance.num_particles(), 4)
+
+    def test_attributes(self):
+        import copy
+        import os
+        import pickle
+        import tempfile
+        from particles import Particles, Vec3, TrajectoryReader, TrajectoryWriter
+
+        self.instance.add_particles([(1, 0, 0), (2, 0, 0)], [(0, 0, 0)] * 2, 1.0)
+        self.instance.set_attribute("charge", [1.0, -1.0, 0.5])
+        self.instance.set_attribute("tag", 7)
+        self.assertEqual(self.instance.attribute_names(), ["charge", "tag"])
+        self.assertEqual(self.instance.attribute("tag"), [7.0] * 3)
+
+        # new particles have zero attributes unless given
+        self.instance.add_particle(Vec3(3.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+        self.instance.add_particles([(4, 0, 0)], [(0, 0, 0)], 1.0, attributes={"charge": 2.0, "energy": [3.0]})
+        self.assertEqual(self.instance.attribute("charge"), [1.0, -1.0, 0.5, 0.0, 2.0])
+        self.assertEqual(self.instance.attribute("energy"), [0.0, 0.0, 0.0, 0.0, 3.0])
+
+        self.instance.remove_particles([1])
+        self.assertEqual(self.instance.attribute("charge"), [1.0, 0.5, 0.0, 2.0])
+        self.instance.set_ids([10, 20, 30, 40])
+        self.assertEqual(self.instance.add_particle(Vec3(5.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0), 41)
+
+        for restored in [pickle.loads(pickle.dumps(self.instance)), copy.copy(self.instance)]:
+            self.assertEqual(restored.ids(), [10, 20, 30, 40, 41])
+            self.assertEqual(restored.attribute("charge"), [1.0, 0.5, 0.0, 2.0, 0.0])
+
+        directory = tempfile.mkdtemp()
+        self.instance.save(os.path.join(directory, "state.chk"))
+        self.assertEqual(Particles.load(os.path.join(directory, "state.chk")).attribute("energy")[3], 3.0)
+
+        path = os.path.join(directory, "state.xyz")
+        from particles import XyzWriter
+        XyzWriter(path).write(self.instance)
+        restored = Particles.read_xyz(path)
+        self.assertEqual(restored.attribute_names(), ["charge", "energy", "tag"])
+        self.assertEqual(restored.attribute("charge"), self.instance.attribute("charge"))
+        self.assertEqual(restored.ids(), self.instance.ids())
+
+        path = os.path.join(directory, "run.traj")
+        writer = TrajectoryWriter(path)
+        writer.write(self.instance)
+        self.instance.set_attribute("spin", 0.5)
+        writer.write(self.instance)
+        writer.close()
+        self.instance.remove_attribute("spin")
+        reader = TrajectoryReader(path)
+        self.assertEqual(reader.ids(0), [10, 20, 30, 40, 41])
+        self.assertEqual(reader.attribute_names(0), ["charge", "energy", "tag"])
+        self.assertEqual(reader.attribute_names(1), ["charge", "energy", "spin", "tag"])
+        restored = reader.particles(0)
+        self.assertEqual(restored.ids(), [10, 20, 30, 40, 41])
+        self.assertEqual(restored.attribute_names(), ["charge", "energy", "tag"])
+        self.assertEqual(restored.attribute("charge"), [1.0, 0.5, 0.0, 2.0, 0.0])
+        self.assertEqual(restored.attribute("energy"), [0.0, 0.0, 0.0, 3.0, 0.0])
+        self.assertEqual(reader.particles(1).attribute("spin"), [0.5] * 5)
+
+        self.instance.remove_attribute("tag")
+        self.assertEqual(self.instance.attribute_names(), ["charge", "energy"])
+        with self.assertRaises(KeyError):
+            self.instance.attribute("tag")
+        with self.assertRaises(KeyError):
+            self.instance.remove_attribute("tag")
+        with self.assertRaises(ValueError):
+            self.instance.set_attribute("charge", [1.0])
+        with self.assertRaises(ValueError):
+            self.instance.set_attribute("2nd", 0.0)
+        with self.assertRaises(ValueError):
+            self.instance.set_attribute("mass", 0.0)
+        with self.assertRaises(ValueError):
+            self.instance.set_ids([1, 1, 2, 3, 4])
+
+    @unittest.skipIf(np is None, "requires numpy")
+    def test_numpy_arrays(self):
+        from particles import Vec3
+
+        for i in range(4):
+            self.instance.add_particle(Vec3(i, 2.0 * i, 0.5), Vec3(0.0, 0.0, -i), 1.0 + i)
+
+        x = self.instance.positions_array()
+        self.assertEqual(x.shape, (5, 3))
+        self.assertEqual(x.dtype, np.float64)
+        self.assertEqual(list(x[2]), [1.0, 2.0, 0.5])
+        self.assertEqual(list(self.instance.velocities_array()[:, 2]), [0.0, 0.0, -1.0, -2.0, -3.0])
+        self.assertEqual(list(self.instance.masses_array()), self.instance.masses())
+
+        self.instance.set_positions(x - x.mean(axis=0))
+        np.testing.assert_allclose(self.instance.positions_array().mean(axis=0), 0.0, atol=1e-12)
+        self.instance.set_velocities(np.ones((5, 3), dtype=np.int64))
+        self.assertEqual(self.instance.velocities()[4].z, 1.0)
+        self.instance.set_masses(np.full(5, 2.0))
+        self.assertEqual(self.instance.masses(), [2.0] * 5)
+
+        with self.assertRaises(ValueError):
+            self.instance.set_positions(np.zeros((5, 2)))
+        with self.assertRaises(ValueError):
+            self.instance.set_positions(np.zeros((4, 3)))
+
+        ids = self.instance.add_particles(np.full((3, 3), 100.0), np.zeros((3, 3)), np.full(3, 4.0))
+        self.assertEqual(ids, [5, 6, 7])
+        x = self.instance.positions_array()
+        self.assertEqual(self.instance.remove_where(x[:, 2] > 50.0), 3)
+        self.assertEqual(self.instance.ids(), [0, 1, 2, 3, 4])
+
+        self.instance.set_attribute("charge", np.arange(5.0))
+        self.assertEqual(list(self.instance.attribute_array("charge")), [0.0, 1.0, 2.0, 3.0, 4.0])
+
+    def test_structure_factor(self):
+        from particles import Vec3
+
+        for i in range(9):
+            self.instance.add_particle(Vec3(i, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+
+        # the forward scattering peak is S(0) = N
+        s0, = self.instance.structure_factor([Vec3(0.0, 0.0, 0.0)])
+        self.assertAlmostEqual(s0, 10.0)
+
+        s = self.instance.structure_factor_spherical([0.5, 1.0, 2.0], n_directions=16)
+        self.assertEqual(len(s), 3)
+        self.assertTrue(all(x >= 0.0 for x in s))
+
+
+    def test_time_correlation(self):
+        from particles import TimeCorrelation, Particles, Vec3
+
+        # a single free particle moves ballistically
+        ps = Particles()
+        ps.add_particle(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 2.0, 0.0), 1.0)
+
+        tc = TimeCorrelation(points_per_level=4, num_levels=4)
+        for _ in range(40):
+            tc.push(ps)
+            ps.run(n=1, h=0.1)
+
+        self.assertEqual(tc.num_frames, 40)
+        for lag, msd, vacf in zip(tc.lags(), tc.msd(), tc.vacf()):
+            self.assertAlmostEqual(msd, 5.0 * lag ** 2)
+            self.assertAlmostEqual(vacf, 5.0)
+
+        with self.assertRaises(ValueError):
+            ps.run(n=2, h=0.1)
+            tc.push(ps)
+
+    def test_grid_fields(self):
+        from particles import Vec3
+
+        for i in range(9):
+            self.instance.add_particle(Vec3(i, 0.5 * i, 0.0), Vec3(1.0, 0.0, 0.0), 2.0)
+
+        fields = self.instance.grid_fields([8, 4], scheme="tsc")
+        self.assertEqual(fields.shape, [8, 4])
+        # the particles lie in the xy plane
+        self.assertEqual(fields.dimensions, 2)
+        self.assertEqual(len(fields.edges(0)), 9)
+        self.assertEqual(len(fields.centers(1)), 4)
+
+        with self.assertRaises(ValueError):
+            self.instance.grid_fields([8, 4], scheme="unknown")
+        with self.assertRaises(ValueError):
+            self.instance.grid_fields([8, 4, 2, 1])
+
+    @unittest.skipIf(np is None, "requires numpy")
+    def test_grid_fields_mass(self):
+        from particles import Vec3
+
+        for i in range(9):
+            self.instance.add_particle(Vec3(i, 0.5 * i, 0.0), Vec3(1.0, 0.0, 0.0), 2.0)
+
+        # all particles are well inside the grid, so no mass is lost
+        fields = self.instance.grid_fields([10], ranges=[(-5.0, 15.0)])
+        self.assertAlmostEqual(fields.density.sum() * 2.0, 19.0)
+        self.assertEqual(fields.velocity.shape, (10, 3))
+
+    def test_grid_fields_temperature(self):
+        import os
+        import tempfile
+        from particles import Particles, Vec3
+
+        # a planar pair with thermal energy 1 has two degrees of freedom per particle
+        ps = Particles()
+        ps.add_particle(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0), 1.0)
+        ps.add_particle(Vec3(0.5, 0.5, 0.0), Vec3(-1.0, 0.0, 0.0), 1.0)
+        fields = ps.grid_fields([1], ranges=[(-1.0, 1.0)], scheme="ngp")
+        self.assertEqual(fields.dimensions, 2)
+
+        path = os.path.join(tempfile.mkdtemp(), "fields.vti")
+        fields.write_vti(path)
+        self.assertAlmostEqual(_vtk_arrays(path)["temperature"][0], 0.5)
+
+    def test_grid_fields_lower_edge(self):
+        import os
+        import tempfile
+        from particles import Particles, Vec3
+
+        # the lower edge of a cell belongs to it, like in a lattice starting at the lower bound
+        ps = Particles()
+        ps.add_particle(Vec3(-1.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+        ps.add_particle(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 2.0)
+
+        path = os.path.join(tempfile.mkdtemp(), "fields.vti")
+        ps.grid_fields([2], ranges=[(-1.0, 1.0)], scheme="ngp").write_vti(path)
+        self.assertEqual(_vtk_arrays(path)["density"], [1.0, 2.0])
+
+    def test_shape(self):
+        from particles import Particles, Vec3
+        from random import gauss, seed
+
+        seed(0)
+        ps = Particles()
+        for _ in range(5000):
+            ps.add_particle(Vec3(gauss(0.0, 2.0), gauss(0.0, 1.0), 0.0), Vec3(gauss(0.0, 1.0), 0.0, 0.0), 1.0)
+
+        shape = ps.shape()
+        self.assertAlmostEqual(shape.position.gaussian_widths.x, 2.0, delta=0.1)
+        self.assertAlmostEqual(shape.position.gaussian_widths.y, 1.0, delta=0.05)
+        self.assertAlmostEqual(shape.position.aspect_ratio, 2.0, delta=0.1)
+        self.assertAlmostEqual(shape.position.eccentricity, 0.75 ** 0.5, delta=0.02)
+        self.assertAlmostEqual(abs(shape.position.principal_axes[0].x), 1.0, delta=0.01)
+        self.assertAlmostEqual(shape.momentum.rms_widths.x, 1.0, delta=0.05)
+
+    def test_shape_observer(self):
+        from particles import ShapeObserver
+
+        observer = ShapeObserver(interval=5)
+        self.instance.add_observer(observer)
+        self.instance.run(n=10, h=0.01)
+
+        self.assertEqual(len(observer.shapes), 3)
+        self.assertAlmostEqual(observer.times[-1], 0.1)
+
+        with self.assertRaises(TypeError):
+            self.instance.add_observer(object())
+
+    def test_energy_observers(self):
+        from particles import EnergyObserver, TemperatureObserver
+
+        ps = self._cloud(10)
+        energies, temperatures = EnergyObserver(interval=2), TemperatureObserver(interval=5)
+        ps.add_observer(energies)
+        ps.add_observer(temperatures)
+        ps.run(n=10, h=0.01)
+
+        self.assertEqual(len(energies.times), 6)
+        self.assertEqual(len(temperatures.temperatures), 3)
+        self.assertAlmostEqual(energies.kinetic[-1], ps.kinetic_energy())
+        self.assertAlmostEqual(energies.interaction[-1], ps.interaction_energy())
+        for k, u, e in zip(energies.kinetic, energies.interaction, energies.total):
+            self.assertAlmostEqual(k + u, e)
+        self.assertAlmostEqual(temperatures.temperatures[-1], ps.temperature())
+
+        energies.clear()
+        self.assertEqual(energies.total, [])
+
+    def test_snapshot_observer(self):
+        from particles import SnapshotObserver
+
+        ps = self._cloud(5)
+        ps.set_attribute("tag", 1.0)
+        observer = SnapshotObserver(interval=4)
+        ps.add_observer(observer)
+        ps.run(n=8, h=0.01)
+
+        snapshots = observer.snapshots
+        self.assertEqual([s.step for s in snapshots], [0, 4, 8])
+        last = snapshots[-1]
+        self.assertEqual(last.num_particles(), 5)
+        self.assertEqual(last.positions, ps.positions())
+        self.assertEqual(last.velocities, ps.velocities())
+        self.assertEqual(last.ids, ps.ids())
+        self.assertEqual(last.attributes, {"tag": [1.0] * 5})
+        self.assertAlmostEqual(observer.times[-1], ps.time)
+        # snapshots are copies
+        self.assertNotEqual(snapshots[0].positions, last.positions)
+
+    def test_observer_base(self):
+        import os
+        import tempfile
+        import particles
+
+        directory = tempfile.mkdtemp()
+        recorders = [
+            particles.ShapeObserver(interval=3),
+            particles.EnergyObserver(interval=3),
+            particles.TemperatureObserver(interval=3),
+            particles.SnapshotObserver(interval=3),
+            particles.VelocityDistributionObserver(interval=3),
+            particles.ClusterStatisticsObserver(1.5, interval=3),
+        ]
+        writers = [
+            particles.XyzWriter(os.path.join(directory, "run.xyz"), interval=3),
+            particles.LammpsDumpWriter(os.path.join(directory, "run.dump"), interval=3),
+            particles.TrajectoryWriter(os.path.join(directory, "run.traj"), interval=3),
+            particles.VtkWriter(os.path.join(directory, "run"), interval=3, bins=[2, 2, 2]),
+        ]
+
+        ps = self._cloud(5)
+        for observer in recorders + writers:
+            self.assertIsInstance(observer, particles.ObserverBase)
+            self.assertEqual(observer.interval, 3)
+            ps.add_observer(observer)
+        ps.run(n=6, h=0.01)
+
+        for observer in recorders:
+            self.assertEqual(len(observer.times), 3)
+            self.assertAlmostEqual(observer.times[-1], ps.time)
+            observer.clear()
+            self.assertEqual(observer.times, [])
+        for writer in writers:
+            self.assertEqual(writer.frames, 3)
+            writer.close()
+
+    def test_initial_state_observed_once(self):
+        from particles import Particles2D, SnapshotObserver
+
+        ps = self._cloud(5)
+        observer = SnapshotObserver()
+        seen = []
+        ps.add_observer(observer)
+        ps.add_observer(lambda simulation: seen.append(simulation.step))
+        ps.run(n=0, h=0.01)
+        ps.run(n=2, h=0.01)
+        ps.run(n=1, h=0.01)
+        self.assertEqual([s.step for s in observer.snapshots], [0, 1, 2, 3])
+        self.assertEqual(seen, [0, 1, 2, 3])
+
+        observer.clear()
+        ps = self._cloud(5)
+        ps.add_observer(observer)
+        run = ps.run_background(n=0, h=0.01)
+        run.wait()
+        ps.run(n=1, h=0.01)
+        self.assertEqual([s.step for s in observer.snapshots], [0, 1])
+
+        ps, seen = Particles2D(), []
+        ps.add_observer(lambda simulation: seen.append(simulation.step))
+        ps.run(n=0, h=0.01)
+        ps.run(n=1, h=0.01)
+        self.assertEqual(seen, [0, 1])
+
+    def test_callable_observer(self):
+        from particles import Particles, ShapeObserver
+
+        ps = self._cloud(5)
+        seen = []
+
+        def callback(simulation):
+            self.assertIsInstance(simulation, Particles)
+            seen.append(simulation.step)
+            return simulation.step >= 6
+
+        ps.add_observer(callback, interval=3)
+        ps.run(n=20, h=0.01)
+        self.assertEqual(seen, [0, 3, 6])
+        self.assertEqual(ps.step, 6)
+
+        with self.assertRaises(ValueError):
+            ps.run_background(n=10, h=0.01)
+
+        ps.clear_observers()
+        ps.run(n=2, h=0.01)
+        self.assertEqual(seen, [0, 3, 6])
+
+        with self.assertRaises(ValueError):
+            ps.add_observer(ShapeObserver(), interval=2)
+        with self.assertRaises(TypeError):
+            ps.add_observer(1)
+
+    def test_callable_observer_modifies(self):
+        ps = self._cloud(5)
+        ps.set_potential(lambda p: -0.01 * p)
+
+        def callback(simulation):
+            if simulation.step == 2:
+                simulation.unset_potential()
+
+        ps.add_observer(callback)
+        ps.run(n=5, h=0.01)
+        self.assertEqual(ps.step, 5)
+        self.assertFalse(ps.has_potential())
+
+    def test_callable_observer_error(self):
+        ps = self._cloud(5)
+
+        def callback(simulation):
+            if simulation.step == 2:
+                raise RuntimeError("stop")
+
+        ps.add_observer(callback)
+        with self.assertRaises(RuntimeError):
+            ps.run(n=5, h=0.01)
+        self.assertEqual(ps.step, 2)
+
+    def test_shape_observer_stop(self):
+        from particles import ShapeObserver
+
+        ps = self._cloud(10)
+        ps.set_velocities([(0.0, 0.0, 10.0)] * 5 + [(0.0, 0.0, -10.0)] * 5)
+        observer = ShapeObserver(max_radius=10.0)
+        ps.add_observer(observer)
+        ps.run(n=1000, h=0.01)
+
+        self.assertEqual(observer.max_radius, 10.0)
+        self.assertLess(ps.step, 1000)
+        self.assertGreater(observer.shapes[-1].position.rms_radius, 10.0)
+        self.assertLessEqual(observer.shapes[-2].position.rms_radius, 10.0)
+
+    def test_velocity_distribution(self):
+        from particles import Particles, Vec3
+        from random import gauss, seed
+
+        seed(1)
+        ps = Particles()
+        for _ in range(20000):
+            ps.add_particle(Vec3(0.0, 0.0, 0.0), Vec3(gauss(0.0, 1.5), gauss(0.0, 1.5), gauss(0.0, 1.5)), 1.0)
+
+        dist = ps.velocity_distribution(bins=40)
+        self.assertAlmostEqual(dist.temperature, 2.25, delta=0.05)
+        self.assertLess(dist.kl_divergence_speed(), 0.01)
+        self.assertLess(dist.kl_divergence_component(2), 0.01)
+        self.assertAlmostEqual(dist.h_function(), dist.h_function_equilibrium(), delta=0.05)
+
+        # a shell in velocity space is far from equilibrium
+        shell = Particles()
+        for _ in range(2000):
+            v = Vec3(gauss(0.0, 1.0), gauss(0.0, 1.0), gauss(0.0, 1.0))
+            shell.add_particle(Vec3(0.0, 0.0, 0.0), v.unit(), 1.0)
+
+        self.assertGreater(shell.velocity_distribution().kl_divergence_speed(), 0.5)
+
+    def test_velocity_distribution_observer(self):
+        from particles import VelocityDistributionObserver
+
+        observer = VelocityDistributionObserver(interval=2, v_max=1.0, dimensions=2)
+        self.instance.add_observer(observer)
+        self.instance.run(n=4, h=0.01)
+
+        self.assertEqual(len(observer.records), 3)
+        self.assertEqual(len(observer.records[0].kl_divergence_components), 2)
+        self.assertEqual(observer.distribution.samples, 3)
+
+    def test_friends_of_friends(self):
+        from particles import Vec3
+
+        # the instance already has a particle at the origin, which joins the first chain
+        for i in range(1, 5):
+            self.instance.add_particle(Vec3(i, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+        self.instance.add_particle(Vec3(50.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+        for i in range(3):
+            self.instance.add_particle(Vec3(100.0, i, 0.0), Vec3(0.0, 0.0, 0.0), 2.0)
+
+        clusters = self.instance.friends_of_friends(1.5, min_size=2)
+        self.assertEqual(clusters.num_clusters, 2)
+        self.assertEqual(clusters.sizes, [5, 3])
+        self.assertEqual(clusters.members[1], [6, 7, 8])
+        self.assertEqual(clusters.unclustered, [5])
+        self.assertEqual(clusters.labels[5], -1)
+        self.assertAlmostEqual(clusters.centers_of_mass[0].x, 2.0)
+        self.assertAlmostEqual(clusters.masses[1], 6.0)
+
+        with self.assertRaises(ValueError):
+            self.instance.friends_of_friends(0.0)
+
+    def test_dbscan(self):
+        from particles import Vec3
+
+        for i in range(1, 6):
+            self.instance.add_particle(Vec3(0.5 * i, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+        # a close particle with a very different velocity, which is noise in phase space
+        self.instance.add_particle(Vec3(3.5, 0.0, 0.0), Vec3(10.0, 0.0, 0.0), 1.0)
+        self.instance.add_particle(Vec3(50.0, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+
+        clusters = self.instance.dbscan(1.1, min_pts=3)
+        self.assertEqual(clusters.members, [[0, 1, 2, 3, 4, 5, 6]])
+        self.assertEqual(clusters.unclustered, [7])
+
+        clusters = self.instance.dbscan(1.1, min_pts=3, velocity_weight=1.0)
+        self.assertEqual(clusters.members, [[0, 1, 2, 3, 4, 5]])
+
+    def test_cluster_tracker(self):
+        from particles import ClusterTracker, Particles, Vec3
+
+        def snapshot(xs):
+            ps = Particles()
+            for x in xs:
+                ps.add_particle(Vec3(x, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+            return ps.friends_of_friends(1.5)
+
+        tracker = ClusterTracker()
+        # the clusters of the first snapshot are not nucleations
+        self.assertEqual(tracker.update(snapshot([0, 1, 2, 3, 10, 11, 12, 50, 60])), [])
+        self.assertEqual(tracker.track_ids, [0, 1])
+
+        events = tracker.update(snapshot([0, 1, 2, 3, 4, 5, 6, 50, 51]))
+        self.assertEqual(sorted(e.kind for e in events), ["merger", "nucleation"])
+        merger, = [e for e in events if e.kind == "merger"]
+        self.assertEqual(merger.tracks_before, [0, 1])
+        self.assertEqual(merger.tracks_after, [0])
+        self.assertEqual(tracker.track_ids, [0, 2])
+
+        events = tracker.update(snapshot([0, 1, 2, 3, 4, 5, 6, 50, 60]))
+        self.assertEqual([e.kind for e in events], ["evaporation"])
+        self.assertEqual(events[0].tracks_before, [2])
+        self.assertEqual(len(tracker.events), 3)
+
+        tracker.reset()
+        self.assertEqual(tracker.update(snapshot([0, 1])), [])
+        self.assertEqual(tracker.track_ids, [0])
+
+    def test_cluster_tracker_removed_particles(self):
+        from particles import ClusterTracker, Particles, Vec3
+
+        ps = Particles()
+        for x in [0, 1, 2, 3, 10, 11, 12]:
+            ps.add_particle(Vec3(x, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+
+        tracker = ClusterTracker()
+        tracker.update(ps.friends_of_friends(1.5))
+        self.assertEqual(tracker.track_ids, [0, 1])
+
+        # the remaining particles shift to lower indices, but keep their IDs
+        ps.remove_particles([0, 1])
+        clusters = ps.friends_of_friends(1.5)
+        self.assertEqual(clusters.member_ids, [[4, 5, 6], [2, 3]])
+        self.assertEqual(tracker.update(clusters), [])
+        self.assertEqual(tracker.track_ids, [1, 0])
+
+    def test_cluster_statistics(self):
+        from particles import Particles, Vec3
+
+        ps = Particles()
+        for x in [0, 1, 2, 10, 11, 50]:
+            ps.add_particle(Vec3(x, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+        ps.add_particle(Vec3(50.5, 0.0, 0.0), Vec3(1.0, 0.0, 0.0), 1.0)
+
+        stats = ps.cluster_statistics(ps.friends_of_friends(1.5))
+        self.assertEqual(stats.num_clusters, 3)
+        self.assertEqual(stats.size_histogram, {2: 2, 3: 1})
+        self.assertAlmostEqual(stats.largest_cluster_fraction, 3 / 7)
+        self.assertAlmostEqual(stats.clustered_fraction, 1.0)
+        # only the last pair moves relative to its center of mass
+        self.assertEqual(stats.temperatures[:2], [0.0, 0.0])
+        self.assertAlmostEqual(stats.temperatures[2], 2 * 0.25 / 3)
+
+    def test_cluster_statistics_observer(self):
+        import os
+        import tempfile
+        from particles import ClusterStatisticsObserver, Particles, Vec3
+
+        ps = Particles()
+        for x in [0, 1, 2, 10, 11, 50]:
+            ps.add_particle(Vec3(x, 0.0, 0.0), Vec3(0.0, 0.0, 0.0), 1.0)
+
+        observer = ClusterStatisticsObserver(1.5, interval=2)
+        ps.add_observer(observer)
+        ps.run(n=4, h=0.001)
+        self.assertEqual([row.step for row in observer.rows], [0, 2, 4])
+        self.assertEqual(observer.table()["num_clusters"], [2.0, 2.0, 2.0])
+        sizes, rows = observer.size_histogram_table()
+        self.assertEqual(sizes, [1, 2, 3])
+        self.assertEqual(rows[0], [0, 1, 1])
+
+        path = os.path.join(tempfile.mkdtemp(), "clusters.csv")
+        observer.to_csv(path)
+        with open(path) as f:
+            self.assertEqual(len(f.readlines()), 4)
+
+        with self.assertRaises(ValueError):
+            ClusterStatisticsObserver(1.5, method="kmeans")
+
+    def test_checkpoint(self):
+        import os
+        import tempfile
+        from particles import Particles, Vec3
+
+        def potential(v):
+            return Vec3(-v.x, -v.y, -v.z)
+
+        ps = Particles()
+        for i in range(5):
+            ps.add_particle(Vec3(1.1 * i, 0.3 * i, 0.0), Vec3(0.1, -0.2 * i, 0.05), 1.0 + i)
+        ps.set_potential(potential)
+        ps.run(n=10, h=0.01)
+
+        path = os.path.join(tempfile.mkdtemp(), "checkpoint.bin")
+        ps.save(path)
+        with self.assertRaises(TypeError):
+            Particles.load(path)
+        restored = Particles.load(path, potential=potential)
+        self.assertEqual(restored.step, 10)
+        self.assertEqual(restored.time, ps.time)
+
+        ps.run(n=10, h=0.01)
+        restored.run(n=10, h=0.01)
+        for a, b in zip(ps.positions(), restored.positions()):
+            self.assertEqual((a.x, a.y, a.z), (b.x, b.y, b.z))
+
+        # attribute names of a damaged checkpoint are checked, as they are written to XML by write_vtu
+        ps.unset_potential()
+        ps.set_attribute("a_b", [0.0] * 5)
+        ps.save(path)
+        with open(path, "rb") as f:
+            data = f.read()
+        with open(path, "wb") as f:
+            f.write(data.replace(b"a_b", b'a"b'))
+        with self.assertRaises(ValueError):
+            Particles.load(path)
+
+    def test_pickle(self):
+        import copy
+        import pickle
+        from particles import Particles, Vec3, ShapeObserver
+
+        v = pickle.loads(pickle.dumps(Vec3(1.0, -2.5, 0.1)))
+        self.assertEqual((v.x, v.y, v.z), (1.0, -2.5, 0.1))
+        v = copy.deepcopy(Vec3(3.0, 2.0, 1.0))
+        self.assertEqual((v.x, v.y, v.z), (3.0, 2.0, 1.0))
+
+        ps = Particles()
+        for i in range(3):
+            ps.add_particle(Vec3(1.2 * i, 0.0, 0.0), Vec3(0.0, 0.1 * i, 0.0), 1.0)
+        ps.set_potential(_harmonic_potential)
+        shapes, seen = ShapeObserver(), []
+        ps.add_observer(shapes)
+        ps.add_observer(lambda simulation: seen.append(simulation.step))
+        ps.run(n=5, h=0.01)
+
+        # neither observers nor callables are pickled or copied
+        for restored in [pickle.loads(pickle.dumps(ps)), copy.copy(ps), copy.deepcopy(ps)]:
+            self.assertEqual(restored.step, 5)
+            self.assertTrue(restored.has_potential())
+            restored.run(n=5, h=0.01)
+            self.assertEqual(len(shapes.shapes), 6)
+            self.assertEqual(seen, list(range(6)))
+
+        ps.run(n=5, h=0.01)
+        self.assertEqual([p.x for p in restored.positions()], [p.x for p in ps.positions()])
+
+    def test_xyz(self):
+        import os
+        import tempfile
+        from particles import Particles, Vec3, XyzWriter
+
+        ps = Particles()
+        for i in range(4):
+            ps.add_particle(Vec3(1.1 * i, 0.1, -0.3), Vec3(0.1 * i, 0.0, 1.0 / 3.0), 1.0 + i)
+
+        path = os.path.join(tempfile.mkdtemp(), "trajectory.xyz")
+        writer = XyzWriter(path, interval=5, species="Ar")
+        ps.add_observer(writer)
+        ps.run(n=10, h=0.01)
+        writer.close()
+        self.assertEqual(writer.frames, 3)
+
+        last = Particles.read_xyz(path)
+        self.assertEqual(last.step, 10)
+        self.assertEqual(last.time, ps.time)
+        self.assertEqual([(p.x, p.y, p.z) for p in last.positions()], [(p.x, p.y, p.z) for p in ps.positions()])
+        self.assertEqual([v.z for v in last.velocities()], [v.z for v in ps.velocities()])
+        self.assertEqual(last.masses(), ps.masses())
+        self.assertEqual(last.ids(), ps.ids())
+
+        self.assertEqual(Particles.read_xyz(path, frame=0).step, 0)
+        with self.assertRaises(IndexError):
+            Particles.read_xyz(path, frame=3)
+
+        # plain XYZ files without velocities and masses
+        with open(path, "w") as f:
+            f.write("2\ncomment\nC 0 0 0\nO 1.2 0 0\n")
+        plain = Particles.read_xyz(path)
+        self.assertEqual(plain.masses(), [1.0, 1.0])
+        self.assertEqual(plain.positions()[1].x, 1.2)
+
+    def test_lammps(self):
+        import os
+        import tempfile
+        from particles import LammpsDumpWriter, Particles, Vec3
+
+        ps = Particles()
+        for i in range(4):
+            ps.add_particle(Vec3(1.1 * i, 0.1, -0.3), Vec3(0.1 * i, 0.0, 1.0 / 3.0), 1.0 + i % 2)
+
+        directory = tempfile.mkdtemp()
+        data = os.path.join(directory, "initial.data")
+        ps.write_lammps_data(data)
+        restored = Particles.read_lammps_data(data)
+        self.assertEqual([p.x for p in restored.positions()], [p.x for p in ps.positions()])
+        self.assertEqual([v.z for v in restored.velocities()], [v.z for v in ps.velocities()])
+        self.assertEqual(restored.masses(), ps.masses())
+
+        dump = os.path.join(directory, "dump.lammpstrj")
+        writer = LammpsDumpWriter(dump, interval=5)
+        ps.add_observer(writer)
+        ps.run(n=10, h=0.01)
+        writer.close()
+
+        last = Particles.read_lammps_dump(dump)
+        self.assertEqual(last.step, 10)
+        self.assertEqual(last.time, ps.time)
+        self.assertEqual([p.y for p in last.positions()], [p.y for p in ps.positions()])
+        self.assertEqual(Particles.read_lammps_dump(dump, frame=0).step, 0)
+
+        # a data file as written by LAMMPS, with image flags and atoms out of order
+        with open(data, "w") as f:
+            f.write("LAMMPS data file\n\n2 atoms\n1 atom types\n\n"
+                    "0.0 10.0 xlo xhi\n0.0 10.0 ylo yhi\n0.0 10.0 zlo zhi\n\n"
+                    "Masses\n\n1 39.9\n\nAtoms # full\n\n"
+                    "2 1 1 0.0 5.0 5.0 5.0 1 0 0\n1 1 1 0.0 1.0 1.0 1.0 0 0 -1\n")
+        lammps = Particles.read_lammps_data(data)
+        self.assertEqual([(p.x, p.z) for p in lammps.positions()], [(1.0, -9.0), (15.0, 5.0)])
+        self.assertEqual(lammps.masses(), [39.9, 39.9])
+
+    def test_lammps_dump_items(self):
+        import math
+        import os
+        import tempfile
+        from particles import Particles
+
+        # unknown items are skipped, and rows may start with nan or inf
+        dump = os.path.join(tempfile.mkdtemp(), "dump.lammpstrj")
+        with open(dump, "w") as f:
+            f.write("ITEM: UNITS\nlj\nITEM: TIME\n0.5\nITEM: TIMESTEP\n50\nITEM: ELAPSED\n3\n"
+                    "ITEM: NUMBER OF ATOMS\n2\nITEM: BOX BOUNDS pp pp pp\n0 10\n0 10\n0 10\n"
+                    "ITEM: ATOMS vx id x y z vy vz\nnan 2 5.0 5.0 5.0 0 0\ninf 1 1.0 1.0 1.0 0 0\n")
+        ps = Particles.read_lammps_dump(dump)
+        self.assertEqual((ps.step, ps.time), (50, 0.5))
+        self.assertEqual([p.x for p in ps.positions()], [1.0, 5.0])
+        vx = [v.x for v in ps.velocities()]
+        self.assertTrue(math.isinf(vx[0]) and math.isnan(vx[1]))
+
+        with open(dump, "a") as f:
+            f.write("ITEM: TIMESTEP\n60\nunexpected\n")
+        with self.assertRaises(ValueError):
+            Particles.read_lammps_dump(dump)
+
+    def _trajectory(self, **kwargs):
+        import os
+        import tempfile
+        from particles import Particles, TrajectoryWriter, Vec3
+
+        ps = Particles()
+        for i in range(4):
+            ps.add_particle(Vec3(1.1 * i, 0.1, -0.3), Vec3(0.1 * i, 0.0, 1.0 / 3.0), 1.0 + i)
+
+        path = os.path.join(tempfile.mkdtemp(), "run.traj")
+        writer = TrajectoryWriter(path, interval=5, **kwargs)
+        ps.add_observer(writer)
+        ps.run(n=10, h=0.01)
+        return ps, writer, path
+
+    def test_trajectory(self):
+        from particles import TrajectoryReader, TrajectoryWriter
+
+        for compression in [None, 6]:
+            ps, writer, path = self._trajectory(compression=compression)
+            reader = TrajectoryReader(path)
+            self.assertEqual(reader.steps, [0, 5, 10])
+            self.assertEqual(reader.num_particles, [4, 4, 4])
+
+            last = reader.particles(-1)
+            self.assertEqual(last.time, ps.time)
+            self.assertEqual([p.x for p in last.positions()], [p.x for p in ps.positions()])
+            self.assertEqual(last.masses(), ps.masses())
+
+            # frames appended while the reader is open
+            ps.run(n=5, h=0.01)
+            self.assertEqual(reader.refresh(), 1)
+            self.assertEqual(reader.particles(3).step, 15)
+
+        writer.close()
+        appended = TrajectoryWriter(path, append=True)
+        appended.write(ps)
+        appended.close()
+        self.assertEqual(TrajectoryReader(path).num_frames, 5)
+
+        with self.assertRaises(ValueError):
+            TrajectoryWriter(path, precision="f32", append=True)
+
+    def test_trajectory_index(self):
+        import os
+        import shutil
+        from particles import TrajectoryReader, TrajectoryWriter
+
+        ps, writer, path = self._trajectory()
+        writer.close()
+        index = path + ".idx"
+        self.assertEqual(os.path.getsize(index), 12 + 3 * 45)
+
+        # with the index, the frame headers are not read, so a damaged one goes unnoticed
+        with open(path, "rb") as f:
+            data = f.read()
+        offset = data.index(b"FRME", data.index(b"FRME") + 1)
+        with open(path, "r+b") as f:
+            f.seek(offset)
+            f.write(b"XXXX")
+        self.assertEqual(TrajectoryReader(path).steps, [0, 5, 10])
+
+        # without the index, the headers are scanned
+        os.remove(index)
+        with self.assertRaises(ValueError):
+            TrajectoryReader(path)
+
+        # appending restores the index from the scanned frames
+        with open(path, "r+b") as f:
+            f.seek(offset)
+            f.write(b"FRME")
+        appended = TrajectoryWriter(path, append=True)
+        appended.write(ps)
+        appended.close()
+        self.assertEqual(os.path.getsize(index), 12 + 4 * 45)
+        shutil.copy(index, index + ".old")
+
+        # a stale index of an overwritten trajectory is ignored
+        writer = TrajectoryWriter(path)
+        writer.write(ps)
+        writer.close()
+        shutil.copy(index + ".old", index)
+        reader = TrajectoryReader(path)
+        self.assertEqual(reader.steps, [ps.step])
+        self.assertEqual(reader.particles(0).ids(), ps.ids())
+
+        # a frame length that overflows the file offset is reported
+        os.remove(index)
+        with open(path, "r+b") as f:
+            f.seek(data.index(b"FRME") + 37)
+            f.write(b"\xff" * 8)
+        with self.assertRaisesRegex(ValueError, "corrupt frame header"):
+            TrajectoryReader(path)
+
+    def test_trajectory_single_precision(self):
+        from particles import TrajectoryReader
+
+        ps, writer, path = self._trajectory(precision="f32")
+        reader = TrajectoryReader(path)
+        self.assertEqual(reader.precision, "f32")
+        restored = reader.particles(-1)
+        for a, b in zip(restored.velocities(), ps.velocities()):
+            self.assertAlmostEqual(a.z, b.z, places=6)
+
+    @unittest.skipIf(np is None, "requires numpy")
+    def test_trajectory_numpy(self):
+        from particles import TrajectoryReader
+
+        ps, writer, path = self._trajectory()
+        reader = TrajectoryReader(path)
+        self.assertEqual(reader.positions(0).shape, (4, 3))
+        self.assertEqual(reader.masses(-1).tolist(), ps.masses())
+
+    def test_energy(self):
+        from particles import Particles, Vec3
+
+        ps = Particles()
+        ps.add_particle(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0), 1.0)
+        ps.add_particle(Vec3(10.0, 0.0, 0.0), Vec3(-1.0, 0.0, 0.0), 1.0)
+        self.assertAlmostEqual(ps.kinetic_energy(), 1.0)
+        self.assertAlmostEqual(ps.temperature(), 2.0 / 3.0)
+        self.assertLess(ps.interaction_energy(), 0.0)
+
+        # the energy is conserved up to the integration error, the particles approach to a distance of 2
+        energy = ps.kinetic_energy() + ps.interaction_energy()
+        ps.run(n=400, h=0.01)
+        self.assertAlmostEqual(ps.kinetic_energy() + ps.interaction_energy(), energy, places=6)
+
+    def test_vtk(self):
+        import os
+        import tempfile
+        import xml.etree.ElementTree as ET
+        from particles import Particles, Vec3, VtkWriter
+
+        ps = Particles()
+        for i in range(4):
+            ps.add_particle(Vec3(1.1 * i, 0.1, -0.3), Vec3(0.1 * i, 0.0, 1.0 / 3.0), 1.0 + i % 2)
+
+        arrays = _vtk_arrays
+
+        directory = tempfile.mkdtemp()
+        path = os.path.join(directory, "particles.vtu")
+        ps.write_vtu(path)
+        data = arrays(path)
+        self.assertEqual(data["mass"], ps.masses())
+        self.assertEqual(data["species"], [1, 2, 1, 2])
+        self.assertEqual(data["velocity"][3:6], [0.1, 0.0, 1.0 / 3.0])
+        self.assertEqual(data[None][3:6], [1.1, 0.1, -0.3])
+        self.assertEqual(data["types"], [1] * 4)
+
+        path = os.path.join(directory, "fields.vti")
+        fields = ps.grid_fields([4, 2], ranges=[(-0.5, 3.5), (-1.0, 1.0)], scheme="ngp")
+        fields.write_vti(path)
+        image = ET.parse(path).getroot().find("ImageData")
+        self.assertEqual(image.get("WholeExtent"), "0 4 0 2 0 0")
+        density = arrays(path)["density"]
+        self.assertEqual(len(density), 8)
+        # x varies fastest, all particles are in the upper row of y
+        self.assertEqual(density, [0.0] * 4 + [1.0, 2.0, 1.0, 2.0])
+
+        path = os.path.join(directory, "run.pvd")
+        writer = VtkWriter(path, interval=5, bins=[4, 4, 4])
+        ps.add_observer(writer)
+        ps.run(n=10, h=0.01)
+        writer.close()
+        self.assertEqual(writer.frames, 3)
+
+        datasets = ET.parse(path).getroot().find("Collection").findall("DataSet")
+        self.assertEqual([d.get("part") for d in datasets], ["0", "1"] * 3)
+        self.assertEqual(float(datasets[-1].get("timestep")), ps.time)
+        for dataset in datasets:
+            self.assertTrue(os.path.exists(os.path.join(directory, dataset.get("file"))))
+        with self.assertRaises(ValueError):
+            writer.write(ps)
+        with self.assertRaises(ValueError):
+            VtkWriter(path, ranges=[(0.0, 1.0)])
+
+    def test_h5md(self):
+        import os
+        import tempfile
+        import particles
+
+        if not hasattr(particles, "H5mdWriter"):
+            self.skipTest("requires the h5md feature")
+
+        path = os.path.join(tempfile.mkdtemp(), "run.h5")
+        writer = particles.H5mdWriter(path, interval=2, parameters={"h": 0.01, "n": 4, "name": "test"})
+        self.instance.add_observer(writer)
+        self.instance.run(n=4, h=0.01)
+        writer.close()
+        self.assertEqual(writer.frames, 3)
+
+        with open(path, "rb") as f:
+            self.assertEqual(f.read(8), b"\x89HDF\r\n\x1a\n")
+        with self.assertRaises(ValueError):
+            writer.write(self.instance)
+
+        # read back without h5py, which needs the version, the box and all particle datasets
+        first = particles.Particles.read_h5md(path, frame=0)
+        last = particles.Particles.read_h5md(path)
+        self.assertEqual((first.step, last.step), (0, 4))
+        self.assertAlmostEqual(last.time, self.instance.time)
+        self.assertEqual(last.ids(), self.instance.ids())
+        self.assertEqual(last.masses(), self.instance.masses())
+        self.assertEqual([p.x for p in last.positions()], [p.x for p in self.instance.positions()])
+        self.assertEqual([v.z for v in last.velocities()], [v.z for v in self.instance.velocities()])
+        with self.assertRaises(IndexError):
+            particles.Particles.read_h5md(path, frame=3)
+
+        try:
+            import h5py
+        except ImportError:
+            return
+        with h5py.File(path, "r") as f:
+            self.assertEqual(list(f["h5md"].attrs["version"]), [1, 1])
+            self.assertEqual(f["particles/all/position/value"].shape, (3, 1, 3))
+            self.assertEqual(list(f["particles/all/position/step"]), [0, 2, 4])
+            self.assertEqual(f["observables/temperature/value"].shape, (3,))
+            self.assertEqual(f["parameters"].attrs["n"], 4)
 
 
 if __name__ == "__main__":